@@ -0,0 +1,142 @@
+use std::hint::black_box;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use pointers::arc::MyArc;
+use pointers::thin_arc::ThinArc;
+
+
+fn new(c: &mut Criterion) {
+    let mut group = c.benchmark_group("arc_new");
+
+    group.bench_function("my_arc", |b| b.iter(|| MyArc::new(black_box(0u64))));
+    group.bench_function("thin_arc", |b| b.iter(|| ThinArc::new(black_box(0u64))));
+    group.bench_function("std_arc", |b| b.iter(|| Arc::new(black_box(0u64))));
+
+    group.finish();
+}
+
+
+// cloning touches the strong count only, so all three should cost the same
+fn clone(c: &mut Criterion) {
+    let mut group = c.benchmark_group("arc_clone_drop");
+
+    let my_arc = MyArc::new(0u64);
+    group.bench_function("my_arc", |b| b.iter(|| drop(black_box(&my_arc).clone())));
+
+    let thin_arc = ThinArc::new(0u64);
+    group.bench_function("thin_arc", |b| b.iter(|| drop(black_box(&thin_arc).clone())));
+
+    let arc = Arc::new(0u64);
+    group.bench_function("std_arc", |b| b.iter(|| drop(black_box(&arc).clone())));
+
+    group.finish();
+}
+
+
+// dropping the last reference, where `ThinArc` has no implicit weak reference to give up
+fn drop_last(c: &mut Criterion) {
+    let mut group = c.benchmark_group("arc_drop_last");
+
+    group.bench_function("my_arc", |b| b.iter_batched(|| MyArc::new(0u64), drop, BatchSize::SmallInput));
+    group.bench_function("thin_arc", |b| b.iter_batched(|| ThinArc::new(0u64), drop, BatchSize::SmallInput));
+    group.bench_function("std_arc", |b| b.iter_batched(|| Arc::new(0u64), drop, BatchSize::SmallInput));
+
+    group.finish();
+}
+
+
+// writing through a unique reference, where `ThinArc` checks one count with a plain load instead
+// of locking the weak count
+fn get_mut(c: &mut Criterion) {
+    let mut group = c.benchmark_group("arc_get_mut");
+
+    let mut my_arc = MyArc::new(0u64);
+    group.bench_function("my_arc", |b| b.iter(|| *MyArc::get_mut(black_box(&mut my_arc)).unwrap() += 1));
+
+    let mut thin_arc = ThinArc::new(0u64);
+    group.bench_function("thin_arc", |b| b.iter(|| *ThinArc::get_mut(black_box(&mut thin_arc)).unwrap() += 1));
+
+    let mut arc = Arc::new(0u64);
+    group.bench_function("std_arc", |b| b.iter(|| *Arc::get_mut(black_box(&mut arc)).unwrap() += 1));
+
+    group.finish();
+}
+
+
+fn make_mut(c: &mut Criterion) {
+    let mut group = c.benchmark_group("arc_make_mut");
+
+    let mut my_arc = MyArc::new(0u64);
+    group.bench_function("my_arc", |b| b.iter(|| *MyArc::make_mut(black_box(&mut my_arc)) += 1));
+
+    let mut thin_arc = ThinArc::new(0u64);
+    group.bench_function("thin_arc", |b| b.iter(|| *ThinArc::make_mut(black_box(&mut thin_arc)) += 1));
+
+    let mut arc = Arc::new(0u64);
+    group.bench_function("std_arc", |b| b.iter(|| *Arc::make_mut(black_box(&mut arc)) += 1));
+
+    group.finish();
+}
+
+
+// runs `op` `iters` times on each of `threads` threads at once, and returns the time from the
+// common start until the last thread is done
+fn contended(threads: usize, iters: u64, op: impl Fn() + Sync) -> Duration {
+    let barrier = Barrier::new(threads + 1);
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                barrier.wait();
+                for _ in 0..iters {
+                    op();
+                }
+            });
+        }
+
+        barrier.wait();
+        let start = Instant::now();
+
+        // leaving the scope joins the threads
+        start
+    }).elapsed()
+}
+
+
+// every thread clones and drops its own references to one shared value, so all of them hammer the
+// same strong count, and the cache line it sits in bounces between the cores. The time is per round
+// in which every thread clones and drops once, and the throughput counts the clones of all threads;
+// thread counts beyond the cores of the machine are skipped, as they would only measure the
+// scheduler
+fn clone_contended(c: &mut Criterion) {
+    let mut group = c.benchmark_group("arc_clone_drop_contended");
+    let cores = thread::available_parallelism().map_or(1, |cores| cores.get());
+
+    for threads in [1, 2, 4, 8, 16].into_iter().filter(|&threads| threads == 1 || threads <= cores) {
+        group.throughput(Throughput::Elements(threads as u64));
+
+        let my_arc = MyArc::new(0u64);
+        group.bench_with_input(BenchmarkId::new("my_arc", threads), &threads, |b, &threads| {
+            b.iter_custom(|iters| contended(threads, iters, || drop(black_box(&my_arc).clone())))
+        });
+
+        let thin_arc = ThinArc::new(0u64);
+        group.bench_with_input(BenchmarkId::new("thin_arc", threads), &threads, |b, &threads| {
+            b.iter_custom(|iters| contended(threads, iters, || drop(black_box(&thin_arc).clone())))
+        });
+
+        let arc = Arc::new(0u64);
+        group.bench_with_input(BenchmarkId::new("std_arc", threads), &threads, |b, &threads| {
+            b.iter_custom(|iters| contended(threads, iters, || drop(black_box(&arc).clone())))
+        });
+    }
+
+    group.finish();
+}
+
+
+criterion_group!(benches, new, clone, drop_last, get_mut, make_mut, clone_contended);
+criterion_main!(benches);
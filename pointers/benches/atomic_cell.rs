@@ -0,0 +1,47 @@
+use std::hint::black_box;
+use std::sync::Mutex;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use pointers::atomic_cell::MyAtomicCell;
+
+
+fn word_sized(c: &mut Criterion) {
+    let mut group = c.benchmark_group("word_sized");
+
+    let cell = MyAtomicCell::new(0u64);
+    group.bench_function("my_atomic_cell", |b| b.iter(|| {
+        cell.store(black_box(cell.load() + 1));
+    }));
+
+    let mutex = Mutex::new(0u64);
+    group.bench_function("mutex", |b| b.iter(|| {
+        let mut value = mutex.lock().unwrap();
+        *value = black_box(*value + 1);
+    }));
+
+    group.finish();
+}
+
+
+fn larger_than_word(c: &mut Criterion) {
+    let mut group = c.benchmark_group("larger_than_word");
+
+    let cell = MyAtomicCell::new([0u64; 4]);
+    group.bench_function("my_atomic_cell", |b| b.iter(|| {
+        let mut value = cell.load();
+        value[0] += 1;
+        cell.store(black_box(value));
+    }));
+
+    let mutex = Mutex::new([0u64; 4]);
+    group.bench_function("mutex", |b| b.iter(|| {
+        let mut value = mutex.lock().unwrap();
+        value[0] = black_box(value[0] + 1);
+    }));
+
+    group.finish();
+}
+
+
+criterion_group!(benches, word_sized, larger_than_word);
+criterion_main!(benches);
@@ -0,0 +1,179 @@
+use std::hint::black_box;
+use std::rc::Rc;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use pointers::rc::MyRc;
+use pointers::rc_pool::RcPool;
+
+
+// a zero-sized value takes its counts from the slab, and dropping it gives them back, so `new`
+// never reaches the allocator, unlike for std
+fn zst_new(c: &mut Criterion) {
+    let mut group = c.benchmark_group("zst_new");
+
+    group.bench_function("my_rc", |b| b.iter(|| drop(black_box(MyRc::new(())))));
+    group.bench_function("std_rc", |b| b.iter(|| drop(black_box(Rc::new(())))));
+
+    group.finish();
+}
+
+
+// many zero-sized values alive at the same time, which take their blocks from a few chunks
+fn zst_new_batch(c: &mut Criterion) {
+    const LEN: u64 = 256;
+    let mut group = c.benchmark_group("zst_new_batch");
+    group.throughput(Throughput::Elements(LEN));
+
+    group.bench_function("my_rc", |b| b.iter(|| {
+        let tokens: Vec<_> = (0..LEN).map(|_| MyRc::new(())).collect();
+        drop(black_box(tokens));
+    }));
+
+    group.bench_function("std_rc", |b| b.iter(|| {
+        let tokens: Vec<_> = (0..LEN).map(|_| Rc::new(())).collect();
+        drop(black_box(tokens));
+    }));
+
+    group.finish();
+}
+
+
+// cloning and dropping a reference that is not the last one only touches the strong count
+fn zst_clone_drop(c: &mut Criterion) {
+    let mut group = c.benchmark_group("zst_clone_drop");
+
+    let my_rc = MyRc::new(());
+    group.bench_function("my_rc", |b| b.iter(|| drop(black_box(&my_rc).clone())));
+
+    let rc = Rc::new(());
+    group.bench_function("std_rc", |b| b.iter(|| drop(black_box(&rc).clone())));
+
+    group.finish();
+}
+
+
+fn new(c: &mut Criterion) {
+    let mut group = c.benchmark_group("new");
+
+    group.bench_function("my_rc", |b| b.iter(|| MyRc::new(black_box(0u64))));
+    group.bench_function("std_rc", |b| b.iter(|| Rc::new(black_box(0u64))));
+
+    group.finish();
+}
+
+
+fn clone(c: &mut Criterion) {
+    let mut group = c.benchmark_group("clone");
+
+    // the clones are dropped outside of the measurement
+    let my_rc = MyRc::new(0u64);
+    group.bench_function("my_rc", |b| b.iter_batched_ref(
+        || Vec::with_capacity(1),
+        |clones| clones.push(black_box(&my_rc).clone()),
+        BatchSize::SmallInput
+    ));
+
+    let rc = Rc::new(0u64);
+    group.bench_function("std_rc", |b| b.iter_batched_ref(
+        || Vec::with_capacity(1),
+        |clones| clones.push(black_box(&rc).clone()),
+        BatchSize::SmallInput
+    ));
+
+    group.finish();
+}
+
+
+// dropping the last reference, which drops the value and frees the allocation
+fn drop_last(c: &mut Criterion) {
+    let mut group = c.benchmark_group("drop_last");
+
+    group.bench_function("my_rc", |b| b.iter_batched(|| MyRc::new(0u64), drop, BatchSize::SmallInput));
+    group.bench_function("std_rc", |b| b.iter_batched(|| Rc::new(0u64), drop, BatchSize::SmallInput));
+
+    group.finish();
+}
+
+
+// reading through many pointers, which only pays for the indirection to the value behind the counts
+fn deref(c: &mut Criterion) {
+    const LEN: u64 = 1_024;
+    let mut group = c.benchmark_group("deref");
+    group.throughput(Throughput::Elements(LEN));
+
+    let my_rcs: Vec<MyRc<u64>> = (0..LEN).map(MyRc::new).collect();
+    group.bench_function("my_rc", |b| b.iter(|| black_box(&my_rcs).iter().map(|value| **value).sum::<u64>()));
+
+    let rcs: Vec<Rc<u64>> = (0..LEN).map(Rc::new).collect();
+    group.bench_function("std_rc", |b| b.iter(|| black_box(&rcs).iter().map(|value| **value).sum::<u64>()));
+
+    group.finish();
+}
+
+
+// comparing two handles of one large interned value, which is a pointer comparison for std and,
+// with the nightly-only `specialization` feature, for `MyRc` too
+fn eq_same_allocation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("eq_same_allocation");
+    let value = "x".repeat(64 * 1024);
+
+    let my_rc = MyRc::new(value.clone());
+    let my_rc_clone = my_rc.clone();
+    group.bench_function("my_rc", |b| b.iter(|| black_box(&my_rc) == black_box(&my_rc_clone)));
+
+    let rc = Rc::new(value);
+    let rc_clone = rc.clone();
+    group.bench_function("std_rc", |b| b.iter(|| black_box(&rc) == black_box(&rc_clone)));
+
+    group.finish();
+}
+
+
+// short-lived values, the workload `RcPool` is for: a pooled `new` and drop reuse the same block
+// instead of going to the allocator every time
+fn churn(c: &mut Criterion) {
+    let mut group = c.benchmark_group("churn");
+
+    group.bench_function("my_rc", |b| b.iter(|| drop(MyRc::new(black_box(0u64)))));
+
+    let pool = RcPool::new();
+    group.bench_function("my_rc_pooled", |b| b.iter(|| drop(pool.new_rc(black_box(0u64)))));
+
+    group.bench_function("std_rc", |b| b.iter(|| drop(Rc::new(black_box(0u64)))));
+
+    group.finish();
+}
+
+
+// a batch of values that are alive at the same time, which the pool serves from its free list
+// once the first batch has been dropped
+fn churn_batch(c: &mut Criterion) {
+    const LEN: u64 = 256;
+    let mut group = c.benchmark_group("churn_batch");
+    group.throughput(Throughput::Elements(LEN));
+
+    group.bench_function("my_rc", |b| b.iter(|| {
+        let values: Vec<_> = (0..LEN).map(|value| MyRc::new(black_box(value))).collect();
+        drop(values);
+    }));
+
+    let pool = RcPool::new();
+    group.bench_function("my_rc_pooled", |b| b.iter(|| {
+        let values: Vec<_> = (0..LEN).map(|value| pool.new_rc(black_box(value))).collect();
+        drop(values);
+    }));
+
+    group.bench_function("std_rc", |b| b.iter(|| {
+        let values: Vec<_> = (0..LEN).map(|value| Rc::new(black_box(value))).collect();
+        drop(values);
+    }));
+
+    group.finish();
+}
+
+
+criterion_group!(
+    benches, new, clone, drop_last, deref, eq_same_allocation, churn, churn_batch, zst_new,
+    zst_new_batch, zst_clone_drop
+);
+criterion_main!(benches);
@@ -0,0 +1,213 @@
+use std::cell::RefCell;
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use pointers::refcell::MyRefCell;
+use enum_state::EnumRefCell;
+
+
+// the borrow tracking of `MyRefCell` before its state was packed into a single `isize`: an enum in
+// a `Cell` that every borrow and every guard drop matches on. Only the parts on the measured paths
+// are kept, so the packed state is compared against its predecessor and not just against std
+mod enum_state {
+    use std::cell::{Cell, UnsafeCell};
+    use std::ops::{Deref, DerefMut};
+    use std::ptr::NonNull;
+
+
+    #[derive(Clone, Copy)]
+    enum RefState {
+        Unshared,
+        Shared(usize),
+        Exclusive(usize)
+    }
+
+
+    pub struct EnumRefCell<T> {
+        state: Cell<RefState>,
+        value: UnsafeCell<T>
+    }
+
+
+    pub struct BorrowError;
+
+
+    struct BorrowRef<'refcell> {
+        state: &'refcell Cell<RefState>
+    }
+
+
+    struct BorrowRefMut<'refcell> {
+        state: &'refcell Cell<RefState>
+    }
+
+
+    pub struct Ref<'refcell, T> {
+        value: NonNull<T>,
+        _borrow: BorrowRef<'refcell>
+    }
+
+
+    pub struct RefMut<'refcell, T> {
+        value: NonNull<T>,
+        _borrow: BorrowRefMut<'refcell>
+    }
+
+
+    impl<T> EnumRefCell<T> {
+        pub fn new(value: T) -> Self {
+            EnumRefCell {
+                state: Cell::new(RefState::Unshared),
+                value: UnsafeCell::new(value)
+            }
+        }
+
+
+        #[track_caller]
+        pub fn borrow(&self) -> Ref<'_, T> {
+            match self.try_borrow() {
+                Ok(borrow) => borrow,
+                Err(_) => panic!("already mutably borrowed")
+            }
+        }
+
+
+        #[track_caller]
+        pub fn borrow_mut(&self) -> RefMut<'_, T> {
+            match self.try_borrow_mut() {
+                Ok(borrow) => borrow,
+                Err(_) => panic!("already borrowed")
+            }
+        }
+
+
+        pub fn try_borrow(&self) -> Result<Ref<'_, T>, BorrowError> {
+            match self.state.get() {
+                RefState::Unshared => self.state.set(RefState::Shared(1)),
+                RefState::Shared(count) => self.state.set(RefState::Shared(count + 1)),
+                RefState::Exclusive(_) => return Err(BorrowError)
+            }
+
+            Ok(Ref {
+                value: unsafe { NonNull::new_unchecked(self.value.get()) },
+                _borrow: BorrowRef {state: &self.state}
+            })
+        }
+
+
+        pub fn try_borrow_mut(&self) -> Result<RefMut<'_, T>, BorrowError> {
+            match self.state.get() {
+                RefState::Unshared => self.state.set(RefState::Exclusive(1)),
+                RefState::Shared(_) | RefState::Exclusive(_) => return Err(BorrowError)
+            }
+
+            Ok(RefMut {
+                value: unsafe { NonNull::new_unchecked(self.value.get()) },
+                _borrow: BorrowRefMut {state: &self.state}
+            })
+        }
+    }
+
+
+    impl Drop for BorrowRef<'_> {
+        fn drop(&mut self) {
+            match self.state.get() {
+                RefState::Exclusive(_) | RefState::Unshared => unreachable!(),
+                RefState::Shared(1) => self.state.set(RefState::Unshared),
+                RefState::Shared(n) => self.state.set(RefState::Shared(n - 1))
+            }
+        }
+    }
+
+
+    impl Drop for BorrowRefMut<'_> {
+        fn drop(&mut self) {
+            match self.state.get() {
+                RefState::Shared(_) | RefState::Unshared => unreachable!(),
+                RefState::Exclusive(1) => self.state.set(RefState::Unshared),
+                RefState::Exclusive(n) => self.state.set(RefState::Exclusive(n - 1))
+            }
+        }
+    }
+
+
+    impl<T> Deref for Ref<'_, T> {
+        type Target = T;
+
+
+        fn deref(&self) -> &T {
+            unsafe { self.value.as_ref() }
+        }
+    }
+
+
+    impl<T> Deref for RefMut<'_, T> {
+        type Target = T;
+
+
+        fn deref(&self) -> &T {
+            unsafe { self.value.as_ref() }
+        }
+    }
+
+
+    impl<T> DerefMut for RefMut<'_, T> {
+        fn deref_mut(&mut self) -> &mut T {
+            unsafe { self.value.as_mut() }
+        }
+    }
+}
+
+
+fn borrow(c: &mut Criterion) {
+    let mut group = c.benchmark_group("borrow");
+
+    let my_ref_cell = MyRefCell::new(0u64);
+    group.bench_function("my_ref_cell", |b| b.iter(|| {
+        let first = black_box(&my_ref_cell).borrow();
+        let second = black_box(&my_ref_cell).borrow();
+        *first + *second
+    }));
+
+    let enum_ref_cell = EnumRefCell::new(0u64);
+    group.bench_function("enum_state", |b| b.iter(|| {
+        let first = black_box(&enum_ref_cell).borrow();
+        let second = black_box(&enum_ref_cell).borrow();
+        *first + *second
+    }));
+
+    let ref_cell = RefCell::new(0u64);
+    group.bench_function("std_ref_cell", |b| b.iter(|| {
+        let first = black_box(&ref_cell).borrow();
+        let second = black_box(&ref_cell).borrow();
+        *first + *second
+    }));
+
+    group.finish();
+}
+
+
+fn borrow_mut(c: &mut Criterion) {
+    let mut group = c.benchmark_group("borrow_mut");
+
+    let my_ref_cell = MyRefCell::new(0u64);
+    group.bench_function("my_ref_cell", |b| b.iter(|| {
+        *black_box(&my_ref_cell).borrow_mut() += 1;
+    }));
+
+    let enum_ref_cell = EnumRefCell::new(0u64);
+    group.bench_function("enum_state", |b| b.iter(|| {
+        *black_box(&enum_ref_cell).borrow_mut() += 1;
+    }));
+
+    let ref_cell = RefCell::new(0u64);
+    group.bench_function("std_ref_cell", |b| b.iter(|| {
+        *black_box(&ref_cell).borrow_mut() += 1;
+    }));
+
+    group.finish();
+}
+
+
+criterion_group!(benches, borrow, borrow_mut);
+criterion_main!(benches);
@@ -0,0 +1,121 @@
+/*
+- An `Allocator` hands out blocks of memory for a given `Layout` and takes them back again. Smart
+pointers that are generic over an allocator, such as `MyRc<T, A>`, can then be backed by an arena
+or a pool instead of the global heap.
+
+- The trait mirrors `std::alloc::Allocator`, which is still unstable, so the same kind of
+implementation works on stable Rust. Only the two required methods are part of it: `allocate`
+reports failure as an `AllocError` instead of a null pointer, and `deallocate` gets the layout the
+block was allocated with back.
+
+- `Global` forwards to the global allocator (`std::alloc::alloc`), and is the default wherever an
+allocator parameter can be left out, so code that does not care about allocators never names one.
+
+- A reference to an allocator is an allocator too, so several pointers can share an arena that
+lives on the stack without owning it.
+
+- Implementing `Allocator` is `unsafe`: a block must stay valid until it is deallocated, even when
+the allocator is moved, and a clone of an allocator must be able to deallocate the blocks of the
+original, since every clone of a smart pointer carries its own clone of the allocator.
+*/
+use std::{error::Error, fmt, ptr};
+use std::alloc::{self, Layout};
+use std::ptr::NonNull;
+
+
+/// # Safety
+///
+/// Blocks returned by `allocate` must stay valid until they are passed to `deallocate` of the
+/// same allocator or one of its clones, and must fit the layout they were requested for.
+pub unsafe trait Allocator {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError>;
+
+
+    /// # Safety
+    ///
+    /// `ptr` must be a block currently allocated by this allocator (or one of its clones), and
+    /// `layout` must be the layout it was allocated with.
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout);
+}
+
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Global;
+
+
+unsafe impl Allocator for Global {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        // the global allocator must not be asked for zero bytes, but any aligned address is a
+        // valid block of size zero
+        let memory = if layout.size() == 0 {
+            ptr::without_provenance_mut(layout.align())
+        } else {
+            unsafe { alloc::alloc(layout) }
+        };
+
+        match NonNull::new(memory) {
+            Some(memory) => Ok(NonNull::slice_from_raw_parts(memory, layout.size())),
+            None => Err(AllocError)
+        }
+    }
+
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            alloc::dealloc(ptr.as_ptr(), layout);
+        }
+    }
+}
+
+
+unsafe impl<A: Allocator + ?Sized> Allocator for &A {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        (**self).allocate(layout)
+    }
+
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        (**self).deallocate(ptr, layout)
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AllocError;
+
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("memory allocation failed")
+    }
+}
+
+
+impl Error for AllocError {}
+
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::Layout;
+    use crate::allocator::{Allocator, Global};
+
+
+    #[test]
+    fn global_allocate_and_deallocate() {
+        let layout = Layout::new::<[u64; 4]>();
+        let block = Global.allocate(layout).unwrap();
+        assert_eq!(block.len(), layout.size());
+        assert!(block.cast::<u64>().is_aligned());
+
+        unsafe {
+            block.cast::<[u64; 4]>().write([1, 2, 3, 4]);
+            assert_eq!(block.cast::<[u64; 4]>().read(), [1, 2, 3, 4]);
+            Global.deallocate(block.cast(), layout);
+        }
+
+        let empty = Layout::from_size_align(0, 16).unwrap();
+        let block = Global.allocate(empty).unwrap();
+        assert!(block.cast::<u8>().as_ptr().addr().is_multiple_of(16));
+        unsafe { Global.deallocate(block.cast(), empty) };
+    }
+}
@@ -0,0 +1,1606 @@
+/*
+- `Arc<T>` is the thread-safe counterpart of `Rc<T>`: a reference-counted pointer whose clones can
+be sent to other threads, which all share the one value. The strong count is an `AtomicUsize`, so
+clones made and dropped on different threads at the same time never lose an update.
+
+- `Arc<T>` is `Send` and `Sync` exactly when `T` is both `Send` and `Sync`. Sending a clone hands
+out shared access to the value on another thread (so `T: Sync`), and whichever thread drops the
+last clone drops the value as well (so `T: Send`).
+
+- Cloning increments the count with `Relaxed` ordering: a thread can only clone an `Arc<T>` it
+already holds, so the value is alive and nothing has to be synchronized. Dropping decrements the
+count with `Release`, so every access to the value made through a clone happens before the count
+goes down, and the thread that brings it to zero issues an `Acquire` fence before dropping the
+value, which makes all of those accesses happen before the value is destroyed.
+
+- The count aborts the process once it passes `isize::MAX`, like in std. Other threads may keep
+cloning between the increment and the check, so the limit leaves enough room below `usize::MAX`
+for any number of threads racing past it, and the count never wraps around.
+
+- `ArcWeak<T>` is the weak reference of `Arc<T>`: it keeps the allocation alive, but not the
+value. The value is dropped together with the last `Arc<T>`, the memory is freed together with the
+last weak reference, and all strong references together hold one implicit weak reference, so the
+thread that drops the value also releases that one. Weak references of values that are parked in
+caches or registries can then be dropped on any thread.
+
+- `ArcWeak::upgrade` increments the strong count with a compare-exchange loop that gives up once
+the count is zero. A plain increment could revive a value whose last strong reference is being
+dropped on another thread at the same moment: once the count reached zero, it never leaves zero
+again, and an upgrade either wins the race before the drop or fails cleanly after it.
+
+- `make_mut` is clone-on-write, like for `Rc<T>`, except that the check for other references has
+to hold while other threads keep going. It swaps the strong count from one to zero in a single
+step, which fails if any other strong reference exists, and then the value is cloned. Otherwise no
+other thread can create a strong reference any more, since upgrades give up at zero and cloning
+or downgrading needs a strong reference in the first place, so if weak references remain, the
+value moves to a fresh allocation and leaves them with the old one, and if none remain, the count
+goes back to one and the value is borrowed in place.
+
+- `get_mut` has to know that no other strong or weak reference exists, and the two counts cannot
+be read in one step. Between the reads, another thread could downgrade its strong reference and
+drop it, so that the value seems unique while a weak reference to it exists. `get_mut` therefore
+locks the weak count while it reads the strong one, by swapping a weak count of one for
+`usize::MAX`, and `downgrade` waits while the count is locked. Only `downgrade` has to wait: the
+lock is only taken when no weak reference exists, so no other operation can touch the weak count
+in the meantime.
+
+- `try_unwrap` and `into_inner` both move the value out of the last strong reference. `try_unwrap`
+succeeds only if there is no other strong reference at the time, so two threads that each call it
+on their clone at the same moment can both fail, and the value stays behind in the clones they
+get back. If those are dropped, the value is gone. `into_inner` instead gives up the strong
+reference in any case, and only the thread that gives up the last one gets the value, so of any
+number of threads that call it on all clones, exactly one ends up with the value.
+
+- `Arc<T>` accepts unsized values with the same constructors as `Rc<T>`, so the two stay
+interchangeable: an `Arc<str>` comes from a `&str`, a `String` or a `Box<str>`, an `Arc<[T]>` from
+a `Vec<T>`, a `Box<[T]>`, a `&[T]` or any iterator, with the contents stored inline after the
+counts, and a `Box<T>` moves its value over without passing it through the stack. The
+`coerce_arc!` macro turns an `Arc<T>` into an `Arc<dyn Trait>` (or any other unsized type `T`
+coerces to) on stable Rust, the `unsize` feature makes that coercion implicit, and an
+`Arc<dyn Any + Send + Sync>` can be downcast back to its concrete type.
+
+- `Arc::new_cyclic` builds a value that holds a weak reference to its own allocation, e.g. a worker
+that hands a handle to itself to the callbacks it registers. The weak reference may already be
+cloned and sent to other threads while the value is being constructed, but it only upgrades once
+construction has finished: the strong count stays zero until the value is in place, and it is
+then set to one with `Release`, which the `Acquire` of a successful upgrade synchronizes with.
+
+- `Arc::pin` allocates a value that stays pinned for as long as it lives, like `Rc::pin`:
+`Pin<Arc<T>>` hands out shared references only, to any number of threads, and everything that
+could move the value out or mutate it (`get_mut`, `make_mut`, `try_unwrap`, ...) needs an unpinned
+`Arc<T>`. `Arc<T>` and `ArcWeak<T>` themselves are always `Unpin`, since moving them never moves
+the value.
+
+- `Arc::into_raw` and `Arc::from_raw` turn a strong reference into a pointer to the value and back,
+and `Arc::increment_strong_count` and `Arc::decrement_strong_count` adjust the count a pointer
+represents in the meantime, like for `Rc<T>`. On top of that, `myarc_clone` and `myarc_drop` are
+exported as C functions, so a C or C++ component can hold references to a `MyArc<Foreign>` that
+Rust handed it as an opaque `const void *`: another reference is taken with
+`void *myarc_clone(const void *)` and given up with `void myarc_drop(const void *)`, from any
+thread. `Foreign` is a boxed `dyn Any + Send + Sync`, so any shared Rust type fits behind the one
+thin pointer, and the value is dropped on whichever side gives up the last reference.
+
+- `Default`, `Debug`, `Display`, `Hash`, `PartialEq`, `Eq`, `PartialOrd`, `Ord`, `Borrow<T>` and
+`AsRef<T>` forward to the shared value, like for `Rc<T>`.
+
+- Counts and fences are imported through `crate::sync`, so with the `loom` feature enabled, loom
+stands in for the std atomics, and `cargo test --features loom` model-checks the races between
+clone, drop, upgrade, `get_mut`, `into_inner` and `make_mut` in every interleaving loom finds,
+instead of hoping that a stress test happens to hit the bad one.
+*/
+use std::{fmt, mem, process, ptr};
+use std::alloc::{self, Layout};
+use std::any::Any;
+use std::borrow::Borrow;
+use std::cmp::Ordering as CmpOrdering;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::ptr::NonNull;
+use crate::sync::{fence, hint, AtomicUsize, Ordering};
+
+
+// far enough below `usize::MAX` that threads racing past it before they abort cannot overflow it
+const MAX_REFCOUNT: usize = isize::MAX as usize;
+
+
+// no allocation can end at the very last address, so this never collides with a real `ArcInner`
+const DANGLING: usize = usize::MAX;
+
+
+// the weak count while `get_mut` checks for uniqueness
+const LOCKED: usize = usize::MAX;
+
+
+pub struct MyArc<T: ?Sized> {
+    inner: NonNull<ArcInner<T>>,
+    _marker: PhantomData<ArcInner<T>>
+}
+
+
+pub struct ArcWeak<T: ?Sized> {
+    inner: NonNull<ArcInner<T>>,
+    _marker: PhantomData<ArcInner<T>>
+}
+
+
+// `repr(C)` puts the elements of a slice right after the counts, where `MyArc::<[T]>::slice_layout`
+// expects them
+#[repr(C)]
+struct ArcInner<T: ?Sized> {
+    strong: AtomicUsize,
+    // one more than the weak references while there are strong ones
+    weak: AtomicUsize,
+    value: T
+}
+
+
+// what a weak reference may look at while the value is being dropped on another thread
+struct Counts<'inner> {
+    strong: &'inner AtomicUsize,
+    weak: &'inner AtomicUsize
+}
+
+
+unsafe impl<T: ?Sized + Send + Sync> Send for MyArc<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for MyArc<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Send for ArcWeak<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for ArcWeak<T> {}
+
+
+impl<T> MyArc<T> {
+    pub fn new(value: T) -> Self {
+        let inner = Box::new(ArcInner {
+            strong: AtomicUsize::new(1),
+            weak: AtomicUsize::new(1),
+            value
+        });
+
+        MyArc {
+            inner: NonNull::from(Box::leak(inner)),
+            _marker: PhantomData
+        }
+    }
+
+
+    pub fn pin(value: T) -> Pin<MyArc<T>> {
+        // the value never moves out of its allocation while it is shared, and every way of moving
+        // it out or mutating it (`try_unwrap`, `get_mut`, `make_mut`, ...) needs an unpinned `MyArc`
+        unsafe { Pin::new_unchecked(MyArc::new(value)) }
+    }
+
+
+    pub fn new_cyclic(f: impl FnOnce(&ArcWeak<T>) -> T) -> Self {
+        // the allocation starts out without a value, so the weak reference handed to `f` cannot
+        // upgrade yet
+        let inner = Box::into_raw(Box::<ArcInner<T>>::new_uninit()).cast::<ArcInner<T>>();
+        unsafe {
+            (&raw mut (*inner).strong).write(AtomicUsize::new(0));
+            (&raw mut (*inner).weak).write(AtomicUsize::new(1));
+        }
+
+        // if `f` panics, dropping the weak reference frees the allocation without touching the
+        // missing value
+        let weak = ArcWeak {inner: unsafe { NonNull::new_unchecked(inner) }, _marker: PhantomData};
+        let value = f(&weak);
+
+        unsafe {
+            (&raw mut (*inner).value).write(value);
+            (*inner).strong.store(1, Ordering::Release);
+        }
+
+        // the weak reference used during construction becomes the implicit weak reference of the
+        // strong ones
+        let weak = ManuallyDrop::new(weak);
+
+        MyArc {
+            inner: weak.inner,
+            _marker: PhantomData
+        }
+    }
+
+
+    pub fn into_raw(this: Self) -> *const T {
+        // the strong reference moves into the pointer
+        let this = ManuallyDrop::new(this);
+        MyArc::as_ptr(&this)
+    }
+
+
+    /// # Safety
+    ///
+    /// `ptr` must come from `MyArc::into_raw` for the same `T`, and each pointer may be turned back
+    /// into a `MyArc<T>` only once for every strong reference it represents.
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        // `ptr` keeps the provenance of the whole allocation, counts included
+        let inner = NonNull::new_unchecked(ptr.cast_mut()).byte_sub(mem::offset_of!(ArcInner<T>, value));
+
+        MyArc {
+            inner: inner.cast(),
+            _marker: PhantomData
+        }
+    }
+
+
+    /// # Safety
+    ///
+    /// `ptr` must come from `MyArc::into_raw` for the same `T`, and the value must still be alive.
+    pub unsafe fn increment_strong_count(ptr: *const T) {
+        let this = ManuallyDrop::new(Self::from_raw(ptr));
+        mem::forget(MyArc::clone(&this));
+    }
+
+
+    /// # Safety
+    ///
+    /// `ptr` must come from `MyArc::into_raw` for the same `T`, and it must represent a strong
+    /// reference that has not been given up yet, since this gives it up.
+    pub unsafe fn decrement_strong_count(ptr: *const T) {
+        drop(Self::from_raw(ptr));
+    }
+
+
+    pub fn make_mut(this: &mut Self) -> &mut T
+    where
+        T: Clone
+    {
+        let inner = this.inner();
+
+        if inner.strong.compare_exchange(1, 0, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            // other owners keep the current value, this one continues with its own copy
+            *this = MyArc::new((**this).clone());
+        } else if inner.weak.load(Ordering::Relaxed) != 1 {
+            // only weak references are left, and they can no longer upgrade: the value moves to a
+            // new allocation, and they stay behind with the old one
+            let value = unsafe { ptr::read(&inner.value) };
+            let old = ArcWeak {inner: this.inner, _marker: PhantomData};
+
+            unsafe { ptr::write(this, MyArc::new(value)) };
+            drop(old);
+        } else {
+            // nothing else refers to the value, so the count goes back to one
+            inner.strong.store(1, Ordering::Release);
+        }
+
+        unsafe { &mut (*this.inner.as_ptr()).value }
+    }
+
+
+    /// # Safety
+    ///
+    /// `coerce` must return the pointer it is given, turned into a pointer to an unsized type by
+    /// an unsizing coercion (e.g. `*const T` to `*const dyn Trait`). The `coerce_arc!` macro calls
+    /// this function with exactly such a closure and is the safe way to use it.
+    pub unsafe fn unsize<U: ?Sized>(this: Self, coerce: impl FnOnce(*const T) -> *const U) -> MyArc<U> {
+        // the strong reference moves over to the result
+        let this = ManuallyDrop::new(this);
+        let value = coerce(MyArc::as_ptr(&this));
+
+        // an unsizing coercion keeps the address and the alignment, so the counts sit at the same
+        // offset in front of the value for `T` and `U`
+        let inner = NonNull::new_unchecked(value.cast_mut()).byte_sub(mem::offset_of!(ArcInner<T>, value));
+
+        MyArc {
+            inner: NonNull::new_unchecked(inner.as_ptr() as *mut ArcInner<U>),
+            _marker: PhantomData
+        }
+    }
+
+
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        let strong = &this.inner().strong;
+        if strong.compare_exchange(1, 0, Ordering::Relaxed, Ordering::Relaxed).is_err() {
+            return Err(this);
+        }
+
+        // synchronizes with the decrements of the strong references dropped before
+        fence(Ordering::Acquire);
+
+        let this = ManuallyDrop::new(this);
+        Ok(unsafe { Self::take_value(&this) })
+    }
+
+
+    pub fn into_inner(this: Self) -> Option<T> {
+        // the strong reference is given up by hand below, so `Drop` must not run
+        let this = ManuallyDrop::new(this);
+
+        if this.inner().strong.fetch_sub(1, Ordering::Release) != 1 {
+            return None;
+        }
+
+        fence(Ordering::Acquire);
+        Some(unsafe { Self::take_value(&this) })
+    }
+
+
+    // moves the value out once the strong count reached zero, releasing the implicit weak
+    // reference of the strong ones
+    unsafe fn take_value(this: &Self) -> T {
+        let value = ptr::read(&this.inner().value);
+        drop(ArcWeak {inner: this.inner, _marker: PhantomData});
+        value
+    }
+}
+
+
+impl<T: ?Sized> MyArc<T> {
+    pub fn downgrade(this: &Self) -> ArcWeak<T> {
+        let weak = &this.inner().weak;
+        let mut count = weak.load(Ordering::Relaxed);
+
+        loop {
+            // `get_mut` holds the count for a moment, and must see the new weak reference after
+            if count == LOCKED {
+                hint::spin_loop();
+                count = weak.load(Ordering::Relaxed);
+                continue;
+            }
+            if count > MAX_REFCOUNT {
+                process::abort();
+            }
+
+            let next = count + 1;
+            match weak.compare_exchange_weak(count, next, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(actual) => count = actual
+            }
+        }
+
+        ArcWeak {
+            inner: this.inner,
+            _marker: PhantomData
+        }
+    }
+
+
+    // a snapshot only: other threads may clone or drop in the meantime
+    pub fn strong_count(this: &Self) -> usize {
+        this.inner().strong.load(Ordering::Acquire)
+    }
+
+
+    pub fn weak_count(this: &Self) -> usize {
+        match this.inner().weak.load(Ordering::Acquire) {
+            // only locked while there are no weak references
+            LOCKED => 0,
+            count => count - 1
+        }
+    }
+
+
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if Self::is_unique(this) {
+            // no other `MyArc` can read the value and no `ArcWeak` can upgrade to one
+            Some(unsafe { &mut (*this.inner.as_ptr()).value })
+        } else {
+            None
+        }
+    }
+
+
+    fn is_unique(this: &Self) -> bool {
+        let inner = this.inner();
+
+        // keeps other threads from downgrading while the strong count is read
+        if inner.weak.compare_exchange(1, LOCKED, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            return false;
+        }
+
+        // acquires the decrements of strong references dropped before, like the last drop does
+        let unique = inner.strong.load(Ordering::Acquire) == 1;
+        inner.weak.store(1, Ordering::Release);
+        unique
+    }
+
+
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        // compares addresses only, so the metadata of unsized values does not matter
+        std::ptr::addr_eq(this.inner.as_ptr(), other.inner.as_ptr())
+    }
+
+
+    pub fn as_ptr(this: &Self) -> *const T {
+        // projects through the raw pointer, so the result keeps the provenance of the allocation
+        unsafe { &raw const (*this.inner.as_ptr()).value }
+    }
+
+
+    fn inner(&self) -> &ArcInner<T> {
+        // the allocation lives for as long as this reference does
+        unsafe { self.inner.as_ref() }
+    }
+}
+
+
+impl<T> ArcWeak<T> {
+    pub fn new() -> Self {
+        ArcWeak {
+            inner: unsafe { NonNull::new_unchecked(ptr::without_provenance_mut(DANGLING)) },
+            _marker: PhantomData
+        }
+    }
+}
+
+
+impl<T: ?Sized> ArcWeak<T> {
+    pub fn upgrade(&self) -> Option<MyArc<T>> {
+        let strong = self.counts()?.strong;
+        let mut count = strong.load(Ordering::Relaxed);
+
+        // a count that reached zero stays there, as the value is gone
+        loop {
+            if count == 0 {
+                return None;
+            }
+            if count > MAX_REFCOUNT {
+                process::abort();
+            }
+
+            let next = count + 1;
+            match strong.compare_exchange_weak(count, next, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(actual) => count = actual
+            }
+        }
+
+        Some(MyArc {
+            inner: self.inner,
+            _marker: PhantomData
+        })
+    }
+
+
+    pub fn strong_count(&self) -> usize {
+        self.counts().map_or(0, |counts| counts.strong.load(Ordering::Acquire))
+    }
+
+
+    // zero once the value is gone, like in std, since the implicit weak reference of the strong
+    // ones may still be counted for a moment
+    pub fn weak_count(&self) -> usize {
+        let Some(counts) = self.counts() else {
+            return 0;
+        };
+
+        let weak = counts.weak.load(Ordering::Acquire);
+        if counts.strong.load(Ordering::Acquire) == 0 {
+            0
+        } else {
+            weak - 1
+        }
+    }
+
+
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(self.inner.as_ptr(), other.inner.as_ptr())
+    }
+
+
+    // `None` for a weak reference made by `ArcWeak::new`, which has no allocation
+    fn counts(&self) -> Option<Counts<'_>> {
+        if self.inner.as_ptr().cast::<u8>().addr() == DANGLING {
+            return None;
+        }
+
+        // the allocation stays alive for as long as any weak reference exists, but another thread
+        // may be dropping the value, so only the counts are borrowed
+        let inner = self.inner.as_ptr();
+        unsafe {
+            Some(Counts {
+                strong: &(*inner).strong,
+                weak: &(*inner).weak
+            })
+        }
+    }
+}
+
+
+// increments a count of references that the caller holds one of, so nothing has to be
+// synchronized
+fn increment(count: &AtomicUsize) {
+    if count.fetch_add(1, Ordering::Relaxed) > MAX_REFCOUNT {
+        process::abort();
+    }
+}
+
+
+impl<T> MyArc<[T]> {
+    fn slice_layout(len: usize) -> Layout {
+        Layout::new::<ArcInner<()>>()
+            .extend(Layout::array::<T>(len).expect("slice too large"))
+            .expect("slice too large")
+            .0
+            .pad_to_align()
+    }
+
+
+    // allocates an `ArcInner<[T]>` for `len` elements with both counts set to one; the elements
+    // are left uninitialized for the caller to fill in
+    unsafe fn allocate_for_slice(len: usize) -> NonNull<ArcInner<[T]>> {
+        let layout = Self::slice_layout(len);
+        let Some(memory) = NonNull::new(alloc::alloc(layout)) else {
+            alloc::handle_alloc_error(layout);
+        };
+
+        let inner = ptr::slice_from_raw_parts_mut(memory.cast::<T>().as_ptr(), len) as *mut ArcInner<[T]>;
+        (&raw mut (*inner).strong).write(AtomicUsize::new(1));
+        (&raw mut (*inner).weak).write(AtomicUsize::new(1));
+
+        NonNull::new_unchecked(inner)
+    }
+
+
+    // moves `len` elements from `values` into a new allocation; the caller must not drop them
+    unsafe fn move_from_slice(values: *const T, len: usize) -> Self {
+        let inner = Self::allocate_for_slice(len);
+        ptr::copy_nonoverlapping(values, &raw mut (*inner.as_ptr()).value as *mut T, len);
+
+        MyArc {
+            inner,
+            _marker: PhantomData
+        }
+    }
+
+
+    // fills a new allocation of exactly `len` elements from `iter`; if `iter` turns out to yield
+    // fewer or more elements, everything taken from it so far is handed back in order instead
+    fn from_iter_exact(iter: &mut impl Iterator<Item = T>, len: usize) -> Result<Self, Vec<T>> {
+        let mut guard = SliceGuard {
+            inner: unsafe { Self::allocate_for_slice(len) },
+            len,
+            written: 0
+        };
+
+        while guard.written < len {
+            let Some(value) = iter.next() else {
+                return Err(guard.into_vec());
+            };
+
+            unsafe { guard.elements().add(guard.written).write(value) };
+            guard.written += 1;
+        }
+
+        if let Some(value) = iter.next() {
+            let mut values = guard.into_vec();
+            values.push(value);
+            return Err(values);
+        }
+
+        let inner = guard.inner;
+        mem::forget(guard);
+
+        Ok(MyArc {
+            inner,
+            _marker: PhantomData
+        })
+    }
+}
+
+
+// owns a slice allocation while it is being filled: if filling it is abandoned, or a panic unwinds
+// through it, the elements written so far are dropped and the allocation is freed
+struct SliceGuard<T> {
+    inner: NonNull<ArcInner<[T]>>,
+    len: usize,
+    written: usize
+}
+
+
+impl<T> SliceGuard<T> {
+    fn elements(&self) -> *mut T {
+        unsafe { &raw mut (*self.inner.as_ptr()).value as *mut T }
+    }
+
+
+    // moves the elements written so far into a vector and frees the allocation
+    fn into_vec(mut self) -> Vec<T> {
+        let mut values = Vec::with_capacity(self.written);
+
+        unsafe {
+            ptr::copy_nonoverlapping(self.elements(), values.as_mut_ptr(), self.written);
+            values.set_len(self.written);
+        }
+
+        // the elements are owned by the vector now, so only the allocation is left to free
+        self.written = 0;
+        values
+    }
+}
+
+
+impl<T> Drop for SliceGuard<T> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.elements(), self.written));
+            alloc::dealloc(self.inner.cast().as_ptr(), MyArc::<[T]>::slice_layout(self.len));
+        }
+    }
+}
+
+
+impl MyArc<dyn Any + Send + Sync> {
+    pub fn downcast<T: Any + Send + Sync>(self) -> Result<MyArc<T>, Self> {
+        if (*self).is::<T>() {
+            Ok(unsafe { self.downcast_unchecked() })
+        } else {
+            Err(self)
+        }
+    }
+
+
+    /// # Safety
+    ///
+    /// The value must be a `T`, as checked by `downcast`.
+    pub unsafe fn downcast_unchecked<T: Any + Send + Sync>(self) -> MyArc<T> {
+        let this = ManuallyDrop::new(self);
+
+        MyArc {
+            inner: this.inner.cast::<ArcInner<T>>(),
+            _marker: PhantomData
+        }
+    }
+}
+
+
+impl<T: ?Sized> Clone for MyArc<T> {
+    fn clone(&self) -> Self {
+        // holding `self` keeps the value alive, so the increment needs no synchronization
+        increment(&self.inner().strong);
+
+        MyArc {
+            inner: self.inner,
+            _marker: PhantomData
+        }
+    }
+}
+
+
+impl<T: ?Sized> Drop for MyArc<T> {
+    fn drop(&mut self) {
+        // every use of the value through this reference happens before the decrement
+        if self.inner().strong.fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+
+        // synchronizes with the decrements of all other references, so their uses of the value
+        // happen before it is dropped
+        fence(Ordering::Acquire);
+        unsafe { ptr::drop_in_place(&raw mut (*self.inner.as_ptr()).value) };
+
+        // releases the implicit weak reference of the strong ones, which frees the allocation
+        // unless weak references are left
+        drop(ArcWeak {inner: self.inner, _marker: PhantomData});
+    }
+}
+
+
+impl<T: ?Sized> Clone for ArcWeak<T> {
+    fn clone(&self) -> Self {
+        if let Some(counts) = self.counts() {
+            increment(counts.weak);
+        }
+
+        ArcWeak {
+            inner: self.inner,
+            _marker: PhantomData
+        }
+    }
+}
+
+
+impl<T: ?Sized> Drop for ArcWeak<T> {
+    fn drop(&mut self) {
+        let Some(counts) = self.counts() else {
+            return;
+        };
+
+        if counts.weak.fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+
+        // the value is gone already, and every other reference has let go of the counts
+        fence(Ordering::Acquire);
+        unsafe {
+            let layout = Layout::for_value(&*self.inner.as_ptr());
+            alloc::dealloc(self.inner.as_ptr().cast(), layout);
+        }
+    }
+}
+
+
+impl<T> Default for ArcWeak<T> {
+    fn default() -> Self {
+        ArcWeak::new()
+    }
+}
+
+
+impl<T: ?Sized> fmt::Debug for ArcWeak<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(ArcWeak)")
+    }
+}
+
+
+// moving a `MyArc<T>` only moves the pointer, never the value behind it
+impl<T: ?Sized> Unpin for MyArc<T> {}
+
+
+impl<T: ?Sized> Unpin for ArcWeak<T> {}
+
+
+impl<T: ?Sized> Deref for MyArc<T> {
+    type Target = T;
+
+
+    fn deref(&self) -> &T {
+        &self.inner().value
+    }
+}
+
+
+impl<T: Default> Default for MyArc<T> {
+    fn default() -> Self {
+        MyArc::new(T::default())
+    }
+}
+
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for MyArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+
+impl<T: ?Sized + fmt::Display> fmt::Display for MyArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+
+impl<T: ?Sized + Hash> Hash for MyArc<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+
+impl<T: ?Sized + PartialEq> PartialEq for MyArc<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+
+impl<T: ?Sized + Eq> Eq for MyArc<T> {}
+
+
+impl<T: ?Sized + PartialOrd> PartialOrd for MyArc<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+
+impl<T: ?Sized + Ord> Ord for MyArc<T> {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        (**self).cmp(&**other)
+    }
+}
+
+
+impl<T: ?Sized> Borrow<T> for MyArc<T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+
+impl<T: ?Sized> AsRef<T> for MyArc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+
+impl<T> From<T> for MyArc<T> {
+    fn from(value: T) -> Self {
+        MyArc::new(value)
+    }
+}
+
+
+impl<T> From<Box<T>> for MyArc<T> {
+    fn from(value: Box<T>) -> Self {
+        // the box has no room for the counts, so the value is copied from one heap allocation
+        // straight into the other, without passing through the stack
+        unsafe {
+            let inner = Box::into_raw(Box::<ArcInner<T>>::new_uninit()).cast::<ArcInner<T>>();
+            (&raw mut (*inner).strong).write(AtomicUsize::new(1));
+            (&raw mut (*inner).weak).write(AtomicUsize::new(1));
+
+            let value = Box::into_raw(value);
+            ptr::copy_nonoverlapping(value, &raw mut (*inner).value, 1);
+            drop(Box::from_raw(value as *mut ManuallyDrop<T>));
+
+            MyArc {
+                inner: NonNull::new_unchecked(inner),
+                _marker: PhantomData
+            }
+        }
+    }
+}
+
+
+impl MyArc<str> {
+    // the bytes must be valid UTF-8
+    unsafe fn from_utf8_unchecked(bytes: MyArc<[u8]>) -> Self {
+        let bytes = ManuallyDrop::new(bytes);
+
+        // `str` has the same layout and metadata as `[u8]`
+        MyArc {
+            inner: NonNull::new_unchecked(bytes.inner.as_ptr() as *mut ArcInner<str>),
+            _marker: PhantomData
+        }
+    }
+}
+
+
+impl From<&str> for MyArc<str> {
+    fn from(value: &str) -> Self {
+        // the bytes came from a `str`, so they are valid UTF-8
+        unsafe { MyArc::from_utf8_unchecked(MyArc::from(value.as_bytes())) }
+    }
+}
+
+
+impl From<Box<str>> for MyArc<str> {
+    fn from(value: Box<str>) -> Self {
+        unsafe { MyArc::from_utf8_unchecked(MyArc::from(value.into_boxed_bytes())) }
+    }
+}
+
+
+impl From<String> for MyArc<str> {
+    fn from(value: String) -> Self {
+        MyArc::from(value.as_str())
+    }
+}
+
+
+impl<T> From<Vec<T>> for MyArc<[T]> {
+    fn from(mut values: Vec<T>) -> Self {
+        unsafe {
+            let my_arc = MyArc::move_from_slice(values.as_ptr(), values.len());
+
+            // the elements moved into the new allocation, so the vector only frees its buffer
+            values.set_len(0);
+            my_arc
+        }
+    }
+}
+
+
+impl<T> From<Box<[T]>> for MyArc<[T]> {
+    fn from(values: Box<[T]>) -> Self {
+        unsafe {
+            let my_arc = MyArc::move_from_slice(values.as_ptr(), values.len());
+
+            // the elements moved into the new allocation, so only the box itself is freed
+            drop(Box::from_raw(Box::into_raw(values) as *mut [ManuallyDrop<T>]));
+            my_arc
+        }
+    }
+}
+
+
+impl<T: Clone> From<&[T]> for MyArc<[T]> {
+    fn from(values: &[T]) -> Self {
+        match Self::from_iter_exact(&mut values.iter().cloned(), values.len()) {
+            Ok(my_arc) => my_arc,
+            Err(_) => unreachable!("a slice yields exactly its length")
+        }
+    }
+}
+
+
+impl<T> FromIterator<T> for MyArc<[T]> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut iter = iter.into_iter();
+
+        // an exact size hint allows writing the elements straight into their final allocation;
+        // a hint that turns out to be wrong falls back to collecting into a vector first
+        match iter.size_hint() {
+            (lower, Some(upper)) if lower == upper => match Self::from_iter_exact(&mut iter, lower) {
+                Ok(my_arc) => my_arc,
+                Err(mut values) => {
+                    values.extend(iter);
+                    MyArc::from(values)
+                }
+            },
+            _ => MyArc::from(iter.collect::<Vec<T>>())
+        }
+    }
+}
+
+
+// a value shared with C through `myarc_clone` and `myarc_drop`
+pub type Foreign = Box<dyn Any + Send + Sync>;
+
+
+/// # Safety
+///
+/// `ptr` must be null or come from `MyArc::<Foreign>::into_raw`, and the value must still be alive.
+#[no_mangle]
+pub unsafe extern "C" fn myarc_clone(ptr: *const Foreign) -> *const Foreign {
+    if !ptr.is_null() {
+        MyArc::increment_strong_count(ptr);
+    }
+
+    ptr
+}
+
+
+/// # Safety
+///
+/// `ptr` must be null or come from `MyArc::<Foreign>::into_raw` or `myarc_clone`, and must not be
+/// used again afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn myarc_drop(ptr: *const Foreign) {
+    if !ptr.is_null() {
+        MyArc::decrement_strong_count(ptr);
+    }
+}
+
+
+// turns a `MyArc<T>` into a `MyArc<U>` for an unsized `U` that `T` coerces to, e.g.
+// `coerce_arc!(MyArc::new(Logger) => dyn Plugin + Send + Sync)`
+#[macro_export]
+macro_rules! coerce_arc {
+    ($arc:expr => $target:ty) => {
+        // `$arc` is evaluated outside of the `unsafe` block
+        match $arc {
+            // the only implicit coercion from one raw pointer type to another is an unsizing one
+            arc => unsafe { $crate::arc::MyArc::unsize(arc, |value| -> *const $target { value }) }
+        }
+    };
+}
+
+
+#[cfg(feature = "unsize")]
+impl<T: ?Sized + std::marker::Unsize<U>, U: ?Sized> std::ops::CoerceUnsized<MyArc<U>> for MyArc<T> {}
+
+
+#[cfg(feature = "unsize")]
+impl<T: ?Sized + std::marker::Unsize<U>, U: ?Sized> std::ops::CoerceUnsized<ArcWeak<U>> for ArcWeak<T> {}
+
+
+#[cfg(all(test, not(feature = "loom")))]
+mod tests {
+    use std::thread;
+    use std::any::Any;
+    use std::collections::HashMap;
+    use std::marker::PhantomPinned;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Barrier;
+    use crate::arc::{self, ArcWeak, Foreign, MyArc};
+
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+
+    #[test]
+    fn my_arc_new_clone_drop() {
+        let my_arc = MyArc::new(String::from("Hello World!"));
+        let my_arc_clone = my_arc.clone();
+
+        assert_eq!(*my_arc_clone, "Hello World!");
+        assert_eq!(MyArc::strong_count(&my_arc), 2);
+        assert!(MyArc::ptr_eq(&my_arc, &my_arc_clone));
+        assert!(!MyArc::ptr_eq(&my_arc, &MyArc::new(String::from("Hello World!"))));
+        assert_eq!(MyArc::as_ptr(&my_arc), &*my_arc_clone as *const String);
+
+        drop(my_arc_clone);
+        assert_eq!(MyArc::strong_count(&my_arc), 1);
+        assert_send_sync::<MyArc<String>>();
+        assert_send_sync::<ArcWeak<String>>();
+    }
+
+
+    #[test]
+    fn my_arc_downgrade_upgrade() {
+        let my_arc = MyArc::new(String::from("Hello World!"));
+        let weak = MyArc::downgrade(&my_arc);
+        let weak_clone = weak.clone();
+
+        assert_eq!((MyArc::strong_count(&my_arc), MyArc::weak_count(&my_arc)), (1, 2));
+        assert!(weak.ptr_eq(&weak_clone));
+        assert_eq!(*weak.upgrade().unwrap(), "Hello World!");
+
+        // the value goes with the last strong reference, the counts with the last weak one
+        drop(my_arc);
+        assert!(weak.upgrade().is_none());
+        assert_eq!((weak.strong_count(), weak.weak_count()), (0, 0));
+        drop(weak_clone);
+        drop(weak);
+
+        let empty = ArcWeak::<String>::new();
+        assert!(empty.upgrade().is_none());
+        assert_eq!((empty.strong_count(), empty.clone().weak_count()), (0, 0));
+        assert_eq!(format!("{:?}", empty), "(ArcWeak)");
+    }
+
+
+    #[test]
+    fn my_arc_make_mut() {
+        // shared with another strong reference: the value is cloned
+        let mut my_arc = MyArc::new(vec![1]);
+        let other = my_arc.clone();
+        MyArc::make_mut(&mut my_arc).push(2);
+        assert_eq!((&*my_arc, &*other), (&vec![1, 2], &vec![1]));
+        assert!(!MyArc::ptr_eq(&my_arc, &other));
+        assert_eq!(MyArc::strong_count(&other), 1);
+
+        // shared with weak references only: the value moves, and they are left behind
+        let weak = MyArc::downgrade(&my_arc);
+        let ptr = MyArc::as_ptr(&my_arc);
+        MyArc::make_mut(&mut my_arc).push(3);
+        assert_eq!(*my_arc, [1, 2, 3]);
+        assert_ne!(MyArc::as_ptr(&my_arc), ptr);
+        assert!(weak.upgrade().is_none());
+        assert_eq!((MyArc::strong_count(&my_arc), MyArc::weak_count(&my_arc)), (1, 0));
+
+        // not shared at all: the value is changed in place
+        let ptr = MyArc::as_ptr(&my_arc);
+        MyArc::make_mut(&mut my_arc).push(4);
+        assert_eq!(*my_arc, [1, 2, 3, 4]);
+        assert_eq!(MyArc::as_ptr(&my_arc), ptr);
+        assert_eq!(MyArc::strong_count(&my_arc), 1);
+
+        // a clone on another thread is dropped before the next write, which then needs no copy
+        let clone = my_arc.clone();
+        thread::scope(|scope| {
+            scope.spawn(move || assert_eq!(clone.len(), 4));
+        });
+        MyArc::make_mut(&mut my_arc).push(5);
+        assert_eq!(MyArc::as_ptr(&my_arc), ptr);
+    }
+
+
+    #[test]
+    fn my_arc_get_mut() {
+        let mut my_arc = MyArc::new(1);
+        *MyArc::get_mut(&mut my_arc).unwrap() += 1;
+        assert_eq!(*my_arc, 2);
+
+        // any other reference, weak ones included, rules out mutable access
+        let clone = my_arc.clone();
+        assert!(MyArc::get_mut(&mut my_arc).is_none());
+        drop(clone);
+
+        let weak = MyArc::downgrade(&my_arc);
+        assert!(MyArc::get_mut(&mut my_arc).is_none());
+        drop(weak);
+
+        assert_eq!(MyArc::get_mut(&mut my_arc), Some(&mut 2));
+        assert_eq!(MyArc::weak_count(&my_arc), 0);
+    }
+
+
+    #[test]
+    fn my_arc_try_unwrap_into_inner() {
+        let my_arc = MyArc::new(String::from("Hello World!"));
+        let clone = my_arc.clone();
+        let weak = MyArc::downgrade(&my_arc);
+
+        let my_arc = MyArc::try_unwrap(my_arc).unwrap_err();
+        assert_eq!(MyArc::into_inner(clone), None);
+        assert_eq!(MyArc::strong_count(&my_arc), 1);
+
+        // the value moves out, weak references stay behind with the allocation
+        assert_eq!(MyArc::try_unwrap(my_arc).unwrap(), "Hello World!");
+        assert!(weak.upgrade().is_none());
+
+        assert_eq!(MyArc::into_inner(MyArc::new(7)), Some(7));
+    }
+
+
+    #[test]
+    fn my_arc_into_inner_concurrently() {
+        let rounds = if cfg!(miri) { 10 } else { 1_000 };
+        let threads = 4;
+
+        // whichever thread gives up the last reference gets the value, and no other one does
+        for round in 0..rounds {
+            let my_arc = MyArc::new(round);
+            let mut clones: Vec<_> = (1..threads).map(|_| my_arc.clone()).collect();
+            clones.push(my_arc);
+            let barrier = Barrier::new(threads);
+
+            let values: Vec<_> = thread::scope(|scope| {
+                let handles: Vec<_> = clones
+                    .into_iter()
+                    .map(|clone| {
+                        let barrier = &barrier;
+                        scope.spawn(move || {
+                            barrier.wait();
+                            MyArc::into_inner(clone)
+                        })
+                    })
+                    .collect();
+
+                handles.into_iter().filter_map(|handle| handle.join().unwrap()).collect()
+            });
+
+            assert_eq!(values, [round]);
+        }
+    }
+
+
+    #[test]
+    fn my_arc_upgrade_races_last_drop() {
+        struct Tracked<'a>(&'a AtomicUsize);
+
+        impl Drop for Tracked<'_> {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let drops = AtomicUsize::new(0);
+        let rounds = if cfg!(miri) { 10 } else { 1_000 };
+
+        // an upgrade either wins against the last drop and keeps the value alive, or fails, but it
+        // never revives a value that is being dropped
+        for round in 1..=rounds {
+            let my_arc = MyArc::new(Tracked(&drops));
+            let weak = MyArc::downgrade(&my_arc);
+            let barrier = Barrier::new(2);
+
+            thread::scope(|scope| {
+                scope.spawn(|| {
+                    barrier.wait();
+                    drop(my_arc);
+                });
+                scope.spawn(|| {
+                    barrier.wait();
+                    if let Some(upgraded) = weak.upgrade() {
+                        assert_eq!(drops.load(Ordering::Relaxed), round - 1);
+                        drop(upgraded);
+                    }
+                });
+            });
+
+            assert!(weak.upgrade().is_none());
+            assert_eq!(drops.load(Ordering::Relaxed), round);
+        }
+    }
+
+
+    #[test]
+    fn my_arc_shared_between_threads() {
+        struct Tracked<'a>(&'a AtomicUsize);
+
+        impl Drop for Tracked<'_> {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let drops = AtomicUsize::new(0);
+        let my_arc = MyArc::new(Tracked(&drops));
+
+        // every thread clones and drops its own references, and the last one may be dropped on
+        // any of them
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                let my_arc = my_arc.clone();
+                scope.spawn(move || {
+                    let clones: Vec<_> = (0..100).map(|_| my_arc.clone()).collect();
+                    assert!(MyArc::strong_count(&my_arc) > 100);
+                    drop(clones);
+                });
+            }
+        });
+
+        assert_eq!(MyArc::strong_count(&my_arc), 1);
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+        drop(my_arc);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+
+        // the last reference can also be dropped by another thread than the one that created it
+        let my_arc = MyArc::new(Tracked(&drops));
+        thread::scope(|scope| {
+            scope.spawn(move || drop(my_arc));
+        });
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
+    }
+
+
+    #[test]
+    fn my_arc_new_cyclic() {
+        // a worker whose callbacks run on other threads and find their way back to it
+        struct Worker {
+            me: ArcWeak<Worker>,
+            calls: AtomicUsize
+        }
+
+        impl Worker {
+            fn callback(&self) -> impl FnOnce() + Send + 'static {
+                let me = self.me.clone();
+                move || {
+                    if let Some(worker) = me.upgrade() {
+                        worker.calls.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+
+        let worker = MyArc::new_cyclic(|me| {
+            assert!(me.upgrade().is_none());
+            Worker {me: me.clone(), calls: AtomicUsize::new(0)}
+        });
+        assert_eq!((MyArc::strong_count(&worker), MyArc::weak_count(&worker)), (1, 1));
+
+        let callbacks: Vec<_> = (0..4).map(|_| worker.callback()).collect();
+        thread::scope(|scope| {
+            for callback in callbacks {
+                scope.spawn(callback);
+            }
+        });
+        assert_eq!(worker.calls.load(Ordering::Relaxed), 4);
+
+        // the callbacks do nothing once the worker is gone
+        let callback = worker.callback();
+        drop(worker);
+        thread::spawn(callback).join().unwrap();
+    }
+
+
+    #[test]
+    #[should_panic(expected = "construction failed")]
+    fn my_arc_new_cyclic_panics() {
+        MyArc::<String>::new_cyclic(|_| panic!("construction failed"));
+    }
+
+
+    #[test]
+    fn my_arc_pin() {
+        // an intrusive node that must not move once other threads may point at it
+        struct Node {
+            value: u32,
+            _pinned: PhantomPinned
+        }
+
+        fn assert_unpin<T: Unpin>() {}
+
+        let pinned = MyArc::pin(Node {value: 1, _pinned: PhantomPinned});
+        let address = (&*pinned as *const Node).addr();
+
+        // clones on other threads see the value at the same address
+        let addresses: Vec<usize> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..2).map(|_| {
+                let clone = pinned.clone();
+                scope.spawn(move || {
+                    assert_eq!(clone.as_ref().get_ref().value, 1);
+                    (&*clone as *const Node).addr()
+                })
+            }).collect();
+
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+        assert_eq!(addresses, [address; 2]);
+
+        let moved = Box::new(pinned);
+        assert_eq!((&**moved as *const Node).addr(), address);
+        assert_unpin::<MyArc<Node>>();
+        assert_unpin::<ArcWeak<Node>>();
+    }
+
+
+    #[test]
+    fn my_arc_raw_pointers() {
+        let my_arc = MyArc::new(String::from("Hello World!"));
+        let weak = MyArc::downgrade(&my_arc);
+
+        let ptr = MyArc::into_raw(my_arc);
+        assert_eq!(unsafe { &*ptr }, "Hello World!");
+
+        unsafe { MyArc::increment_strong_count(ptr) };
+        assert_eq!(weak.strong_count(), 2);
+        unsafe { MyArc::decrement_strong_count(ptr) };
+
+        let my_arc = unsafe { MyArc::from_raw(ptr) };
+        assert_eq!(MyArc::strong_count(&my_arc), 1);
+        assert!(MyArc::ptr_eq(&my_arc, &weak.upgrade().unwrap()));
+        drop(my_arc);
+        assert!(weak.upgrade().is_none());
+    }
+
+
+    #[test]
+    fn my_arc_foreign_references() {
+        let shared: Foreign = Box::new(vec![1, 2, 3]);
+        let my_arc = MyArc::new(shared);
+        let weak = MyArc::downgrade(&my_arc);
+
+        // a pointer held by C, which C passes to its own threads
+        struct Handle(*const Foreign);
+        unsafe impl Send for Handle {}
+
+        let ptr = MyArc::into_raw(my_arc);
+        let handles: Vec<_> = (0..4).map(|_| Handle(unsafe { arc::myarc_clone(ptr) })).collect();
+        assert_eq!(weak.strong_count(), 5);
+
+        thread::scope(|scope| {
+            for handle in handles {
+                scope.spawn(move || unsafe {
+                    // moves the whole handle into the closure, not just the pointer in it
+                    let handle = handle;
+                    assert_eq!((*handle.0).downcast_ref::<Vec<i32>>(), Some(&vec![1, 2, 3]));
+                    arc::myarc_drop(handle.0);
+                });
+            }
+        });
+
+        assert_eq!(weak.strong_count(), 1);
+        unsafe { arc::myarc_drop(ptr) };
+        assert!(weak.upgrade().is_none());
+
+        assert!(unsafe { arc::myarc_clone(std::ptr::null()) }.is_null());
+        unsafe { arc::myarc_drop(std::ptr::null()) };
+    }
+
+
+    #[test]
+    fn my_arc_str_and_slice() {
+        let names: Vec<MyArc<str>> = vec![
+            MyArc::from("Hello World!"),
+            MyArc::from(String::from("x")),
+            MyArc::from(Box::<str>::from(""))
+        ];
+        assert_eq!(names.iter().map(|name| name.len()).collect::<Vec<_>>(), [12, 1, 0]);
+
+        let from_vec: MyArc<[String]> = MyArc::from(vec![String::from("a"), String::from("b")]);
+        let from_slice: MyArc<[String]> = MyArc::from(&[String::from("a"), String::from("b")][..]);
+        let from_box: MyArc<[String]> = MyArc::from(vec![String::from("a"), String::from("b")].into_boxed_slice());
+        let exact: MyArc<[u64]> = (1..=4).collect();
+        let filtered: MyArc<[u64]> = (1..=8).filter(|value| value % 2 == 0).collect();
+        let empty: MyArc<[u8]> = MyArc::from(Vec::new());
+
+        assert_eq!((&*from_vec, &*from_slice), (&*from_box, &*from_box));
+        assert_eq!((&*exact, &*filtered), (&[1, 2, 3, 4][..], &[2, 4, 6, 8][..]));
+        assert!(empty.is_empty());
+
+        // a slice is shared between threads like any other value, weak references included
+        let weak = MyArc::downgrade(&from_vec);
+        thread::scope(|scope| {
+            scope.spawn(move || assert_eq!(from_vec[1], "b"));
+        });
+        assert!(weak.upgrade().is_none());
+
+        let large: MyArc<[u64; 512]> = MyArc::from(Box::new([7; 512]));
+        assert!(large.iter().all(|value| *value == 7));
+        assert_eq!(*MyArc::<String>::from(Box::new(String::from("boxed"))), "boxed");
+    }
+
+
+    #[test]
+    fn my_arc_trait_objects() {
+        trait Plugin: Send + Sync {
+            fn name(&self) -> String;
+        }
+
+        struct Logger;
+        struct Counter(u32);
+
+        impl Plugin for Logger {
+            fn name(&self) -> String {
+                String::from("logger")
+            }
+        }
+
+        impl Plugin for Counter {
+            fn name(&self) -> String {
+                format!("counter {}", self.0)
+            }
+        }
+
+        let counter = MyArc::new(Counter(3));
+        let plugins: Vec<MyArc<dyn Plugin>> = vec![
+            coerce_arc!(MyArc::new(Logger) => dyn Plugin),
+            coerce_arc!(counter.clone() => dyn Plugin)
+        ];
+        let names = thread::scope(|scope| {
+            let handles: Vec<_> = plugins.iter().map(|plugin| scope.spawn(|| plugin.name())).collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect::<Vec<_>>()
+        });
+
+        assert_eq!(names, ["logger", "counter 3"]);
+        assert!(std::ptr::addr_eq(MyArc::as_ptr(&plugins[1]), MyArc::as_ptr(&counter)));
+        drop(plugins);
+        assert_eq!(MyArc::strong_count(&counter), 1);
+
+        let shared: MyArc<dyn Any + Send + Sync> = coerce_arc!(MyArc::new(String::from("a")) => dyn Any + Send + Sync);
+        let Err(shared) = shared.downcast::<u32>() else {
+            panic!("downcast to the wrong type");
+        };
+        assert_eq!(*shared.downcast::<String>().unwrap(), "a");
+    }
+
+
+    #[cfg(feature = "unsize")]
+    #[test]
+    fn my_arc_coerce_unsized() {
+        let my_arc: MyArc<dyn std::fmt::Display + Send + Sync> = MyArc::new(42);
+        let value = MyArc::new(String::from("value"));
+        let weak = MyArc::downgrade(&value);
+        let weak: ArcWeak<dyn std::fmt::Display + Send + Sync> = weak;
+
+        assert_eq!(my_arc.to_string(), "42");
+        assert_eq!(weak.upgrade().unwrap().to_string(), "value");
+    }
+
+
+    #[test]
+    fn my_arc_trait_impls() {
+        let my_arc = MyArc::new(String::from("b"));
+
+        assert_eq!(format!("{:?}", my_arc), "\"b\"");
+        assert_eq!(my_arc.to_string(), "b");
+        assert_eq!(*MyArc::<Vec<u8>>::default(), Vec::<u8>::new());
+        assert_eq!(MyArc::from(String::from("b")), my_arc);
+        assert!(MyArc::new(String::from("a")) < my_arc);
+        assert_eq!(AsRef::<String>::as_ref(&my_arc), "b");
+
+        let mut counts: HashMap<MyArc<String>, usize> = HashMap::new();
+        *counts.entry(my_arc.clone()).or_default() += 1;
+        *counts.entry(MyArc::new(String::from("b"))).or_default() += 1;
+        assert_eq!(counts[&String::from("b")], 2);
+    }
+}
+
+
+#[cfg(all(test, feature = "loom"))]
+mod loom_tests {
+    use loom::cell::UnsafeCell;
+    use loom::sync::Arc;
+    use loom::sync::atomic::{AtomicUsize, Ordering};
+    use loom::thread;
+    use crate::arc::MyArc;
+
+
+    // a value whose drop conflicts with every read of it, so loom reports a data race unless all
+    // reads happen before the drop; it also counts its drops
+    struct Payload {
+        data: UnsafeCell<u32>,
+        drops: Arc<AtomicUsize>
+    }
+
+
+    unsafe impl Sync for Payload {}
+
+
+    impl Payload {
+        fn new(data: u32, drops: &Arc<AtomicUsize>) -> Self {
+            Payload {data: UnsafeCell::new(data), drops: drops.clone()}
+        }
+
+
+        fn read(&self) -> u32 {
+            self.data.with(|data| unsafe { *data })
+        }
+    }
+
+
+    impl Drop for Payload {
+        fn drop(&mut self) {
+            self.data.with_mut(|data| unsafe { *data = 0 });
+            self.drops.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+
+    #[test]
+    fn my_arc_clone_drop() {
+        loom::model(|| {
+            let drops = Arc::new(AtomicUsize::new(0));
+            let my_arc = MyArc::new(Payload::new(7, &drops));
+
+            // whichever thread drops the last reference must see the reads of the others
+            let handles: Vec<_> = (0..2).map(|_| {
+                let clone = my_arc.clone();
+                thread::spawn(move || assert_eq!(clone.read(), 7))
+            }).collect();
+
+            assert_eq!(my_arc.read(), 7);
+            drop(my_arc);
+
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            assert_eq!(drops.load(Ordering::Relaxed), 1);
+        });
+    }
+
+
+    #[test]
+    fn my_arc_upgrade_races_last_drop() {
+        loom::model(|| {
+            let drops = Arc::new(AtomicUsize::new(0));
+            let my_arc = MyArc::new(Payload::new(7, &drops));
+            let weak = MyArc::downgrade(&my_arc);
+
+            let dropper = thread::spawn(move || drop(my_arc));
+            let upgrader = thread::spawn(move || {
+                if let Some(upgraded) = weak.upgrade() {
+                    assert_eq!(upgraded.read(), 7);
+                }
+            });
+
+            dropper.join().unwrap();
+            upgrader.join().unwrap();
+            assert_eq!(drops.load(Ordering::Relaxed), 1);
+        });
+    }
+
+
+    #[test]
+    fn my_arc_get_mut_races_downgrade() {
+        loom::model(|| {
+            let mut my_arc = MyArc::new(0u32);
+            let clone = my_arc.clone();
+
+            // by the time the clone is gone, a weak reference has taken its place, so the value
+            // is never unique, however the two threads interleave
+            let downgrader = thread::spawn(move || {
+                let weak = MyArc::downgrade(&clone);
+                drop(clone);
+                weak
+            });
+            assert!(MyArc::get_mut(&mut my_arc).is_none());
+
+            let weak = downgrader.join().unwrap();
+            assert!(MyArc::ptr_eq(&weak.upgrade().unwrap(), &my_arc));
+        });
+    }
+
+
+    #[test]
+    fn my_arc_into_inner_races() {
+        loom::model(|| {
+            let drops = Arc::new(AtomicUsize::new(0));
+            let my_arc = MyArc::new(Payload::new(7, &drops));
+            let clone = my_arc.clone();
+
+            let other = thread::spawn(move || MyArc::into_inner(clone).map(|payload| payload.read()));
+            let here = MyArc::into_inner(my_arc).map(|payload| payload.read());
+            let other = other.join().unwrap();
+
+            // exactly one side gets the value, and nothing is dropped twice or leaked
+            assert!(matches!((here, other), (Some(7), None) | (None, Some(7))));
+            assert_eq!(drops.load(Ordering::Relaxed), 1);
+        });
+    }
+
+
+    #[test]
+    fn my_arc_make_mut_races_upgrade() {
+        loom::model(|| {
+            let mut my_arc = MyArc::new(1u32);
+            let weak = MyArc::downgrade(&my_arc);
+
+            // an upgrade that wins keeps the old value, one that loses finds nothing
+            let upgrader = thread::spawn(move || weak.upgrade().map(|upgraded| *upgraded));
+            *MyArc::make_mut(&mut my_arc) += 1;
+
+            assert!(matches!(upgrader.join().unwrap(), Some(1) | None));
+            assert_eq!(*my_arc, 2);
+        });
+    }
+}
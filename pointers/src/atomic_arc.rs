@@ -0,0 +1,382 @@
+/*
+- `AtomicMyArc<T>` is a shared slot holding a `MyArc<T>` that threads can replace while others are
+reading it, in the spirit of `arc-swap`: a configuration that is read on every request and
+reloaded once in a while. `load` hands out a `MyArc<T>` snapshot that stays valid however often the
+slot is replaced afterwards, and `store`, `swap` and `compare_and_swap` put a new `MyArc<T>` in.
+`rcu` combines them into a read-copy-update loop that derives the new value from the current one.
+
+- The slot is an `AtomicPtr` to the value, holding one strong reference of its own. The difficulty
+is the window in `load` between reading the pointer and incrementing the strong count behind it:
+a writer that replaced the pointer in the meantime could drop the last reference, and the count
+would be incremented in freed memory. Readers therefore announce themselves in one of two reader
+counts before they read the pointer, and a writer that replaced the pointer waits until every
+reader that may have read the old one has taken its reference, before it gives up the slot's
+reference to the old value.
+
+- Which of the two counts a reader uses is picked by an epoch bit. A writer swaps the pointer,
+flips the epoch, and then waits for the count of the old epoch to drain. Readers that arrive after
+the flip announce themselves in the other count, so a steady stream of readers cannot keep a
+writer waiting forever; it only waits for the handful of loads that were in flight. Writers take a
+small spinlock among themselves, so only one epoch flip is in progress at a time.
+
+- A reader announces itself, issues a `SeqCst` fence, then reads the epoch again and starts over
+if it changed, and only then reads the pointer. A writer swaps the pointer, flips the epoch with
+`Release`, issues a `SeqCst` fence, and then polls the count. The two fences are what makes the
+scheme sound: they are ordered one way or the other, and whichever thread issues the later one
+sees the writes the other made before its fence. Say a reader read the pointer `p` that a writer
+`W` replaces. Then the reader did not see `W`'s swap, so its fence came first, and `W` sees its
+announcement in whichever count it made it in. It made it in the count `W` waits for, unless its
+second epoch read was older than the previous writer `W'`'s flip, which it can only be if its fence
+also came before `W'`'s. In that case `W'` saw the announcement and waited for the reader to
+finish, and `W` came after `W'`. Either way, the reader takes its reference before the old value
+can go. The counts are decremented with `Release` and polled with `Acquire`, so the reader's
+increment of the strong count happens before the writer hands the old value on.
+
+- Only `load` is lock-free. It never waits: a reader only starts over if a writer flips the epoch
+while it is in the middle of announcing itself. The writes (`store`, `swap`, `compare_and_swap` and
+every attempt of `rcu`) are not: they serialize on the writer spinlock and then spin until the
+loads in flight are done. A writer that is preempted while it holds the lock stalls every other
+writer, and a reader preempted between its announcement and taking its reference stalls the writer
+that waits for it. The loads take a few instructions each, so the slot suits data that is read
+often and replaced rarely, and is no fit for values that many threads replace at once.
+
+- New values are published by an `AcqRel` swap and read in `load` with `Acquire`, so whatever was
+written to a value before it was stored is visible to every thread that loads it. The old value is
+dropped like any `MyArc<T>`: by whoever gives up its last reference, possibly a reader that still
+holds a snapshot.
+
+- The synchronization primitives come from `crate::sync`, so loom model-checks the races between
+`load` and the writers under the `loom` feature. Loom treats `SeqCst` loads and stores like
+`AcqRel` ones, but it does model `SeqCst` fences, which is one more reason the argument above rests
+on fences alone.
+*/
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use crate::arc::MyArc;
+use crate::sync::{fence, hint, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+
+pub struct AtomicMyArc<T> {
+    // a pointer from `MyArc::into_raw`, which owns one strong reference
+    ptr: AtomicPtr<T>,
+    epoch: AtomicUsize,
+    // the loads in flight that announced themselves under either epoch
+    readers: [AtomicUsize; 2],
+    writer: AtomicBool,
+    _marker: PhantomData<MyArc<T>>
+}
+
+
+impl<T> AtomicMyArc<T> {
+    pub fn new(value: MyArc<T>) -> Self {
+        AtomicMyArc {
+            ptr: AtomicPtr::new(MyArc::into_raw(value).cast_mut()),
+            epoch: AtomicUsize::new(0),
+            readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            writer: AtomicBool::new(false),
+            _marker: PhantomData
+        }
+    }
+
+
+    pub fn load(&self) -> MyArc<T> {
+        loop {
+            let epoch = self.epoch.load(Ordering::Acquire) & 1;
+            self.readers[epoch].fetch_add(1, Ordering::Relaxed);
+            fence(Ordering::SeqCst);
+
+            // a writer flipped the epoch in between and may not wait for this count any more
+            if self.epoch.load(Ordering::Acquire) & 1 != epoch {
+                self.readers[epoch].fetch_sub(1, Ordering::Release);
+                continue;
+            }
+
+            // any writer that replaces the pointer from here on waits for this reader, so the
+            // slot's reference keeps the value alive until the increment
+            let ptr = self.ptr.load(Ordering::Acquire);
+            let snapshot = unsafe {
+                MyArc::increment_strong_count(ptr);
+                MyArc::from_raw(ptr)
+            };
+
+            self.readers[epoch].fetch_sub(1, Ordering::Release);
+            return snapshot;
+        }
+    }
+
+
+    pub fn store(&self, value: MyArc<T>) {
+        drop(self.swap(value));
+    }
+
+
+    pub fn swap(&self, value: MyArc<T>) -> MyArc<T> {
+        let _guard = self.lock();
+        unsafe { self.replace(value) }
+    }
+
+
+    // replaces the value only if it is still `current`, and hands back the previous value either
+    // way: `Ok` if it was replaced, `Err` with the actual value if not
+    pub fn compare_and_swap(&self, current: &MyArc<T>, new: MyArc<T>) -> Result<MyArc<T>, MyArc<T>> {
+        let _guard = self.lock();
+
+        // only writers change the pointer, and this thread holds their lock
+        let ptr = self.ptr.load(Ordering::Relaxed);
+        if ptr.cast_const() != MyArc::as_ptr(current) {
+            return Err(unsafe {
+                MyArc::increment_strong_count(ptr);
+                MyArc::from_raw(ptr)
+            });
+        }
+
+        Ok(unsafe { self.replace(new) })
+    }
+
+
+    // replaces the value with one computed from the current one, retrying if another writer got
+    // there first, and hands back the value that was replaced; `f` may run more than once
+    pub fn rcu(&self, mut f: impl FnMut(&T) -> T) -> MyArc<T> {
+        let mut current = self.load();
+
+        loop {
+            match self.compare_and_swap(&current, MyArc::new(f(&current))) {
+                Ok(previous) => return previous,
+                Err(actual) => current = actual
+            }
+        }
+    }
+
+
+    pub fn into_inner(self) -> MyArc<T> {
+        let this = ManuallyDrop::new(self);
+        unsafe { MyArc::from_raw(this.ptr.load(Ordering::Relaxed)) }
+    }
+
+
+    // the caller must hold the writer lock
+    unsafe fn replace(&self, value: MyArc<T>) -> MyArc<T> {
+        let old = self.ptr.swap(MyArc::into_raw(value).cast_mut(), Ordering::AcqRel);
+
+        // readers that announce themselves under the new epoch read the new pointer, so only the
+        // count of the old epoch has to drain
+        let epoch = self.epoch.load(Ordering::Relaxed) & 1;
+        self.epoch.store(epoch ^ 1, Ordering::Release);
+        fence(Ordering::SeqCst);
+
+        while self.readers[epoch].load(Ordering::Acquire) != 0 {
+            hint::spin_loop();
+        }
+
+        // the slot's reference to the old value moves to the caller
+        MyArc::from_raw(old)
+    }
+
+
+    fn lock(&self) -> WriterGuard<'_> {
+        while self.writer.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            while self.writer.load(Ordering::Relaxed) {
+                hint::spin_loop();
+            }
+        }
+
+        WriterGuard {lock: &self.writer}
+    }
+}
+
+
+struct WriterGuard<'slot> {
+    lock: &'slot AtomicBool
+}
+
+
+impl Drop for WriterGuard<'_> {
+    fn drop(&mut self) {
+        self.lock.store(false, Ordering::Release);
+    }
+}
+
+
+impl<T> Drop for AtomicMyArc<T> {
+    fn drop(&mut self) {
+        // no load can be in flight while the slot is dropped
+        drop(unsafe { MyArc::from_raw(self.ptr.load(Ordering::Relaxed)) });
+    }
+}
+
+
+impl<T: Default> Default for AtomicMyArc<T> {
+    fn default() -> Self {
+        AtomicMyArc::new(MyArc::default())
+    }
+}
+
+
+impl<T> From<MyArc<T>> for AtomicMyArc<T> {
+    fn from(value: MyArc<T>) -> Self {
+        AtomicMyArc::new(value)
+    }
+}
+
+
+impl<T: fmt::Debug> fmt::Debug for AtomicMyArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AtomicMyArc").field(&*self.load()).finish()
+    }
+}
+
+
+#[cfg(all(test, not(feature = "loom")))]
+mod tests {
+    use std::thread;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use crate::arc::MyArc;
+    use crate::atomic_arc::AtomicMyArc;
+
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+
+    #[test]
+    fn atomic_my_arc_load_store() {
+        let slot = AtomicMyArc::new(MyArc::new(String::from("a")));
+        let snapshot = slot.load();
+        assert_eq!(*snapshot, "a");
+        assert_eq!(MyArc::strong_count(&snapshot), 2);
+
+        // a snapshot keeps the value it was taken of
+        slot.store(MyArc::new(String::from("b")));
+        assert_eq!((&*snapshot, &*slot.load()), (&String::from("a"), &String::from("b")));
+        assert_eq!(MyArc::strong_count(&snapshot), 1);
+
+        let previous = slot.swap(MyArc::new(String::from("c")));
+        assert_eq!(*previous, "b");
+
+        // comparisons go by identity, not by value
+        let current = slot.load();
+        let stale = MyArc::new(String::from("c"));
+        let actual = slot.compare_and_swap(&stale, MyArc::new(String::from("d"))).unwrap_err();
+        assert!(MyArc::ptr_eq(&actual, &current));
+        let replaced = slot.compare_and_swap(&current, MyArc::new(String::from("d"))).unwrap();
+        assert!(MyArc::ptr_eq(&replaced, &current));
+
+        assert_eq!(*slot.rcu(|value| format!("{}e", value)), "d");
+        assert_eq!(format!("{:?}", slot), "AtomicMyArc(\"de\")");
+        assert_eq!(*slot.into_inner(), "de");
+        assert_eq!(*AtomicMyArc::<u8>::default().load(), 0);
+        assert_send_sync::<AtomicMyArc<String>>();
+    }
+
+
+    #[test]
+    fn atomic_my_arc_hot_reload() {
+        // a configuration whose versions count their drops
+        struct Config<'a> {
+            version: usize,
+            drops: &'a AtomicUsize
+        }
+
+        impl Drop for Config<'_> {
+            fn drop(&mut self) {
+                self.drops.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let drops = AtomicUsize::new(0);
+        let created = AtomicUsize::new(1);
+        let versions = if cfg!(miri) { 20 } else { 2_000 };
+        let slot = AtomicMyArc::new(MyArc::new(Config {version: 0, drops: &drops}));
+        let done = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            // readers never see a version go backwards, nor one that was already dropped
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let mut last = 0;
+                    while !done.load(Ordering::Relaxed) {
+                        let config = slot.load();
+                        assert!(config.version >= last);
+                        last = config.version;
+                    }
+                });
+            }
+
+            // two writers reload concurrently, each bumping the version it finds
+            let writers: Vec<_> = (0..2).map(|_| scope.spawn(|| {
+                for _ in 0..versions / 2 {
+                    slot.rcu(|config| {
+                        created.fetch_add(1, Ordering::Relaxed);
+                        Config {version: config.version + 1, drops: &drops}
+                    });
+                }
+            })).collect();
+
+            for writer in writers {
+                writer.join().unwrap();
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+
+        assert_eq!(slot.load().version, versions);
+        drop(slot);
+        // including the versions of `rcu` attempts that lost against another writer
+        assert_eq!(drops.load(Ordering::Relaxed), created.load(Ordering::Relaxed));
+    }
+}
+
+
+#[cfg(all(test, feature = "loom"))]
+mod loom_tests {
+    use loom::sync::Arc;
+    use loom::thread;
+    use crate::arc::MyArc;
+    use crate::atomic_arc::AtomicMyArc;
+
+
+    #[test]
+    fn atomic_my_arc_load_races_swap() {
+        loom::model(|| {
+            let slot = Arc::new(AtomicMyArc::new(MyArc::new(1u32)));
+
+            let reader = {
+                let slot = slot.clone();
+                thread::spawn(move || *slot.load())
+            };
+
+            // the old value is only dropped once the reader holds its own reference to it
+            let old = slot.swap(MyArc::new(2));
+            assert_eq!(*old, 1);
+            drop(old);
+
+            assert!(matches!(reader.join().unwrap(), 1 | 2));
+            assert_eq!(*slot.load(), 2);
+        });
+    }
+
+
+    #[test]
+    fn atomic_my_arc_load_races_two_writers() {
+        let mut builder = loom::model::Builder::new();
+        builder.preemption_bound = Some(2);
+
+        // a reader that announced itself before the first writer flipped the epoch must not be
+        // missed by the second one, which flips it back
+        builder.check(|| {
+            let slot = Arc::new(AtomicMyArc::new(MyArc::new(0u32)));
+
+            let reader = {
+                let slot = slot.clone();
+                thread::spawn(move || *slot.load())
+            };
+            let writer = {
+                let slot = slot.clone();
+                thread::spawn(move || slot.store(MyArc::new(1)))
+            };
+            slot.store(MyArc::new(2));
+
+            writer.join().unwrap();
+            assert!(matches!(reader.join().unwrap(), 0..=2));
+        });
+    }
+}
@@ -0,0 +1,324 @@
+/*
+- `AtomicCell<T>` is the thread-safe counterpart of `Cell<T>`: values are copied in and out through
+a shared reference, but every access is synchronized, so the cell is `Sync` and can be shared
+between threads without a `Mutex`.
+
+- Primitive values that fit in an atomic word (`bool`, `char`, integers and floats up to 64 bits)
+are loaded, stored, swapped and compared with lock-free atomic instructions on the cell's memory.
+
+- Any other `Copy` type falls back to a small spinlock guarding the value. Each critical section
+is a single copy in or out, so spinning is cheaper than parking the thread.
+
+- Only primitive types take the lock-free path. Reinterpreting an arbitrary `T` as an integer would
+also read its padding bytes, which are uninitialized, so the decision is made by comparing
+`TypeId`s, which in turn requires `T: 'static`.
+*/
+use std::{mem, ptr};
+use std::any::TypeId;
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicU16, AtomicU32, AtomicU64, AtomicU8};
+
+use crate::sync::{hint, AtomicBool, Ordering};
+
+
+// runs `$body` with `$atomic` bound to the atomic integer that has the same size as `$type`,
+// viewing the memory behind `$ptr`
+macro_rules! with_atomic {
+    ($type:ty, $ptr:expr, |$atomic:ident| $body:expr) => {
+        match mem::size_of::<$type>() {
+            1 => { let $atomic = &*($ptr as *const AtomicU8); $body },
+            2 => { let $atomic = &*($ptr as *const AtomicU16); $body },
+            4 => { let $atomic = &*($ptr as *const AtomicU32); $body },
+            8 => { let $atomic = &*($ptr as *const AtomicU64); $body },
+            _ => unreachable!()
+        }
+    };
+}
+
+
+// `align(8)` places the value at an address that is suitably aligned for every atomic integer
+// that the lock-free path may view it as
+#[repr(C, align(8))]
+pub struct MyAtomicCell<T> {
+    value: UnsafeCell<T>,
+    lock: AtomicBool
+}
+
+
+// every access to the value is either atomic or happens under the spinlock
+unsafe impl<T: Send> Sync for MyAtomicCell<T> {}
+
+
+impl<T> MyAtomicCell<T> {
+    #[cfg(not(feature = "loom"))]
+    pub const fn new(value: T) -> Self {
+        Self {
+            value: UnsafeCell::new(value),
+            lock: AtomicBool::new(false)
+        }
+    }
+
+
+    // loom's atomics cannot be created in a const context
+    #[cfg(feature = "loom")]
+    pub fn new(value: T) -> Self {
+        Self {
+            value: UnsafeCell::new(value),
+            lock: AtomicBool::new(false)
+        }
+    }
+
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+
+impl<T: Copy + 'static> MyAtomicCell<T> {
+    pub fn is_lock_free() -> bool {
+        // loom cannot observe atomics that alias plain memory, so under loom every type goes
+        // through the spinlock, which is then what the model checker verifies
+        if cfg!(feature = "loom") {
+            return false;
+        }
+
+        let id = TypeId::of::<T>();
+        [
+            TypeId::of::<bool>(), TypeId::of::<char>(),
+            TypeId::of::<u8>(), TypeId::of::<u16>(), TypeId::of::<u32>(), TypeId::of::<u64>(),
+            TypeId::of::<i8>(), TypeId::of::<i16>(), TypeId::of::<i32>(), TypeId::of::<i64>(),
+            TypeId::of::<usize>(), TypeId::of::<isize>(), TypeId::of::<f32>(), TypeId::of::<f64>()
+        ].contains(&id)
+    }
+
+
+    pub fn load(&self) -> T {
+        if Self::is_lock_free() {
+            unsafe {
+                with_atomic!(T, self.value.get(), |atomic| {
+                    mem::transmute_copy(&atomic.load(Ordering::Acquire))
+                })
+            }
+        } else {
+            let _guard = self.lock();
+            unsafe { *self.value.get() }
+        }
+    }
+
+
+    pub fn store(&self, value: T) {
+        if Self::is_lock_free() {
+            unsafe {
+                with_atomic!(T, self.value.get(), |atomic| {
+                    atomic.store(mem::transmute_copy(&value), Ordering::Release)
+                })
+            }
+        } else {
+            let _guard = self.lock();
+            unsafe { *self.value.get() = value; }
+        }
+    }
+
+
+    pub fn swap(&self, value: T) -> T {
+        if Self::is_lock_free() {
+            unsafe {
+                with_atomic!(T, self.value.get(), |atomic| {
+                    mem::transmute_copy(&atomic.swap(mem::transmute_copy(&value), Ordering::AcqRel))
+                })
+            }
+        } else {
+            let _guard = self.lock();
+            unsafe { ptr::replace(self.value.get(), value) }
+        }
+    }
+
+
+    pub fn compare_exchange(&self, current: T, new: T) -> Result<T, T>
+    where
+        T: Eq
+    {
+        if Self::is_lock_free() {
+            // the lock-free types that are `Eq` compare equal exactly when their bits are equal
+            unsafe {
+                with_atomic!(T, self.value.get(), |atomic| {
+                    atomic.compare_exchange(
+                        mem::transmute_copy(&current),
+                        mem::transmute_copy(&new),
+                        Ordering::AcqRel,
+                        Ordering::Acquire
+                    ).map(|bits| mem::transmute_copy(&bits)).map_err(|bits| mem::transmute_copy(&bits))
+                })
+            }
+        } else {
+            let _guard = self.lock();
+            let value = unsafe { *self.value.get() };
+
+            if value == current {
+                unsafe { *self.value.get() = new; }
+                Ok(value)
+            } else {
+                Err(value)
+            }
+        }
+    }
+
+
+    fn lock(&self) -> SpinLockGuard<'_> {
+        while self.lock.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            // wait with plain loads so the waiting threads don't keep stealing the cache line
+            while self.lock.load(Ordering::Relaxed) {
+                hint::spin_loop();
+            }
+        }
+
+        SpinLockGuard {lock: &self.lock}
+    }
+}
+
+
+struct SpinLockGuard<'cell> {
+    lock: &'cell AtomicBool
+}
+
+
+impl Drop for SpinLockGuard<'_> {
+    fn drop(&mut self) {
+        self.lock.store(false, Ordering::Release);
+    }
+}
+
+
+#[cfg(all(test, not(feature = "loom")))]
+mod tests {
+    use std::thread;
+    use std::sync::Arc;
+    use crate::atomic_cell::MyAtomicCell;
+
+
+    #[test]
+    fn my_atomic_cell_is_lock_free() {
+        assert!(MyAtomicCell::<u8>::is_lock_free());
+        assert!(MyAtomicCell::<usize>::is_lock_free());
+        assert!(MyAtomicCell::<f64>::is_lock_free());
+        assert!(!MyAtomicCell::<(u8, u16)>::is_lock_free());
+        assert!(!MyAtomicCell::<[u64; 4]>::is_lock_free());
+    }
+
+
+    #[test]
+    fn my_atomic_cell_load_store_swap() {
+        let word = MyAtomicCell::new(1u32);
+        word.store(2);
+        assert_eq!(word.swap(3), 2);
+        assert_eq!(word.load(), 3);
+
+        let large = MyAtomicCell::new([1u64; 4]);
+        large.store([2; 4]);
+        assert_eq!(large.swap([3; 4]), [2; 4]);
+        assert_eq!(large.into_inner(), [3; 4]);
+    }
+
+
+    #[test]
+    fn my_atomic_cell_compare_exchange() {
+        let word = MyAtomicCell::new('a');
+        assert_eq!(word.compare_exchange('a', 'b'), Ok('a'));
+        assert_eq!(word.compare_exchange('a', 'c'), Err('b'));
+
+        let mut large = MyAtomicCell::new((1u8, 2u64));
+        assert_eq!(large.compare_exchange((1, 2), (3, 4)), Ok((1, 2)));
+        assert_eq!(large.compare_exchange((1, 2), (5, 6)), Err((3, 4)));
+
+        large.get_mut().0 = 7;
+        assert_eq!(large.load(), (7, 4));
+    }
+
+
+    #[test]
+    fn my_atomic_cell_shared_between_threads() {
+        let counter = Arc::new(MyAtomicCell::new(0u64));
+        let pair = Arc::new(MyAtomicCell::new([0u64; 2]));
+
+        let handles: Vec<_> = (0..8).map(|_| {
+            let counter = counter.clone();
+            let pair = pair.clone();
+
+            thread::spawn(move || {
+                for _ in 0..1000 {
+                    let mut current = counter.load();
+                    while let Err(actual) = counter.compare_exchange(current, current + 1) {
+                        current = actual;
+                    }
+
+                    // both halves are always written together, so a torn read would show up
+                    // as two different values
+                    let [first, second] = pair.load();
+                    assert_eq!(first, second);
+                    pair.store([first + 1, second + 1]);
+                }
+            })
+        }).collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(counter.load(), 8000);
+        let [first, second] = pair.load();
+        assert_eq!(first, second);
+    }
+}
+
+
+#[cfg(all(test, feature = "loom"))]
+mod loom_tests {
+    use loom::thread;
+    use loom::sync::Arc;
+    use crate::atomic_cell::MyAtomicCell;
+
+
+    #[test]
+    fn my_atomic_cell_store_is_not_torn() {
+        loom::model(|| {
+            let cell = Arc::new(MyAtomicCell::new([0u64; 2]));
+
+            let writer = {
+                let cell = cell.clone();
+                thread::spawn(move || cell.store([1, 1]))
+            };
+
+            let value = cell.load();
+            assert!(value == [0, 0] || value == [1, 1]);
+
+            writer.join().unwrap();
+            assert_eq!(cell.load(), [1, 1]);
+        });
+    }
+
+
+    #[test]
+    fn my_atomic_cell_compare_exchange_has_single_winner() {
+        loom::model(|| {
+            let cell = Arc::new(MyAtomicCell::new(0u32));
+
+            let handles: Vec<_> = (1..=2).map(|id| {
+                let cell = cell.clone();
+                thread::spawn(move || cell.compare_exchange(0, id).is_ok())
+            }).collect();
+
+            let winners = handles.into_iter()
+                .map(|handle| handle.join().unwrap())
+                .filter(|won| *won)
+                .count();
+            assert_eq!(winners, 1);
+            assert_ne!(cell.load(), 0);
+        });
+    }
+}
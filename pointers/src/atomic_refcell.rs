@@ -0,0 +1,456 @@
+/*
+- `AtomicRefCell<T>` is the thread-safe counterpart of `RefCell<T>`: it hands out shared
+`AtomicRef`s and exclusive `AtomicRefMut`s through a shared reference and checks the borrowing
+rules at runtime, but the borrow state lives in an `AtomicUsize`, so the cell is `Sync` and can be
+borrowed from several threads at once.
+
+- A conflicting borrow is never waited out. `borrow()` and `borrow_mut()` panic, and
+`try_borrow()` and `try_borrow_mut()` fail with a `BorrowError` or `BorrowMutError`, exactly like
+`RefCell<T>`. Use a `RwLock<T>` when threads should block until the value is free.
+
+- The highest bit of the state marks an exclusive borrow and the remaining bits count shared
+borrows. Acquiring a shared borrow is a compare-exchange loop that refuses to set the count while
+the exclusive bit is set, so a failed borrow never touches the state. An exclusive borrow swaps an
+unshared state for the exclusive bit in a single compare-exchange.
+
+- Acquiring a borrow synchronizes with the release of the previous conflicting borrow
+(`Acquire`/`Release`), so writes made through an `AtomicRefMut` on one thread are visible to
+whoever borrows the value next on another.
+
+- With the `loom` feature enabled, the state is a loom atomic, and the tests in `loom_tests`
+model-check the borrow transitions under every interleaving.
+*/
+use std::{fmt, mem};
+use std::error::Error;
+use std::ptr::NonNull;
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+use crate::sync::{AtomicUsize, Ordering};
+
+
+const UNSHARED: usize = 0;
+const EXCLUSIVE: usize = 1 << (usize::BITS - 1);
+const MAX_SHARED: usize = EXCLUSIVE - 1;
+
+
+pub struct MyAtomicRefCell<T: ?Sized> {
+    state: AtomicUsize,
+    value: UnsafeCell<T>
+}
+
+
+// every access to the value goes through a borrow that the atomic state has granted, so sharing
+// the cell hands out `&T` to several threads and `&mut T` to a single one
+unsafe impl<T: ?Sized + Send + Sync> Sync for MyAtomicRefCell<T> {}
+
+
+impl<T> MyAtomicRefCell<T> {
+    #[cfg(not(feature = "loom"))]
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicUsize::new(UNSHARED),
+            value: UnsafeCell::new(value)
+        }
+    }
+
+
+    // loom's atomics cannot be created in a const context
+    #[cfg(feature = "loom")]
+    pub fn new(value: T) -> Self {
+        Self {
+            state: AtomicUsize::new(UNSHARED),
+            value: UnsafeCell::new(value)
+        }
+    }
+
+
+    #[track_caller]
+    pub fn replace(&self, value: T) -> T {
+        mem::replace(&mut *self.borrow_mut(), value)
+    }
+
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+
+impl<T: ?Sized> MyAtomicRefCell<T> {
+    #[track_caller]
+    pub fn borrow(&self) -> AtomicRef<'_, T> {
+        match self.try_borrow() {
+            Ok(borrow) => borrow,
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+
+    #[track_caller]
+    pub fn borrow_mut(&self) -> AtomicRefMut<'_, T> {
+        match self.try_borrow_mut() {
+            Ok(borrow) => borrow,
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+
+    pub fn try_borrow(&self) -> Result<AtomicRef<'_, T>, BorrowError> {
+        Ok(AtomicRef {
+            _borrow: AtomicBorrowRef::new(&self.state)?,
+            value: unsafe { NonNull::new_unchecked(self.value.get()) }
+        })
+    }
+
+
+    pub fn try_borrow_mut(&self) -> Result<AtomicRefMut<'_, T>, BorrowMutError> {
+        Ok(AtomicRefMut {
+            _borrow: AtomicBorrowRefMut::new(&self.state)?,
+            value: unsafe { NonNull::new_unchecked(self.value.get()) },
+            _marker: PhantomData
+        })
+    }
+
+
+    pub const fn as_ptr(&self) -> *mut T {
+        self.value.get()
+    }
+
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for MyAtomicRefCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_borrow() {
+            Ok(borrow) => f.debug_struct("MyAtomicRefCell").field("value", &&*borrow).finish(),
+            Err(_) => f.debug_struct("MyAtomicRefCell").field("value", &format_args!("<borrowed>")).finish()
+        }
+    }
+}
+
+
+impl<T: Default> Default for MyAtomicRefCell<T> {
+    fn default() -> Self {
+        MyAtomicRefCell::new(T::default())
+    }
+}
+
+
+impl<T> From<T> for MyAtomicRefCell<T> {
+    fn from(value: T) -> Self {
+        MyAtomicRefCell::new(value)
+    }
+}
+
+
+// holds one shared borrow of the state and releases it on drop
+struct AtomicBorrowRef<'cell> {
+    state: &'cell AtomicUsize
+}
+
+
+impl<'cell> AtomicBorrowRef<'cell> {
+    fn new(state: &'cell AtomicUsize) -> Result<Self, BorrowError> {
+        let mut current = state.load(Ordering::Relaxed);
+
+        loop {
+            if current & EXCLUSIVE != 0 {
+                return Err(BorrowError {_private: ()});
+            }
+            assert!(current != MAX_SHARED, "too many shared borrows");
+
+            match state.compare_exchange_weak(current, current + 1, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => return Ok(AtomicBorrowRef {state}),
+                Err(actual) => current = actual
+            }
+        }
+    }
+}
+
+
+impl Drop for AtomicBorrowRef<'_> {
+    fn drop(&mut self) {
+        let previous = self.state.fetch_sub(1, Ordering::Release);
+        debug_assert!(previous != UNSHARED && previous & EXCLUSIVE == 0);
+    }
+}
+
+
+// holds the exclusive borrow of the state and releases it on drop
+struct AtomicBorrowRefMut<'cell> {
+    state: &'cell AtomicUsize
+}
+
+
+impl<'cell> AtomicBorrowRefMut<'cell> {
+    fn new(state: &'cell AtomicUsize) -> Result<Self, BorrowMutError> {
+        match state.compare_exchange(UNSHARED, EXCLUSIVE, Ordering::Acquire, Ordering::Relaxed) {
+            Ok(_) => Ok(AtomicBorrowRefMut {state}),
+            Err(_) => Err(BorrowMutError {_private: ()})
+        }
+    }
+}
+
+
+impl Drop for AtomicBorrowRefMut<'_> {
+    fn drop(&mut self) {
+        debug_assert_eq!(self.state.load(Ordering::Relaxed), EXCLUSIVE);
+        self.state.store(UNSHARED, Ordering::Release);
+    }
+}
+
+
+pub struct AtomicRef<'cell, T: ?Sized> {
+    value: NonNull<T>,
+    // only held for its `Drop`, which releases the shared borrow
+    _borrow: AtomicBorrowRef<'cell>
+}
+
+
+impl<T: ?Sized> Deref for AtomicRef<'_, T> {
+    type Target = T;
+
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.value.as_ref() }
+    }
+}
+
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for AtomicRef<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+
+impl<T: ?Sized + fmt::Display> fmt::Display for AtomicRef<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+
+pub struct AtomicRefMut<'cell, T: ?Sized> {
+    value: NonNull<T>,
+    // only held for its `Drop`, which releases the exclusive borrow
+    _borrow: AtomicBorrowRefMut<'cell>,
+    // makes `AtomicRefMut` invariant over `T`, like the `&mut T` it stands in for
+    _marker: PhantomData<&'cell mut T>
+}
+
+
+impl<T: ?Sized> Deref for AtomicRefMut<'_, T> {
+    type Target = T;
+
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.value.as_ref() }
+    }
+}
+
+
+impl<T: ?Sized> DerefMut for AtomicRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { self.value.as_mut() }
+    }
+}
+
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for AtomicRefMut<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+
+impl<T: ?Sized + fmt::Display> fmt::Display for AtomicRefMut<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+
+#[derive(Debug)]
+pub struct BorrowError {
+    _private: ()
+}
+
+
+impl fmt::Display for BorrowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("already mutably borrowed")
+    }
+}
+
+
+impl Error for BorrowError {}
+
+
+#[derive(Debug)]
+pub struct BorrowMutError {
+    _private: ()
+}
+
+
+impl fmt::Display for BorrowMutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("already borrowed")
+    }
+}
+
+
+impl Error for BorrowMutError {}
+
+
+#[cfg(all(test, not(feature = "loom")))]
+mod tests {
+    use std::thread;
+    use std::sync::Barrier;
+    use super::{MyAtomicRefCell, EXCLUSIVE, UNSHARED};
+    use crate::sync::Ordering;
+
+
+    #[test]
+    fn my_atomic_ref_cell_borrow() {
+        let cell = MyAtomicRefCell::new(String::from("MyAtomicRefCell"));
+
+        {
+            let first = cell.borrow();
+            let second = cell.borrow();
+            assert_eq!(cell.state.load(Ordering::Relaxed), 2);
+            assert_eq!(*first, *second);
+            assert_eq!(cell.try_borrow_mut().unwrap_err().to_string(), "already borrowed");
+        }
+
+        assert_eq!(cell.state.load(Ordering::Relaxed), UNSHARED);
+    }
+
+
+    #[test]
+    fn my_atomic_ref_cell_borrow_mut() {
+        let cell = MyAtomicRefCell::new(vec![1]);
+
+        {
+            let mut borrow = cell.borrow_mut();
+            borrow.push(2);
+            assert_eq!(cell.state.load(Ordering::Relaxed), EXCLUSIVE);
+            assert_eq!(cell.try_borrow().unwrap_err().to_string(), "already mutably borrowed");
+            assert!(cell.try_borrow_mut().is_err());
+            assert_eq!(format!("{:?}", cell), "MyAtomicRefCell { value: <borrowed> }");
+        }
+
+        assert_eq!(cell.replace(vec![3]), [1, 2]);
+        assert_eq!(format!("{:?}", cell), "MyAtomicRefCell { value: [3] }");
+        assert_eq!(cell.into_inner(), [3]);
+    }
+
+
+    #[test]
+    #[should_panic(expected = "already mutably borrowed")]
+    fn my_atomic_ref_cell_borrow_while_mutably_borrowed_panics() {
+        let cell = MyAtomicRefCell::new(0);
+
+        let _borrow_mut = cell.borrow_mut();
+        let _borrow = cell.borrow();
+    }
+
+
+    #[test]
+    fn my_atomic_ref_cell_shared_between_threads() {
+        let cell = MyAtomicRefCell::new(0u64);
+        let barrier = Barrier::new(4);
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    // every reader borrows before any of them releases
+                    let borrow = cell.borrow();
+                    barrier.wait();
+                    assert_eq!(*borrow, 0);
+                });
+            }
+        });
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..1000 {
+                        // writers never block, so they retry until the cell is free
+                        loop {
+                            if let Ok(mut value) = cell.try_borrow_mut() {
+                                *value += 1;
+                                break;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        assert_eq!(*cell.borrow(), 4000);
+    }
+}
+
+
+#[cfg(all(test, feature = "loom"))]
+mod loom_tests {
+    use loom::thread;
+    use loom::sync::Arc;
+    use super::{MyAtomicRefCell, UNSHARED};
+    use crate::sync::Ordering;
+
+
+    #[test]
+    fn my_atomic_ref_cell_borrow_mut_is_exclusive() {
+        loom::model(|| {
+            let cell = Arc::new(MyAtomicRefCell::new(0u32));
+
+            let writer = {
+                let cell = cell.clone();
+                thread::spawn(move || match cell.try_borrow_mut() {
+                    Ok(mut value) => {
+                        *value += 1;
+                        true
+                    },
+                    Err(_) => false
+                })
+            };
+
+            // the reader either sees the value before or after the write, never during it
+            let read = cell.try_borrow().map(|value| *value);
+
+            let wrote = writer.join().unwrap();
+            assert!(matches!(read, Ok(0) | Ok(1) | Err(_)));
+            assert_eq!(*cell.borrow(), u32::from(wrote));
+            assert_eq!(cell.state.load(Ordering::Relaxed), UNSHARED);
+        });
+    }
+
+
+    #[test]
+    fn my_atomic_ref_cell_shared_borrows_are_counted() {
+        loom::model(|| {
+            let cell = Arc::new(MyAtomicRefCell::new(0u32));
+
+            let handles: Vec<_> = (0..2).map(|_| {
+                let cell = cell.clone();
+                thread::spawn(move || *cell.borrow())
+            }).collect();
+
+            for handle in handles {
+                assert_eq!(handle.join().unwrap(), 0);
+            }
+
+            // every shared borrow was released, so an exclusive borrow succeeds again
+            assert!(cell.try_borrow_mut().is_ok());
+            assert_eq!(cell.state.load(Ordering::Relaxed), UNSHARED);
+        });
+    }
+}
@@ -12,17 +12,47 @@ to mutate through immutable references, which breaks Rust's thread safety guaran
 
 - `Cell<T>` requires `T` to implement the `Copy` trait for the `get` method, as it avoids borrowing
 the inner value. Instead, it returns a copy to prevent issues with Rust's borrowing rules.
+
+- Non-`Copy` values can still be moved in and out of a `Cell<T>` with `replace`, which swaps in a
+new value and returns the old one without ever handing out a reference to the inner value.
+
+- `Cell<T>` has the same in-memory representation as `T`, so a `&mut T` can be reinterpreted as a
+`&Cell<T>` with `from_mut`. The exclusive borrow is traded for a shared one that still allows
+mutation, which lets several closures update the same value.
+
+- A `Cell<[T]>` can be viewed as a `[Cell<T>]` with `as_slice_of_cells`, so every element of a
+shared slice can be mutated independently through a shared reference.
+
+- Cells holding integers get arithmetic helpers (`add`, `sub`, `checked_add`, `saturating_add`,
+...) that perform the read-modify-write in one call and return the new value, which turns a
+`MyCell<usize>` into a convenient single-threaded counter.
+
+- `Cell<T>` accepts unsized types such as `[T]` and `str`. Those cells can only be created behind a
+reference (via `from_mut` or unsized coercion of `&Cell<[T; N]>`), and methods that move the value
+in or out stay restricted to sized types.
+
+- Non-`Copy` values can be read with `get_cloned`, which briefly moves the value out of the cell
+while cloning it. Cloning in place would be unsound: a `Clone` implementation that reaches back
+into the same cell (through a thread local, say) could overwrite the value while it is still
+borrowed, so a placeholder from `Default` holds the cell in the meantime. The value goes back
+into the cell even if `clone` panics, so a failed read leaves the cell as it was.
+
+- With the `serde` feature enabled, a `Cell<T>` serializes as the value it holds and deserializes
+from it, so cells can sit inside config structs without a manual wrapper.
 */
+use std::{fmt, mem, ptr};
+use std::cmp::Ordering;
 use std::cell::UnsafeCell;
 
 
-pub struct MyCell<T> {
+#[repr(transparent)]
+pub struct MyCell<T: ?Sized> {
     value: UnsafeCell<T>
 }
 
 
 impl<T> MyCell<T> {
-    pub fn new(value: T) -> Self {
+    pub const fn new(value: T) -> Self {
         Self {
             value: UnsafeCell::new(value)
         }
@@ -42,6 +72,235 @@ impl<T> MyCell<T> {
     {
         unsafe { *self.value.get() }
     }
+
+
+    pub fn get_cloned(&self) -> T
+    where
+        T: Clone + Default
+    {
+        // puts the value back when dropped, so a panicking `clone` does not leave the placeholder
+        // behind
+        struct Restore<'cell, T> {
+            cell: &'cell MyCell<T>,
+            value: Option<T>
+        }
+
+        impl<T> Drop for Restore<'_, T> {
+            fn drop(&mut self) {
+                if let Some(value) = self.value.take() {
+                    self.cell.set(value);
+                }
+            }
+        }
+
+        let restore = Restore {cell: self, value: Some(self.take())};
+        restore.value.as_ref().unwrap().clone()
+    }
+
+
+    pub fn update(&self, f: impl FnOnce(T) -> T) -> T
+    where
+        T: Copy
+    {
+        let new = f(self.get());
+        self.set(new);
+        new
+    }
+
+
+    pub fn replace(&self, value: T) -> T {
+        unsafe { mem::replace(&mut *self.value.get(), value) }
+    }
+
+
+    pub fn take(&self) -> T
+    where
+        T: Default
+    {
+        self.replace(T::default())
+    }
+
+
+    pub fn swap(&self, other: &MyCell<T>) {
+        // swapping a cell with itself is a no-op
+        if ptr::eq(self, other) {
+            return;
+        }
+
+        unsafe {
+            ptr::swap(self.value.get(), other.value.get());
+        }
+    }
+
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+
+impl<T: ?Sized> MyCell<T> {
+    pub const fn as_ptr(&self) -> *mut T {
+        self.value.get()
+    }
+
+
+    pub fn get_mut(&mut self) -> &mut T {
+        // `&mut self` already guarantees exclusive access, so no interior mutability is needed
+        self.value.get_mut()
+    }
+
+
+    pub fn from_mut(value: &mut T) -> &MyCell<T> {
+        // `MyCell<T>` is `#[repr(transparent)]` over `UnsafeCell<T>`, which is itself transparent
+        // over `T`, and the exclusive borrow guarantees nothing else observes the value
+        unsafe { &*(value as *mut T as *const MyCell<T>) }
+    }
+}
+
+
+impl<T> MyCell<[T]> {
+    pub fn as_slice_of_cells(&self) -> &[MyCell<T>] {
+        // `MyCell<[T]>` and `[MyCell<T>]` share the layout of `[T]`, including the length metadata
+        unsafe { &*(self as *const MyCell<[T]> as *const [MyCell<T>]) }
+    }
+
+
+    pub fn len(&self) -> usize {
+        self.as_slice_of_cells().len()
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+
+    pub fn element(&self, index: usize) -> Option<&MyCell<T>> {
+        self.as_slice_of_cells().get(index)
+    }
+}
+
+
+impl<T, const N: usize> MyCell<[T; N]> {
+    pub fn as_array_of_cells(&self) -> &[MyCell<T>; N] {
+        // same reasoning as `as_slice_of_cells`, with the length known at compile time
+        unsafe { &*(self as *const MyCell<[T; N]> as *const [MyCell<T>; N]) }
+    }
+}
+
+
+macro_rules! impl_integer_ops {
+    ($($int:ty),*) => {
+        $(
+            impl MyCell<$int> {
+                pub fn add(&self, rhs: $int) -> $int {
+                    self.update(|value| value + rhs)
+                }
+
+
+                pub fn sub(&self, rhs: $int) -> $int {
+                    self.update(|value| value - rhs)
+                }
+
+
+                // leaves the cell untouched and returns `None` on overflow
+                pub fn checked_add(&self, rhs: $int) -> Option<$int> {
+                    let value = self.get().checked_add(rhs)?;
+                    self.set(value);
+                    Some(value)
+                }
+
+
+                pub fn checked_sub(&self, rhs: $int) -> Option<$int> {
+                    let value = self.get().checked_sub(rhs)?;
+                    self.set(value);
+                    Some(value)
+                }
+
+
+                pub fn saturating_add(&self, rhs: $int) -> $int {
+                    self.update(|value| value.saturating_add(rhs))
+                }
+
+
+                pub fn saturating_sub(&self, rhs: $int) -> $int {
+                    self.update(|value| value.saturating_sub(rhs))
+                }
+            }
+        )*
+    };
+}
+
+
+impl_integer_ops!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+
+impl<T: Copy> Clone for MyCell<T> {
+    fn clone(&self) -> Self {
+        MyCell::new(self.get())
+    }
+}
+
+
+impl<T: Default> Default for MyCell<T> {
+    fn default() -> Self {
+        MyCell::new(T::default())
+    }
+}
+
+
+impl<T: Copy + fmt::Debug> fmt::Debug for MyCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MyCell").field("value", &self.get()).finish()
+    }
+}
+
+
+impl<T: Copy + PartialEq> PartialEq for MyCell<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.get() == other.get()
+    }
+}
+
+
+impl<T: Copy + Eq> Eq for MyCell<T> {}
+
+
+impl<T: Copy + PartialOrd> PartialOrd for MyCell<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.get().partial_cmp(&other.get())
+    }
+}
+
+
+impl<T: Copy + Ord> Ord for MyCell<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.get().cmp(&other.get())
+    }
+}
+
+
+impl<T> From<T> for MyCell<T> {
+    fn from(value: T) -> Self {
+        MyCell::new(value)
+    }
+}
+
+
+#[cfg(feature = "serde")]
+impl<T: Copy + serde::Serialize> serde::Serialize for MyCell<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.get().serialize(serializer)
+    }
+}
+
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for MyCell<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(MyCell::new)
+    }
 }
 
 #[cfg(test)]
@@ -54,6 +313,21 @@ mod tests {
         assert_eq!(cell.get(), 10);
     }
 
+    #[test]
+    fn my_cell_const_new() {
+        thread_local! {
+            static CONFIG: MyCell<u32> = const { MyCell::new(0) };
+        }
+
+        CONFIG.with(|config| config.set(config.get() + 5));
+        assert_eq!(CONFIG.with(MyCell::get), 5);
+
+        // `MyCell` is not `Copy`, so repeating it in an array requires a const initializer
+        let counters = [const { MyCell::new(0) }; 3];
+        counters[1].set(1);
+        assert_eq!(counters.map(MyCell::into_inner), [0, 1, 0]);
+    }
+
     #[test]
     fn my_cell_set_and_get() {
         let cell = MyCell::new(0);
@@ -67,4 +341,230 @@ mod tests {
         cell.set(100);
         assert_eq!(cell_ref.get(), 100);
     }
+
+    #[test]
+    fn my_cell_get_cloned() {
+        let cell = MyCell::new(vec![1, 2]);
+
+        let snapshot = cell.get_cloned();
+        cell.set(vec![3]);
+
+        assert_eq!(snapshot, [1, 2]);
+        assert_eq!(cell.get_cloned(), [3]);
+        assert_eq!(cell.replace(Vec::new()), [3]);
+        assert!(cell.get_cloned().is_empty());
+    }
+
+    #[test]
+    fn my_cell_get_cloned_panics() {
+        struct Fragile(u8);
+
+        impl Default for Fragile {
+            fn default() -> Self {
+                Fragile(9)
+            }
+        }
+
+        impl Clone for Fragile {
+            fn clone(&self) -> Self {
+                assert_ne!(self.0, 0, "cannot clone zero");
+                Fragile(self.0)
+            }
+        }
+
+        let cell = MyCell::new(Fragile(0));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| cell.get_cloned()));
+
+        // the value is back in the cell, not the placeholder
+        assert!(result.is_err());
+        assert_eq!(cell.replace(Fragile(1)).0, 0);
+        assert_eq!(cell.get_cloned().0, 1);
+    }
+
+    #[test]
+    fn my_cell_update() {
+        let cell = MyCell::new(1);
+
+        assert_eq!(cell.update(|value| value + 1), 2);
+        assert_eq!(cell.update(|value| value * 10), 20);
+        assert_eq!(cell.get(), 20);
+    }
+
+    #[test]
+    fn my_cell_replace() {
+        let cell = MyCell::new(String::from("first"));
+        let cell_ref = &cell;
+
+        assert_eq!(cell_ref.replace(String::from("second")), "first");
+        assert_eq!(cell.replace(String::from("third")), "second");
+        assert_eq!(cell.replace(String::new()), "third");
+    }
+
+    #[test]
+    fn my_cell_take() {
+        let cell = MyCell::new(Some(String::from("taken")));
+
+        assert_eq!(cell.take(), Some(String::from("taken")));
+        assert_eq!(cell.take(), None);
+
+        let counter = MyCell::new(7);
+        assert_eq!(counter.take(), 7);
+        assert_eq!(counter.get(), 0);
+    }
+
+    #[test]
+    fn my_cell_swap() {
+        let first = MyCell::new(String::from("first"));
+        let second = MyCell::new(String::from("second"));
+
+        first.swap(&second);
+        assert_eq!(first.take(), "second");
+        assert_eq!(second.take(), "first");
+
+        let same = MyCell::new(5);
+        same.swap(&same);
+        assert_eq!(same.get(), 5);
+    }
+
+    #[test]
+    fn my_cell_into_inner_and_get_mut() {
+        let mut cell = MyCell::new(String::from("Hello"));
+
+        cell.get_mut().push_str(" World!");
+        assert_eq!(cell.into_inner(), "Hello World!");
+    }
+
+    #[test]
+    fn my_cell_from_mut() {
+        let mut value = 0;
+
+        {
+            let cell = MyCell::from_mut(&mut value);
+            let increment = || cell.set(cell.get() + 1);
+            let double = || cell.set(cell.get() * 2);
+
+            increment();
+            double();
+            increment();
+        }
+
+        assert_eq!(value, 3);
+    }
+
+    #[test]
+    fn my_cell_as_slice_of_cells() {
+        let mut values = [1, 2, 3];
+
+        {
+            let cells = MyCell::from_mut(&mut values[..]).as_slice_of_cells();
+            let (first, last) = (&cells[0], &cells[2]);
+
+            first.set(10);
+            last.set(first.get() + last.get());
+            cells[1].swap(&cells[0]);
+        }
+
+        assert_eq!(values, [2, 10, 13]);
+
+        let array = MyCell::new([4, 5]);
+        let slice: &MyCell<[i32]> = &array;
+        assert_eq!(slice.as_slice_of_cells()[1].get(), 5);
+    }
+
+    #[test]
+    fn my_cell_unsized() {
+        let array = MyCell::new([1, 2, 3]);
+        array.as_array_of_cells()[0].set(7);
+
+        let slice: &MyCell<[i32]> = &array;
+        assert_eq!(slice.len(), 3);
+        assert!(!slice.is_empty());
+        assert_eq!(slice.element(0).map(MyCell::get), Some(7));
+        assert!(slice.element(3).is_none());
+
+        let mut boxed: Box<MyCell<[i32]>> = Box::new(MyCell::new([1, 2]));
+        boxed.get_mut().reverse();
+        assert_eq!(boxed.element(0).map(MyCell::get), Some(2));
+
+        let mut text = String::from("hello");
+        let text_cell: &MyCell<str> = MyCell::from_mut(text.as_mut_str());
+        assert_eq!(std::mem::size_of_val(text_cell), 5);
+
+        let mut bytes = *b"abc";
+        let cell = MyCell::from_mut(&mut bytes[..]);
+        cell.element(1).unwrap().set(b'z');
+        assert_eq!(&bytes, b"azc");
+    }
+
+    #[test]
+    fn my_cell_as_ptr() {
+        let cell = MyCell::new(1);
+        let ptr = cell.as_ptr();
+
+        // no reference to the inner value is alive, so reading and writing through the raw
+        // pointer is fine, and the writes are visible to later `get` calls
+        unsafe {
+            assert_eq!(ptr.read(), 1);
+            ptr.write(2);
+        }
+        assert_eq!(cell.get(), 2);
+
+        cell.set(3);
+        assert_eq!(unsafe { *ptr }, 3);
+        assert_eq!(ptr, cell.as_ptr());
+    }
+
+    #[test]
+    fn my_cell_integer_ops() {
+        let counter = MyCell::new(0usize);
+
+        assert_eq!(counter.add(5), 5);
+        assert_eq!(counter.sub(2), 3);
+        assert_eq!(counter.checked_sub(4), None);
+        assert_eq!(counter.get(), 3);
+        assert_eq!(counter.saturating_sub(10), 0);
+
+        let small = MyCell::new(250u8);
+        assert_eq!(small.checked_add(5), Some(255));
+        assert_eq!(small.checked_add(1), None);
+        assert_eq!(small.saturating_add(1), 255);
+    }
+
+    #[test]
+    fn my_cell_clone_and_default() {
+        let cell = MyCell::new(3);
+        let clone = cell.clone();
+
+        cell.set(4);
+        assert_eq!(clone.get(), 3);
+        assert_eq!(MyCell::<u8>::default().get(), 0);
+        assert_eq!(MyCell::from(9).get(), 9);
+    }
+
+    #[test]
+    fn my_cell_debug() {
+        assert_eq!(format!("{:?}", MyCell::new(42)), "MyCell { value: 42 }");
+    }
+
+    #[test]
+    fn my_cell_comparisons() {
+        let small = MyCell::new(1);
+        let large = MyCell::new(2);
+
+        assert_eq!(small, MyCell::new(1));
+        assert_ne!(small, large);
+        assert!(small < large);
+        assert_eq!(small.cmp(&large), std::cmp::Ordering::Less);
+        assert_eq!(MyCell::new(f64::NAN).partial_cmp(&MyCell::new(1.0)), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn my_cell_serde() {
+        let cell = MyCell::new(7u32);
+        assert_eq!(serde_json::to_string(&cell).unwrap(), "7");
+
+        let cell: MyCell<u32> = serde_json::from_str("8").unwrap();
+        assert_eq!(cell.get(), 8);
+    }
 }
\ No newline at end of file
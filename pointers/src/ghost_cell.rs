@@ -0,0 +1,184 @@
+/*
+- `GhostCell<'id, T>` provides interior mutability with no runtime cost at all: the permission to
+read or write the value is not stored in the cell, but in a separate `GhostToken<'id>`. A shared
+`&GhostToken` unlocks shared borrows of every cell with the same brand, and a `&mut GhostToken`
+unlocks one exclusive borrow, so the borrow checker enforces "many readers or one writer" for a
+whole group of cells at once.
+
+- The brand `'id` is an invariant lifetime that is unique to one call of `GhostToken::new`. The
+token only exists inside the closure passed to `new`, and because the closure has to work for any
+`'id`, two tokens can never share a brand, so a token can only ever unlock its own cells.
+
+- Compared with `RefCell<T>`, there is no borrow flag to update and no panic path: a conflicting
+borrow is a compile error. The price is that borrows are tied to the token rather than to the
+cell, so two cells of the same brand cannot be mutably borrowed at the same time.
+
+- `GhostCell<'id, T>` is `#[repr(transparent)]`, so a `&mut T` can be turned into a
+`&GhostCell<'id, T>` with `from_mut`, and owning the cell or holding a `&mut` to it gives access
+without a token.
+
+- Since every access goes through the token, a cell can be shared between threads whenever the
+value could be: `&GhostToken` hands out `&T` and `&mut GhostToken` hands out `&mut T`, exactly like
+`RwLock<T>` without the lock.
+*/
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+
+
+// `fn(&'id ()) -> &'id ()` is invariant over `'id`, so a brand can neither grow nor shrink into
+// another brand
+type InvariantLifetime<'id> = PhantomData<fn(&'id ()) -> &'id ()>;
+
+
+pub struct GhostToken<'id> {
+    _brand: InvariantLifetime<'id>
+}
+
+
+impl GhostToken<'_> {
+    // the token cannot be returned: its brand must not escape the closure it was created for
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new<R>(f: impl for<'new_id> FnOnce(GhostToken<'new_id>) -> R) -> R {
+        f(GhostToken {_brand: PhantomData})
+    }
+}
+
+
+#[repr(transparent)]
+pub struct GhostCell<'id, T: ?Sized> {
+    _brand: InvariantLifetime<'id>,
+    value: UnsafeCell<T>
+}
+
+
+// the token decides who may touch the value, so the cell is thread-safe whenever `&T` and
+// `&mut T` may be sent between threads
+unsafe impl<'id, T: ?Sized + Send> Send for GhostCell<'id, T> {}
+unsafe impl<'id, T: ?Sized + Send + Sync> Sync for GhostCell<'id, T> {}
+
+
+impl<'id, T> GhostCell<'id, T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            _brand: PhantomData,
+            value: UnsafeCell::new(value)
+        }
+    }
+
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+
+impl<'id, T: ?Sized> GhostCell<'id, T> {
+    pub fn borrow<'token>(&'token self, _token: &'token GhostToken<'id>) -> &'token T {
+        // a shared token rules out any `&mut T` of this brand for as long as the reference lives
+        unsafe { &*self.value.get() }
+    }
+
+
+    #[allow(clippy::mut_from_ref)]
+    pub fn borrow_mut<'token>(&'token self, _token: &'token mut GhostToken<'id>) -> &'token mut T {
+        // the exclusive token rules out any other reference into a cell of this brand
+        unsafe { &mut *self.value.get() }
+    }
+
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+
+    pub fn from_mut(value: &mut T) -> &GhostCell<'id, T> {
+        unsafe { &*(value as *mut T as *const GhostCell<'id, T>) }
+    }
+
+
+    pub const fn as_ptr(&self) -> *mut T {
+        self.value.get()
+    }
+}
+
+
+impl<T: Default> Default for GhostCell<'_, T> {
+    fn default() -> Self {
+        GhostCell::new(T::default())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::ghost_cell::{GhostCell, GhostToken};
+
+
+    #[test]
+    fn ghost_cell_borrow_and_borrow_mut() {
+        GhostToken::new(|mut token| {
+            let cell = GhostCell::new(vec![1, 2]);
+
+            cell.borrow_mut(&mut token).push(3);
+
+            let first = cell.borrow(&token);
+            let second = cell.borrow(&token);
+            assert_eq!(first, second);
+            assert_eq!(*first, [1, 2, 3]);
+        });
+    }
+
+
+    #[test]
+    fn ghost_cell_shared_graph() {
+        struct Node<'id> {
+            value: GhostCell<'id, u32>
+        }
+
+        let sum = GhostToken::new(|mut token| {
+            let nodes: Vec<Node<'_>> = (1..=3).map(|value| Node {value: GhostCell::new(value)}).collect();
+            // several shared references to the same node, as in a graph with multiple parents
+            let parents = [&nodes[0], &nodes[0], &nodes[2]];
+
+            for parent in parents {
+                *parent.value.borrow_mut(&mut token) *= 10;
+            }
+
+            nodes.iter().map(|node| *node.value.borrow(&token)).sum::<u32>()
+        });
+
+        assert_eq!(sum, 100 + 2 + 30);
+    }
+
+
+    #[test]
+    fn ghost_cell_without_token() {
+        GhostToken::new(|token| {
+            let mut value = 1;
+            let cell = GhostCell::from_mut(&mut value);
+            assert_eq!(*cell.borrow(&token), 1);
+
+            let mut cell = GhostCell::new(String::from("ghost"));
+            cell.get_mut().push_str("cell");
+            assert_eq!(cell.into_inner(), "ghostcell");
+        });
+    }
+
+
+    #[test]
+    fn ghost_cell_shared_between_threads() {
+        GhostToken::new(|mut token| {
+            let cell = GhostCell::new(0u64);
+
+            std::thread::scope(|scope| {
+                scope.spawn(|| *cell.borrow_mut(&mut token) += 1);
+            });
+
+            std::thread::scope(|scope| {
+                for _ in 0..2 {
+                    scope.spawn(|| assert_eq!(*cell.borrow(&token), 1));
+                }
+            });
+        });
+    }
+}
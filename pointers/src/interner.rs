@@ -0,0 +1,117 @@
+/*
+- An `Interner` deduplicates strings: `get_or_intern` hands out a `MyRc<str>` for a string, and
+every later call with an equal string hands out a clone of the very same `MyRc<str>`. Each distinct
+string is stored once, no matter how many handles to it exist.
+
+- Since equal strings share an allocation, two interned strings are equal exactly when they point
+at the same allocation, so `MyRc::ptr_eq` compares them in O(1) instead of comparing their bytes.
+This only holds for strings of the same interner.
+
+- The interner keeps one strong reference to every string it has handed out, so a string stays
+interned while no handle to it exists. `collect_garbage` drops the strings that only the interner
+still refers to and gives the memory of the table back; a string interned again afterwards starts
+out with a new allocation.
+
+- The table is a `HashSet<MyRc<str>>`, which is looked up with a plain `&str`, because `MyRc<str>`
+hashes and compares like the string it points at.
+*/
+use std::collections::HashSet;
+use crate::rc::MyRc;
+
+
+#[derive(Default)]
+pub struct Interner {
+    strings: HashSet<MyRc<str>>
+}
+
+
+impl Interner {
+    pub fn new() -> Self {
+        Interner {strings: HashSet::new()}
+    }
+
+
+    pub fn get_or_intern(&mut self, string: &str) -> MyRc<str> {
+        if let Some(interned) = self.strings.get(string) {
+            return interned.clone();
+        }
+
+        let interned = MyRc::<str>::from(string);
+        self.strings.insert(interned.clone());
+        interned
+    }
+
+
+    pub fn get(&self, string: &str) -> Option<MyRc<str>> {
+        self.strings.get(string).cloned()
+    }
+
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+
+    // drops every string nobody but the interner refers to, and returns how many there were
+    pub fn collect_garbage(&mut self) -> usize {
+        let len = self.strings.len();
+
+        self.strings.retain(|interned| MyRc::strong_count(interned) > 1);
+        self.strings.shrink_to_fit();
+
+        len - self.strings.len()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::interner::Interner;
+    use crate::rc::MyRc;
+
+
+    #[test]
+    fn interner_get_or_intern() {
+        let mut interner = Interner::new();
+
+        let first = interner.get_or_intern("hello");
+        let second = interner.get_or_intern(&String::from("hello"));
+        let other = interner.get_or_intern("world");
+
+        assert!(MyRc::ptr_eq(&first, &second));
+        assert!(!MyRc::ptr_eq(&first, &other));
+        assert_eq!(&*second, "hello");
+        assert_eq!(interner.len(), 2);
+
+        assert!(MyRc::ptr_eq(&interner.get("world").unwrap(), &other));
+        assert!(interner.get("missing").is_none());
+    }
+
+
+    #[test]
+    fn interner_collect_garbage() {
+        let mut interner = Interner::new();
+
+        let alive = interner.get_or_intern("alive");
+        let dead = interner.get_or_intern("dead");
+        drop(dead);
+
+        // the interner itself keeps a string interned
+        assert_eq!(interner.len(), 2);
+        assert!(interner.get("dead").is_some());
+
+        assert_eq!(interner.collect_garbage(), 1);
+        assert_eq!(interner.len(), 1);
+        assert!(interner.get("dead").is_none());
+        assert!(MyRc::ptr_eq(&interner.get_or_intern("alive"), &alive));
+
+        drop(alive);
+        assert_eq!(interner.collect_garbage(), 1);
+        assert!(interner.is_empty());
+    }
+}
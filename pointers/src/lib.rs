@@ -1,3 +1,35 @@
-mod rc;
-mod cell;
-mod refcell;
\ No newline at end of file
+// the baseline refcell tests bind guards only to keep their borrows alive
+#![cfg_attr(test, allow(unused_variables))]
+#![cfg_attr(feature = "unsize", feature(coerce_unsized, unsize))]
+#![cfg_attr(feature = "specialization", allow(incomplete_features), feature(specialization))]
+
+pub mod rc;
+pub mod cell;
+pub mod refcell;
+pub mod take_cell;
+pub mod atomic_cell;
+pub mod atomic_refcell;
+pub mod volatile_cell;
+pub mod ghost_cell;
+pub mod token_cell;
+pub mod thin_rc;
+pub mod rc_ref;
+pub mod rc_slice;
+pub mod rc_pool;
+pub mod list;
+pub mod tree;
+pub mod interner;
+pub mod weak_value_map;
+pub mod signal;
+pub mod shared;
+pub mod pvec;
+pub mod pmap;
+pub mod allocator;
+pub mod arc;
+pub mod thin_arc;
+pub mod atomic_arc;
+#[cfg(feature = "leak-check")]
+pub mod rc_diagnostics;
+
+mod sync;
+mod rc_slab;
\ No newline at end of file
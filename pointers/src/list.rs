@@ -0,0 +1,240 @@
+/*
+- `List<T>` is a persistent (immutable) singly linked list built on `MyRc`. A list is a pointer to
+its first node, and every node owns a reference to the rest of the list, so `cons`, `head` and
+`tail` are all O(1) and never copy an element.
+
+- Lists share structure: `list.cons(x)` returns a new list whose tail is `list` itself, and
+`list.tail()` returns the list that is already stored in the first node. Any number of lists can
+share a common suffix, and a node lives for as long as any list still reaches it.
+
+- Cloning a list only clones the pointer to its first node. Dropping a list releases its nodes
+front to back for as long as no other list shares them, in a loop rather than by recursion, so
+dropping a long list does not overflow the stack.
+
+- `iter` walks the elements front to back, and `FromIterator` builds a list with the elements in
+iteration order.
+*/
+use std::fmt;
+use crate::rc::MyRc;
+
+
+pub struct List<T> {
+    head: Link<T>
+}
+
+
+type Link<T> = Option<MyRc<Node<T>>>;
+
+
+struct Node<T> {
+    value: T,
+    next: Link<T>
+}
+
+
+impl<T> List<T> {
+    pub const fn new() -> Self {
+        List {head: None}
+    }
+
+
+    pub fn cons(&self, value: T) -> Self {
+        List {
+            head: Some(MyRc::new(Node {value, next: self.head.clone()}))
+        }
+    }
+
+
+    pub fn head(&self) -> Option<&T> {
+        self.head.as_ref().map(|node| &node.value)
+    }
+
+
+    pub fn tail(&self) -> Self {
+        List {
+            head: self.head.as_ref().and_then(|node| node.next.clone())
+        }
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {next: self.head.as_deref()}
+    }
+
+
+    // two lists share a suffix from the first node they both point at
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.head, &other.head) {
+            (Some(first), Some(second)) => MyRc::ptr_eq(first, second),
+            (None, None) => true,
+            _ => false
+        }
+    }
+}
+
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        List::new()
+    }
+}
+
+
+impl<T> Clone for List<T> {
+    fn clone(&self) -> Self {
+        List {head: self.head.clone()}
+    }
+}
+
+
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        let mut head = self.head.take();
+
+        // takes every node apart that no other list shares, so dropping it never recurses into
+        // the rest of the list
+        while let Some(node) = head {
+            match MyRc::try_unwrap(node) {
+                Ok(mut node) => head = node.next.take(),
+                Err(_) => break
+            }
+        }
+    }
+}
+
+
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        // the list is built back to front, so the first element ends up at the head
+        let values: Vec<T> = iter.into_iter().collect();
+        values.into_iter().rev().fold(List::new(), |list, value| list.cons(value))
+    }
+}
+
+
+impl<T: fmt::Debug> fmt::Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+
+impl<T: Eq> Eq for List<T> {}
+
+
+pub struct Iter<'list, T> {
+    next: Option<&'list Node<T>>
+}
+
+
+impl<'list, T> Iterator for Iter<'list, T> {
+    type Item = &'list T;
+
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next?;
+        self.next = node.next.as_deref();
+        Some(&node.value)
+    }
+}
+
+
+impl<'list, T> IntoIterator for &'list List<T> {
+    type Item = &'list T;
+    type IntoIter = Iter<'list, T>;
+
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use crate::list::List;
+
+
+    #[test]
+    fn list_cons_head_tail() {
+        let empty = List::new();
+        assert!(empty.is_empty());
+        assert_eq!(empty.head(), None);
+        assert!(empty.tail().is_empty());
+
+        let list = empty.cons(3).cons(2).cons(1);
+        assert_eq!(list.head(), Some(&1));
+        assert_eq!(list.tail().head(), Some(&2));
+        assert_eq!(list.tail().tail().tail(), empty);
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(format!("{:?}", list), "[1, 2, 3]");
+    }
+
+
+    #[test]
+    fn list_structural_sharing() {
+        let shared: List<i32> = [2, 3].into_iter().collect();
+        let first = shared.cons(1);
+        let second = shared.cons(10);
+
+        // both lists continue with the very same nodes
+        assert!(first.tail().ptr_eq(&shared));
+        assert!(second.tail().ptr_eq(&first.tail()));
+        assert!(!first.ptr_eq(&second));
+        assert_eq!((&first).into_iter().sum::<i32>(), 6);
+        assert_eq!((&second).into_iter().sum::<i32>(), 15);
+
+        // the shared nodes outlive every list that was built on top of them
+        drop(shared);
+        drop(first);
+        assert_eq!(second.iter().copied().collect::<Vec<_>>(), [10, 2, 3]);
+    }
+
+
+    #[test]
+    fn list_drop() {
+        struct Tracked<'a>(&'a Cell<usize>);
+
+        impl Drop for Tracked<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let drops = Cell::new(0);
+        let shared = List::new().cons(Tracked(&drops)).cons(Tracked(&drops));
+        let list = shared.cons(Tracked(&drops));
+
+        // only the node that no other list reaches is dropped
+        drop(list);
+        assert_eq!(drops.get(), 1);
+        drop(shared);
+        assert_eq!(drops.get(), 3);
+
+        // deep enough to overflow the stack if dropping recursed into the tail (Miri is too slow
+        // for that, but still checks the drop loop)
+        let len = if cfg!(miri) { 1_000 } else { 100_000 };
+        let long: List<u32> = (0..len).collect();
+        assert_eq!(long.len(), len as usize);
+        drop(long);
+    }
+}
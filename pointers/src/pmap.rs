@@ -0,0 +1,542 @@
+/*
+- `PMap<K, V>` is a persistent hash map, the counterpart of `PVec<T>`: `insert` and `remove` leave
+the map they are called on untouched and return a new one, and all versions share the parts they
+have in common through `MyRc`. Cloning a map is O(1).
+
+- The map is a hash array mapped trie (HAMT). Every node covers five bits of the hash, so it has up
+to 32 slots, but it only stores the occupied ones: a 32-bit bitmap tells which slots are in use,
+and the position of a slot among the stored ones is the number of set bits in front of it. A slot
+holds either an entry or a child node for the entries that share these bits of their hash. Nodes
+are small even when the map is big, and a lookup follows at most one node per five hash bits.
+
+- Entries whose full 64-bit hashes are equal end up in a collision node at the bottom of the
+trie, which is searched linearly. Removing entries pulls a lone entry that is left in a node back
+up into its parent, so the trie has the same shape as if the removed entries had never been
+inserted, and two maps with equal contents are equally deep.
+
+- Like for `PVec<T>`, the `&mut self` methods `insert_mut` and `remove_mut` edit the map in place
+wherever its nodes are not shared with another version (through `MyRc::make_mut`), and the
+persistent methods clone the map first and then edit the copy. An update copies the nodes on the
+path to its entry if they are shared, including the entries stored in them, which is why updates
+require `K: Clone` and `V: Clone`.
+
+- Keys are hashed with a `RandomState` picked when the map is created, which every version of the
+map shares. `PartialEq` compares the entries, not the layout, so it also holds between maps that
+were built independently (and hash differently).
+*/
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::{fmt, mem, slice};
+use crate::rc::MyRc;
+
+
+const BITS: u32 = 5;
+const MASK: u64 = (1 << BITS) - 1;
+
+
+pub struct PMap<K, V> {
+    len: usize,
+    root: MyRc<Node<K, V>>,
+    hasher: RandomState
+}
+
+
+enum Node<K, V> {
+    Branch {bitmap: u32, slots: Vec<Slot<K, V>>},
+    // every entry has the same hash
+    Collision {hash: u64, entries: Vec<(K, V)>}
+}
+
+
+enum Slot<K, V> {
+    Entry {hash: u64, key: K, value: V},
+    Node(MyRc<Node<K, V>>)
+}
+
+
+impl<K, V> PMap<K, V> {
+    pub fn new() -> Self {
+        PMap {
+            len: 0,
+            root: MyRc::new(Node::Branch {bitmap: 0, slots: Vec::new()}),
+            hasher: RandomState::new()
+        }
+    }
+
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let Node::Branch {slots, ..} = &*self.root else {
+            unreachable!("the root is always a branch");
+        };
+
+        Iter {
+            slots: vec![slots.iter()],
+            collision: [].iter(),
+            len: self.len
+        }
+    }
+}
+
+
+impl<K: Eq + Hash, V> PMap<K, V> {
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash
+    {
+        let hash = self.hasher.hash_one(key);
+        let mut node = &*self.root;
+        let mut shift = 0;
+
+        loop {
+            match node {
+                Node::Branch {bitmap, slots} => {
+                    let bit = bit(hash, shift);
+                    if bitmap & bit == 0 {
+                        return None;
+                    }
+
+                    match &slots[position(*bitmap, bit)] {
+                        Slot::Entry {hash: entry_hash, key: entry_key, value} => {
+                            return (*entry_hash == hash && entry_key.borrow() == key).then_some(value);
+                        }
+                        Slot::Node(child) => node = child
+                    }
+                }
+                Node::Collision {entries, ..} => {
+                    return entries.iter().find(|(entry_key, _)| entry_key.borrow() == key).map(|(_, value)| value);
+                }
+            }
+            shift += BITS;
+        }
+    }
+
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash
+    {
+        self.get(key).is_some()
+    }
+}
+
+
+impl<K: Clone + Eq + Hash, V: Clone> PMap<K, V> {
+    pub fn insert(&self, key: K, value: V) -> Self {
+        let mut map = self.clone();
+        map.insert_mut(key, value);
+        map
+    }
+
+
+    // a map without the key; if the key is missing, that is the map itself
+    pub fn remove<Q>(&self, key: &Q) -> Self
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash
+    {
+        let mut map = self.clone();
+        map.remove_mut(key);
+        map
+    }
+
+
+    // returns the value the key mapped to before, if any
+    pub fn insert_mut(&mut self, key: K, value: V) -> Option<V> {
+        let hash = self.hasher.hash_one(&key);
+        let old = insert(MyRc::make_mut(&mut self.root), 0, hash, key, value);
+
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+
+    pub fn remove_mut<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash
+    {
+        // looking first keeps the nodes on the path shared when there is nothing to remove
+        if !self.contains_key(key) {
+            return None;
+        }
+
+        let hash = self.hasher.hash_one(key);
+        let value = remove(MyRc::make_mut(&mut self.root), 0, hash, key);
+        self.len -= 1;
+        value
+    }
+}
+
+
+// the bit of the slot that `hash` falls into at the node `shift` bits down the hash
+fn bit(hash: u64, shift: u32) -> u32 {
+    1 << ((hash >> shift) & MASK)
+}
+
+
+// the index of the slot with `bit` among the occupied slots of `bitmap`
+fn position(bitmap: u32, bit: u32) -> usize {
+    (bitmap & (bit - 1)).count_ones() as usize
+}
+
+
+fn insert<K: Clone + Eq, V: Clone>(node: &mut Node<K, V>, shift: u32, hash: u64, key: K, value: V) -> Option<V> {
+    let (bitmap, slots) = match node {
+        Node::Branch {bitmap, slots} => (bitmap, slots),
+        Node::Collision {entries, ..} => {
+            if let Some((_, old)) = entries.iter_mut().find(|(entry_key, _)| *entry_key == key) {
+                return Some(mem::replace(old, value));
+            }
+            entries.push((key, value));
+            return None;
+        }
+    };
+
+    let bit = bit(hash, shift);
+    let position = position(*bitmap, bit);
+    if *bitmap & bit == 0 {
+        *bitmap |= bit;
+        slots.insert(position, Slot::Entry {hash, key, value});
+        return None;
+    }
+
+    match &mut slots[position] {
+        Slot::Entry {hash: entry_hash, key: entry_key, value: old} if *entry_hash == hash && *entry_key == key => {
+            Some(mem::replace(old, value))
+        }
+        Slot::Entry {..} => {
+            // two entries in one slot move down into a node of their own
+            let Slot::Entry {hash: entry_hash, key: entry_key, value: entry_value} = slots.remove(position) else {
+                unreachable!();
+            };
+            let node = pair(shift + BITS, (entry_hash, entry_key, entry_value), (hash, key, value));
+            slots.insert(position, Slot::Node(MyRc::new(node)));
+            None
+        }
+        Slot::Node(child) => insert(MyRc::make_mut(child), shift + BITS, hash, key, value)
+    }
+}
+
+
+// a node holding two entries whose hashes agree up to `shift`
+fn pair<K, V>(shift: u32, first: (u64, K, V), second: (u64, K, V)) -> Node<K, V> {
+    if shift >= u64::BITS {
+        return Node::Collision {hash: first.0, entries: vec![(first.1, first.2), (second.1, second.2)]};
+    }
+
+    let (first_bit, second_bit) = (bit(first.0, shift), bit(second.0, shift));
+    if first_bit == second_bit {
+        return Node::Branch {
+            bitmap: first_bit,
+            slots: vec![Slot::Node(MyRc::new(pair(shift + BITS, first, second)))]
+        };
+    }
+
+    let first = Slot::Entry {hash: first.0, key: first.1, value: first.2};
+    let second = Slot::Entry {hash: second.0, key: second.1, value: second.2};
+    Node::Branch {
+        bitmap: first_bit | second_bit,
+        slots: if first_bit < second_bit { vec![first, second] } else { vec![second, first] }
+    }
+}
+
+
+// removes a key that is known to be in the trie below `node`
+fn remove<K, V, Q>(node: &mut Node<K, V>, shift: u32, hash: u64, key: &Q) -> Option<V>
+where
+    K: Clone + Borrow<Q>,
+    V: Clone,
+    Q: ?Sized + Eq
+{
+    let (bitmap, slots) = match node {
+        Node::Branch {bitmap, slots} => (bitmap, slots),
+        Node::Collision {entries, ..} => {
+            let index = entries.iter().position(|(entry_key, _)| entry_key.borrow() == key)?;
+            return Some(entries.remove(index).1);
+        }
+    };
+
+    let bit = bit(hash, shift);
+    let position = position(*bitmap, bit);
+    let child = match &mut slots[position] {
+        Slot::Entry {..} => {
+            *bitmap &= !bit;
+            let Slot::Entry {value, ..} = slots.remove(position) else {
+                unreachable!();
+            };
+            return Some(value);
+        }
+        Slot::Node(child) => MyRc::make_mut(child)
+    };
+
+    let value = remove(child, shift + BITS, hash, key);
+
+    // a child that is down to a single entry gives it back to this node
+    if let Some(entry) = take_lone_entry(child) {
+        slots[position] = entry;
+    }
+    value
+}
+
+
+fn take_lone_entry<K, V>(node: &mut Node<K, V>) -> Option<Slot<K, V>> {
+    match node {
+        Node::Branch {slots, ..} if slots.len() == 1 && matches!(slots[0], Slot::Entry {..}) => slots.pop(),
+        Node::Collision {hash, entries} if entries.len() == 1 => {
+            let (key, value) = entries.pop()?;
+            Some(Slot::Entry {hash: *hash, key, value})
+        }
+        _ => None
+    }
+}
+
+
+impl<K: Clone, V: Clone> Clone for Node<K, V> {
+    // a copied node shares its children with the original, only the entries stored in it are cloned
+    fn clone(&self) -> Self {
+        match self {
+            Node::Branch {bitmap, slots} => Node::Branch {bitmap: *bitmap, slots: slots.clone()},
+            Node::Collision {hash, entries} => Node::Collision {hash: *hash, entries: entries.clone()}
+        }
+    }
+}
+
+
+impl<K: Clone, V: Clone> Clone for Slot<K, V> {
+    fn clone(&self) -> Self {
+        match self {
+            Slot::Entry {hash, key, value} => Slot::Entry {hash: *hash, key: key.clone(), value: value.clone()},
+            Slot::Node(node) => Slot::Node(node.clone())
+        }
+    }
+}
+
+
+impl<K, V> Clone for PMap<K, V> {
+    fn clone(&self) -> Self {
+        PMap {
+            len: self.len,
+            root: self.root.clone(),
+            hasher: self.hasher.clone()
+        }
+    }
+}
+
+
+impl<K, V> Default for PMap<K, V> {
+    fn default() -> Self {
+        PMap::new()
+    }
+}
+
+
+impl<K: Clone + Eq + Hash, V: Clone> Extend<(K, V)> for PMap<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert_mut(key, value);
+        }
+    }
+}
+
+
+impl<K: Clone + Eq + Hash, V: Clone> FromIterator<(K, V)> for PMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = PMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for PMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+
+impl<K: Eq + Hash, V: PartialEq> PartialEq for PMap<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        // versions of one map share most of their nodes, which are equal without looking inside
+        if MyRc::ptr_eq(&self.root, &other.root) {
+            return true;
+        }
+
+        self.len == other.len && self.iter().all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+
+impl<K: Eq + Hash, V: Eq> Eq for PMap<K, V> {}
+
+
+pub struct Iter<'map, K, V> {
+    // the slots still to visit, one level of the trie per element
+    slots: Vec<slice::Iter<'map, Slot<K, V>>>,
+    collision: slice::Iter<'map, (K, V)>,
+    len: usize
+}
+
+
+impl<'map, K, V> Iterator for Iter<'map, K, V> {
+    type Item = (&'map K, &'map V);
+
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((key, value)) = self.collision.next() {
+                self.len -= 1;
+                return Some((key, value));
+            }
+
+            let Some(slot) = self.slots.last_mut()?.next() else {
+                self.slots.pop();
+                continue;
+            };
+
+            match slot {
+                Slot::Entry {key, value, ..} => {
+                    self.len -= 1;
+                    return Some((key, value));
+                }
+                Slot::Node(node) => match &**node {
+                    Node::Branch {slots, ..} => self.slots.push(slots.iter()),
+                    Node::Collision {entries, ..} => self.collision = entries.iter()
+                }
+            }
+        }
+    }
+
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+
+impl<'map, K, V> IntoIterator for &'map PMap<K, V> {
+    type Item = (&'map K, &'map V);
+    type IntoIter = Iter<'map, K, V>;
+
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::hash::{Hash, Hasher};
+    use crate::pmap::PMap;
+
+
+    fn len() -> u32 {
+        if cfg!(miri) { 300 } else { 10_000 }
+    }
+
+
+    #[test]
+    fn pmap_insert_get_remove() {
+        let mut map = PMap::new();
+        assert!(map.is_empty());
+
+        for key in 0..len() {
+            assert_eq!(map.insert_mut(key, key * 2), None);
+        }
+        assert_eq!(map.insert_mut(7, 70), Some(14));
+        assert_eq!(map.len(), len() as usize);
+        assert!((0..len()).all(|key| map.get(&key) == Some(&if key == 7 { 70 } else { key * 2 })));
+        assert_eq!(map.get(&len()), None);
+
+        let mut entries: Vec<(u32, u32)> = map.iter().map(|(key, value)| (*key, *value)).collect();
+        entries.sort();
+        assert_eq!(entries.len(), map.iter().len());
+        assert_eq!(entries[8], (8, 16));
+
+        for key in (0..len()).step_by(2) {
+            assert!(map.remove_mut(&key).is_some());
+        }
+        assert_eq!(map.remove_mut(&0), None);
+        assert_eq!(map.len(), len() as usize / 2);
+        assert!((0..len()).all(|key| map.contains_key(&key) == (key % 2 == 1)));
+
+        for key in (1..len()).step_by(2) {
+            map.remove_mut(&key);
+        }
+        assert!(map.is_empty());
+        assert_eq!(map.iter().next(), None);
+    }
+
+
+    #[test]
+    fn pmap_versions_share_structure() {
+        let base: PMap<String, u32> = (0..len()).map(|key| (key.to_string(), key)).collect();
+
+        let inserted = base.insert(String::from("new"), 0);
+        let replaced = base.insert(String::from("5"), 50);
+        let removed = base.remove("5");
+
+        // every version sees its own entries, and the original is left untouched
+        assert_eq!((base.len(), base.get("5"), base.get("new")), (len() as usize, Some(&5), None));
+        assert_eq!((inserted.len(), inserted.get("new")), (len() as usize + 1, Some(&0)));
+        assert_eq!((replaced.len(), replaced.get("5")), (len() as usize, Some(&50)));
+        assert_eq!((removed.len(), removed.get("5"), removed.get("6")), (len() as usize - 1, None, Some(&6)));
+
+        // removing what is not there, or taking back what was added, gives an equal map
+        assert_eq!(base.remove("missing"), base);
+        assert_eq!(inserted.remove("new"), base);
+        assert_ne!(replaced, base);
+        let rebuilt: PMap<String, u32> = base.iter().map(|(key, value)| (key.clone(), *value)).collect();
+        assert_eq!(rebuilt, base);
+    }
+
+
+    #[test]
+    fn pmap_hash_collisions() {
+        // every key has the same hash, so they all end up in one collision node
+        #[derive(Clone, Debug, PartialEq, Eq)]
+        struct Colliding(u8);
+
+        impl Hash for Colliding {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                0.hash(state);
+            }
+        }
+
+        let map: PMap<Colliding, u8> = (0..4).map(|key| (Colliding(key), key)).collect();
+        assert_eq!(map.len(), 4);
+        assert!((0..4).all(|key| map.get(&Colliding(key)) == Some(&key)));
+        assert_eq!(map.insert(Colliding(2), 20).get(&Colliding(2)), Some(&20));
+
+        let smaller = map.remove(&Colliding(0)).remove(&Colliding(3));
+        assert_eq!(smaller.len(), 2);
+        assert_eq!(smaller.get(&Colliding(0)), None);
+        let entries: HashMap<u8, u8> = smaller.iter().map(|(key, value)| (key.0, *value)).collect();
+        assert_eq!(entries, HashMap::from([(1, 1), (2, 2)]));
+
+        // the last entry moves all the way back up to the root
+        let single = smaller.remove(&Colliding(1));
+        assert_eq!(format!("{:?}", single), "{Colliding(2): 2}");
+        assert!(single.remove(&Colliding(2)).is_empty());
+    }
+}
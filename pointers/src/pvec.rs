@@ -0,0 +1,520 @@
+/*
+- `PVec<T>` is a persistent vector: `push`, `update` and `pop` leave the vector they are called on
+untouched and return a new one, and all versions share everything they have in common. Cloning a
+`PVec<T>` is O(1), so old versions are cheap to keep around, e.g. as undo history or as snapshots
+handed to other parts of the program.
+
+- The elements live in a trie of `MyRc` nodes with 32 children each, ordered by index, so an index
+is looked up by taking its bits five at a time from the top. With 32-way branching the trie is
+very flat: a million elements are at most four nodes deep, so `get`, `update` and `push` are
+effectively O(1). The last, not yet full, chunk of up to 32 elements is kept outside of the trie
+as the tail, which makes `push` and `pop` at the end touch nothing but the tail most of the time.
+
+- An update copies only the nodes on the path from the root to the element (at most one node per
+level), while every other node is shared with the previous version through its `MyRc`.
+
+- The `&mut self` methods `push_mut`, `set`, `get_mut` and `pop_mut` edit the vector in place
+wherever its nodes are not shared with another version, using `MyRc::make_mut`: only a shared node
+on the path is copied, and a vector that shares nothing is edited exactly like a `Vec<T>`.
+The persistent methods are built on them, since cloning a vector first is O(1).
+
+- This is the "lite" flavour of an RRB vector: the trie is always perfectly balanced (every leaf
+but the tail holds exactly 32 elements), so indices are found by their bits alone and there is no
+size table per node. The price is that there are no O(log n) `concat` or `split`; `extend`
+appends element by element instead.
+
+- Elements are cloned when a shared leaf has to be copied, so updating requires `T: Clone`, while
+reading does not.
+*/
+use std::{fmt, mem};
+use std::ops::Index;
+use crate::rc::MyRc;
+
+
+const BITS: usize = 5;
+const WIDTH: usize = 1 << BITS;
+const MASK: usize = WIDTH - 1;
+
+
+pub struct PVec<T> {
+    len: usize,
+    // the number of index bits below the root, five per level
+    shift: usize,
+    root: MyRc<Node<T>>,
+    tail: MyRc<Vec<T>>
+}
+
+
+enum Node<T> {
+    Branch(Vec<MyRc<Node<T>>>),
+    Leaf(Vec<T>)
+}
+
+
+impl<T> PVec<T> {
+    pub fn new() -> Self {
+        PVec {
+            len: 0,
+            shift: BITS,
+            root: MyRc::new(Node::Branch(Vec::new())),
+            tail: MyRc::new(Vec::new())
+        }
+    }
+
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len {
+            return None;
+        }
+
+        Some(&self.chunk(index)[index & MASK])
+    }
+
+
+    pub fn first(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+
+    pub fn last(&self) -> Option<&T> {
+        self.tail.last()
+    }
+
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {vec: self, index: 0, chunk: &[]}
+    }
+
+
+    // the elements with the same index bits above the lowest five as `index`, i.e. its leaf
+    fn chunk(&self, index: usize) -> &[T] {
+        if index >= self.tail_offset() {
+            return &self.tail;
+        }
+
+        let mut node = &*self.root;
+        let mut level = self.shift;
+        loop {
+            match node {
+                Node::Branch(children) => node = &children[(index >> level) & MASK],
+                Node::Leaf(values) => return values
+            }
+            level -= BITS;
+        }
+    }
+
+
+    // the index of the first element in the tail; every element in front of it is in the trie
+    fn tail_offset(&self) -> usize {
+        if self.len < WIDTH {
+            0
+        } else {
+            ((self.len - 1) >> BITS) << BITS
+        }
+    }
+}
+
+
+impl<T: Clone> PVec<T> {
+    pub fn push(&self, value: T) -> Self {
+        let mut vec = self.clone();
+        vec.push_mut(value);
+        vec
+    }
+
+
+    // `None` if the index is out of bounds
+    pub fn update(&self, index: usize, value: T) -> Option<Self> {
+        if index >= self.len {
+            return None;
+        }
+
+        let mut vec = self.clone();
+        vec.set(index, value);
+        Some(vec)
+    }
+
+
+    // the vector without its last element, together with that element
+    pub fn pop(&self) -> Option<(Self, T)> {
+        let mut vec = self.clone();
+        let value = vec.pop_mut()?;
+        Some((vec, value))
+    }
+
+
+    pub fn push_mut(&mut self, value: T) {
+        if self.tail.len() < WIDTH {
+            MyRc::make_mut(&mut self.tail).push(value);
+            self.len += 1;
+            return;
+        }
+
+        // the tail is full, so it moves into the trie as a new leaf and a fresh tail starts
+        let mut tail = Vec::with_capacity(WIDTH);
+        tail.push(value);
+        let leaf = Node::Leaf(MyRc::unwrap_or_clone(mem::replace(&mut self.tail, MyRc::new(tail))));
+
+        if (self.len >> BITS) > (1 << self.shift) {
+            // the trie is full, so it grows by a level; the old root becomes the first child
+            let path = new_path(self.shift, leaf);
+            let root = mem::replace(&mut self.root, MyRc::new(Node::Branch(Vec::new())));
+            self.root = MyRc::new(Node::Branch(vec![root, MyRc::new(path)]));
+            self.shift += BITS;
+        } else {
+            push_leaf(MyRc::make_mut(&mut self.root), self.shift, self.len - 1, leaf);
+        }
+
+        self.len += 1;
+    }
+
+
+    #[track_caller]
+    pub fn set(&mut self, index: usize, value: T) {
+        let len = self.len;
+        match self.get_mut(index) {
+            Some(slot) => *slot = value,
+            None => panic!("index out of bounds: the len is {} but the index is {}", len, index)
+        }
+    }
+
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.len {
+            return None;
+        }
+
+        if index >= self.tail_offset() {
+            return Some(&mut MyRc::make_mut(&mut self.tail)[index & MASK]);
+        }
+
+        // every node on the path is made unique, copying only the ones that are shared
+        let mut node = MyRc::make_mut(&mut self.root);
+        let mut level = self.shift;
+        loop {
+            match node {
+                Node::Branch(children) => node = MyRc::make_mut(&mut children[(index >> level) & MASK]),
+                Node::Leaf(values) => return Some(&mut values[index & MASK])
+            }
+            level -= BITS;
+        }
+    }
+
+
+    pub fn pop_mut(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let value = MyRc::make_mut(&mut self.tail).pop();
+        self.len -= 1;
+
+        // an empty tail is refilled with the last leaf of the trie
+        if self.tail.is_empty() && self.len > 0 {
+            let leaf = pop_leaf(MyRc::make_mut(&mut self.root), self.shift, self.len - 1);
+            self.tail = MyRc::new(leaf);
+
+            // a root with a single child is replaced by that child
+            if self.shift > BITS {
+                if let Node::Branch(children) = &*self.root {
+                    if children.len() == 1 {
+                        self.root = children[0].clone();
+                        self.shift -= BITS;
+                    }
+                }
+            }
+        }
+
+        value
+    }
+}
+
+
+// wraps `node` into a chain of branches so that it ends up `level` bits below the chain's top
+fn new_path<T>(level: usize, node: Node<T>) -> Node<T> {
+    if level == 0 {
+        node
+    } else {
+        Node::Branch(vec![MyRc::new(new_path(level - BITS, node))])
+    }
+}
+
+
+// appends a full leaf holding the elements up to `last` to the trie below `branch`
+fn push_leaf<T: Clone>(branch: &mut Node<T>, level: usize, last: usize, leaf: Node<T>) {
+    let Node::Branch(children) = branch else {
+        unreachable!("leaves are only found at level zero");
+    };
+
+    let index = (last >> level) & MASK;
+    if level == BITS {
+        children.push(MyRc::new(leaf));
+    } else if index < children.len() {
+        push_leaf(MyRc::make_mut(&mut children[index]), level - BITS, last, leaf);
+    } else {
+        children.push(MyRc::new(new_path(level - BITS, leaf)));
+    }
+}
+
+
+// removes the last leaf, the one holding `last`, from the trie below `branch`, together with the
+// branches it leaves empty
+fn pop_leaf<T: Clone>(branch: &mut Node<T>, level: usize, last: usize) -> Vec<T> {
+    let Node::Branch(children) = branch else {
+        unreachable!("leaves are only found at level zero");
+    };
+
+    if level == BITS {
+        let leaf = children.pop().expect("the trie holds every element in front of the tail");
+        return match MyRc::unwrap_or_clone(leaf) {
+            Node::Leaf(values) => values,
+            Node::Branch(_) => unreachable!("branches are only found above level zero")
+        };
+    }
+
+    let index = (last >> level) & MASK;
+    let child = MyRc::make_mut(&mut children[index]);
+    let leaf = pop_leaf(child, level - BITS, last);
+    if matches!(child, Node::Branch(grandchildren) if grandchildren.is_empty()) {
+        children.pop();
+    }
+    leaf
+}
+
+
+impl<T: Clone> Clone for Node<T> {
+    // a copied branch shares all of its children with the original
+    fn clone(&self) -> Self {
+        match self {
+            Node::Branch(children) => Node::Branch(children.clone()),
+            Node::Leaf(values) => Node::Leaf(values.clone())
+        }
+    }
+}
+
+
+impl<T> Clone for PVec<T> {
+    fn clone(&self) -> Self {
+        PVec {
+            len: self.len,
+            shift: self.shift,
+            root: self.root.clone(),
+            tail: self.tail.clone()
+        }
+    }
+}
+
+
+impl<T> Default for PVec<T> {
+    fn default() -> Self {
+        PVec::new()
+    }
+}
+
+
+impl<T> Index<usize> for PVec<T> {
+    type Output = T;
+
+
+    #[track_caller]
+    fn index(&self, index: usize) -> &T {
+        match self.get(index) {
+            Some(value) => value,
+            None => panic!("index out of bounds: the len is {} but the index is {}", self.len, index)
+        }
+    }
+}
+
+
+impl<T: Clone> Extend<T> for PVec<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push_mut(value);
+        }
+    }
+}
+
+
+impl<T: Clone> FromIterator<T> for PVec<T> {
+    // a vector that is being built shares nothing, so every push edits it in place
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut vec = PVec::new();
+        vec.extend(iter);
+        vec
+    }
+}
+
+
+impl<T: fmt::Debug> fmt::Debug for PVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+
+impl<T: PartialEq> PartialEq for PVec<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+
+impl<T: Eq> Eq for PVec<T> {}
+
+
+pub struct Iter<'vec, T> {
+    vec: &'vec PVec<T>,
+    index: usize,
+    // the rest of the current leaf
+    chunk: &'vec [T]
+}
+
+
+impl<'vec, T> Iterator for Iter<'vec, T> {
+    type Item = &'vec T;
+
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.chunk.is_empty() {
+            if self.index >= self.vec.len {
+                return None;
+            }
+            self.chunk = self.vec.chunk(self.index);
+        }
+
+        let (value, rest) = self.chunk.split_first()?;
+        self.chunk = rest;
+        self.index += 1;
+        Some(value)
+    }
+
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.vec.len - self.index;
+        (len, Some(len))
+    }
+}
+
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+
+impl<'vec, T> IntoIterator for &'vec PVec<T> {
+    type Item = &'vec T;
+    type IntoIter = Iter<'vec, T>;
+
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use crate::pvec::PVec;
+
+
+    // enough elements for a trie three levels deep (Miri stops at two)
+    fn len() -> usize {
+        if cfg!(miri) { 1_100 } else { 40_000 }
+    }
+
+
+    #[test]
+    fn pvec_push_get_pop() {
+        let mut vec = PVec::new();
+        assert!(vec.is_empty());
+        assert_eq!(vec.get(0), None);
+
+        for value in 0..len() {
+            vec.push_mut(value);
+        }
+        assert_eq!(vec.len(), len());
+        assert!((0..len()).all(|index| vec[index] == index));
+        assert!(vec.iter().copied().eq(0..len()));
+        assert_eq!(vec.iter().len(), len());
+        assert_eq!((vec.first(), vec.last()), (Some(&0), Some(&(len() - 1))));
+        assert_eq!(vec.get(len()), None);
+
+        // popping walks back through every leaf and level the pushes created
+        for value in (0..len()).rev() {
+            assert_eq!(vec.pop_mut(), Some(value));
+            assert_eq!(vec.len(), value);
+        }
+        assert_eq!(vec.pop_mut(), None);
+        assert_eq!(vec, PVec::new());
+
+        let vec: PVec<u8> = (0..3).collect();
+        assert_eq!(format!("{:?}", vec), "[0, 1, 2]");
+    }
+
+
+    #[test]
+    fn pvec_versions_share_structure() {
+        let base: PVec<usize> = (0..len()).collect();
+
+        let updated = base.update(7, 700).unwrap();
+        let pushed = base.push(len());
+        let (popped, last) = base.pop().unwrap();
+        assert!(base.update(len(), 0).is_none());
+
+        // every version sees its own elements, and the original is left untouched
+        assert!(base.iter().copied().eq(0..len()));
+        assert_eq!((updated[7], updated[8], updated.len()), (700, 8, len()));
+        assert_eq!((pushed[len()], pushed.len()), (len(), len() + 1));
+        assert_eq!((last, popped.len()), (len() - 1, len() - 1));
+        assert!(popped.iter().copied().eq(0..len() - 1));
+    }
+
+
+    #[test]
+    fn pvec_edits_unique_nodes_in_place() {
+        struct Tracked<'a>(usize, &'a Cell<usize>);
+
+        impl Clone for Tracked<'_> {
+            fn clone(&self) -> Self {
+                self.1.set(self.1.get() + 1);
+                Tracked(self.0, self.1)
+            }
+        }
+
+        let clones = Cell::new(0);
+        let mut vec: PVec<Tracked> = (0..1_000).map(|value| Tracked(value, &clones)).collect();
+        assert_eq!(clones.get(), 0);
+
+        // nothing is shared, so nothing is copied
+        vec.set(5, Tracked(50, &clones));
+        vec.get_mut(999).unwrap().0 = 9_990;
+        vec.push_mut(Tracked(1_000, &clones));
+        vec.pop_mut();
+        assert_eq!(clones.get(), 0);
+
+        // with a snapshot around, only the leaf on the path is copied, once
+        let snapshot = vec.clone();
+        vec.set(6, Tracked(60, &clones));
+        vec.set(7, Tracked(70, &clones));
+        assert_eq!(clones.get(), 32);
+        assert_eq!((snapshot[6].0, vec[6].0, vec[5].0, vec[999].0), (6, 60, 50, 9_990));
+    }
+
+
+    #[test]
+    #[should_panic(expected = "index out of bounds: the len is 3 but the index is 3")]
+    fn pvec_set_out_of_bounds_panics() {
+        let mut vec: PVec<u8> = (0..3).collect();
+
+        vec.set(3, 0);
+    }
+}
@@ -16,99 +16,2156 @@ decrements the reference count, potentially deallocating the value if no more re
 - `Rc<T>` uses the `clone()` method to create additional references to the value. Each cloned
 `Rc<T>` increments the reference count, and the value is only deallocated when
 all references are dropped.
+
+- A zero-sized `T` leaves nothing but the counts in the allocation, so `Rc::new(())` takes them
+from a slab of count blocks (`rc_slab`) instead of allocating. A block given back on drop is the
+next one handed out, so creating and dropping `Rc<()>`s, as liveness tokens do, never reaches the
+allocator once the thread has a chunk of blocks. A shared sentinel would be cheaper still, but it
+would make all `Rc<()>`s equal under `ptr_eq` and let them share their counts, and a `Weak<()>` of
+a dropped `Rc<()>` would upgrade again as soon as another one is created; every value keeps counts
+of its own instead. The slab is used whatever the allocator, so an `Rc<(), A>` never calls `A`;
+only zero-sized types aligned beyond a `usize` do not fit a block and are allocated as before.
+
+- A count that would overflow aborts the process, like in std. This can only happen after
+references were leaked with `mem::forget` in a loop, and letting the count wrap around would free
+the value while it is still referenced.
+
+- `Rc::new_cyclic` builds a value that holds a weak reference to its own allocation, e.g. a node
+that knows its own handle. The weak reference can be cloned and stored while the value is being
+constructed, but only upgrades once construction has finished.
+
+- `Rc<T>` also accepts unsized values. An `Rc<str>` is built from a `&str` or `String` by copying
+the bytes into a single allocation right after the counts (a `Box<str>` works too), so shared
+identifiers cost one allocation and a fat pointer instead of an `Rc<String>` with its extra
+indirection. Methods that move the value in or out stay restricted to sized types.
+
+- An `Rc<[T]>` is built from a `Vec<T>`, a `Box<[T]>`, a `&[T]` or any iterator, again with the
+elements stored inline after the counts. Iterators with an exact size hint are written straight into
+the final allocation; other iterators are collected into a `Vec<T>` first. Elements that were
+already written are dropped if cloning or iterating panics.
+
+- A `Box<T>` is converted into an `Rc<T>` by moving the value from the box into a new allocation,
+since the box has no room for the counts. The value is copied from heap to heap, so even a large
+value never passes through the stack, and the box is freed without dropping it.
+
+- Trait objects are supported too, e.g. `Rc<dyn Trait>` for a list of heterogeneous plugins. On
+stable Rust, the `coerce_rc!` macro converts an `Rc<T>` into an `Rc<U>` for any unsized `U` that
+`T` coerces to, reusing the allocation. With the nightly-only `unsize` feature, `Rc<T>` and
+`Weak<T>` implement `CoerceUnsized`, so the conversion happens implicitly like for `Rc` in std.
+
+- An `Rc<dyn Any>` can be turned back into an `Rc<T>` of its concrete type with `downcast`, which
+hands the `Rc<dyn Any>` back if the value has a different type. The value is neither cloned nor
+moved, so a type-erased registry can still hand out shared handles to its entries.
+
+- The pointer arithmetic follows strict provenance: the way from a value pointer (`into_raw`,
+`unsize`) back to its counts is an offset within the same allocation, and the only pointer made
+from a plain address is the dangling `Weak<T>` sentinel, which is never dereferenced. The unsafe
+paths pass Miri with `-Zmiri-strict-provenance`.
+
+- With the `leak-check` feature enabled, every allocation is recorded in `rc_diagnostics` together
+with its value type and the place it was created at, until it is freed. That makes reference cycles,
+which `Rc<T>` never frees on its own, show up in a report or fail a test.
+
+- `Rc::new_uninit` and `Rc::new_zeroed` allocate room for a value that is filled in afterwards
+(through `get_mut` while the `Rc<MaybeUninit<T>>` is still unique), and `new_uninit_slice` and
+`new_zeroed_slice` do the same for slices. The allocation is made directly on the heap, so large
+buffers never pass through the stack. `assume_init` then turns the result into an `Rc<T>`.
+
+- `UniqueRc<T>` is an `Rc<T>` under construction. It is the only owner of its value and hands out
+`&mut T`, but `UniqueRc::downgrade` already creates weak references to it, which do not upgrade
+until `UniqueRc::into_rc` turns it into an ordinary `Rc<T>`. Unlike `Rc::new_cyclic`, the value
+can be changed after the weak references were taken, e.g. to add children that point back at
+their parent.
+
+- `Rc::pin` allocates a value that is pinned for as long as it lives: `Pin<Rc<T>>` hands out shared
+references only, and everything that could move the value out or mutate it needs an unpinned
+`Rc<T>`. `Rc<T>` itself is always `Unpin`, since moving it never moves the value.
+
+- `Rc<T, A>` and `Weak<T, A>` take an allocator parameter that defaults to `Global`, so an arena
+or a pool can back reference-counted values. `Rc::new_in` allocates from the given allocator, and
+every `Rc` and `Weak` carries its own clone of it (nothing at all for `Global` or a reference), so
+whichever reference happens to be the last one gives the memory back to the right allocator.
+Constructors without an allocator argument always use `Global`.
+
+- `Rc::try_new` and `Rc::try_new_in` report a failed allocation as an `AllocError` instead of
+aborting the process, so memory-constrained callers can back off or free something and retry. The
+value is dropped if the allocation fails.
+
+- `Rc::strong_count` and `Rc::weak_count` report the current counts. They are associated functions
+rather than methods, so they never shadow a method of the same name on `T`.
+
+- `Rc::make_mut` is clone-on-write: it hands out a `&mut T` right away while the `Rc<T>` is the
+only strong reference, and otherwise clones the value into a new allocation first. Weak references
+to a value that is no longer shared are disassociated instead of cloning, and stop upgrading.
+
+- `Rc::ptr_eq` compares two `Rc<T>`s by identity rather than by value, which is the cheap check
+behind caches and interners, and `Rc::as_ptr` exposes the address of the shared value.
+
+- `Rc::get_mut` hands out a `&mut T` while the `Rc<T>` is the only reference to the value, strong
+or weak, so a value can be built up in place before it is shared.
+
+- `Rc::try_unwrap` moves the value out of the last strong reference and hands the `Rc<T>` back
+otherwise. `Rc::into_inner` always consumes the `Rc<T>` and returns the value only if it was the
+last strong reference, so when several owners race to drop their references, exactly one of them
+gets the value. Weak references left behind just stop upgrading.
+
+- `Rc::unwrap_or_clone` takes ownership of possibly shared data: the value is moved out of the last
+strong reference and cloned otherwise.
+
+- `Weak<T>` is a non-owning handle created with `Rc::downgrade`. It does not keep the value alive,
+so it breaks the reference cycles that would otherwise leak, e.g. a child pointing back at its
+parent. `upgrade` turns it back into an `Rc<T>` as long as some `Rc<T>` still owns the value.
+
+- The allocation keeps two counts. The value is dropped as soon as the strong count reaches zero,
+but the allocation itself is only freed once the weak count reaches zero too, so a `Weak<T>` can
+always check whether the value is still alive. All strong references together hold one implicit
+weak reference, which is released after the value has been dropped.
+
+- `Weak::new()` creates a weak reference without any allocation behind it, which never upgrades.
+It is a cheap placeholder for a back pointer that is filled in later (such as the parent of a root).
+
+- `Rc::into_raw` and `Rc::from_raw` turn a strong reference into a pointer to the value and back,
+e.g. to pass it as the user data of a C callback. The pointer does not point at the allocation but
+at the value after the counts, so `from_raw` steps back over them. While a pointer is passed
+around, `Rc::increment_strong_count` and `Rc::decrement_strong_count` adjust the count it
+represents without turning it back into an `Rc<T>`.
+
+- `Weak::into_raw` and `Weak::from_raw` turn a weak reference into a plain pointer to the value and
+back without touching the counts, so weak handles can cross an FFI boundary or be stored in
+intrusive data structures. The pointer must not be dereferenced unless the value is known to be
+alive.
+
+- `Default`, `Debug`, `Display`, `Hash`, `PartialEq`, `Eq`, `PartialOrd`, `Ord`, `Borrow<T>` and
+`AsRef<T>` forward to the shared value, so an `Rc<T>` behaves like the `T` it points at, e.g. an
+`Rc<String>` key in a `HashMap` can be looked up with a `&String`.
+
+- With the nightly-only `specialization` feature, two `Rc<T>`s of the same allocation are equal
+without comparing the values when `T` is `Eq`, like in std, which turns comparisons of interned
+data into a pointer comparison. The short-circuit exists only under that feature, because telling
+`Eq` types apart needs specialization: stable builds have no `ptr_eq` fast path at all, and their
+`PartialEq` compares the values every time. Types that are only `PartialEq` always compare the
+values, so an `Rc<f64>` holding NaN is never equal to itself.
+
+- With the `serde` feature enabled, an `Rc<T>` serializes as the value it points at. Sharing is
+not preserved: every clone is written out in full, and deserializing always allocates a new `Rc<T>`
+with a reference count of one.
 */
+use std::{fmt, mem, process, ptr};
+use std::any::Any;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::alloc::{self, Layout};
 use std::cell::Cell;
 use std::marker::PhantomData;
-use std::ops::Deref;
+use std::mem::{ManuallyDrop, MaybeUninit};
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
 use std::ptr::NonNull;
+use crate::allocator::{AllocError, Allocator, Global};
+use crate::rc_slab;
+
+
+// `repr(C)` fixes the field order independently of `T`, so an `RcInner<MaybeUninit<T>>` can be
+// initialized in place and then used as an `RcInner<T>`
+#[repr(C)]
+struct RcInner<T: ?Sized> {
+    strong: Cell<usize>,
+    // counts the `MyWeak`s, plus one for all `MyRc`s together while the value is alive
+    weak: Cell<usize>,
+    // dropped by hand when the strong count reaches zero, which can happen long before the
+    // allocation is freed
+    value: ManuallyDrop<T>
+}
 
 
-struct RcInner<T> {
-    value: T,
-    ref_count: Cell<usize>
+pub struct MyRc<T: ?Sized, A: Allocator = Global> {
+    inner: NonNull<RcInner<T>>,
+    alloc: A,
+    _marker: PhantomData<RcInner<T>>
+}
+
+
+// a `MyRc` that is not shared yet: the strong count stays zero until `into_rc`, so weak
+// references created in the meantime cannot upgrade
+pub struct MyUniqueRc<T: ?Sized> {
+    inner: NonNull<RcInner<T>>,
+    _marker: PhantomData<RcInner<T>>
 }
 
 
-pub struct MyRc<T> {
+pub struct MyWeak<T: ?Sized, A: Allocator = Global> {
+    // `DANGLING` for a weak reference created with `MyWeak::new`, which has no allocation
     inner: NonNull<RcInner<T>>,
+    alloc: A,
     _marker: PhantomData<RcInner<T>>
 }
 
 
+// the counts of an allocation, borrowed separately from the value
+struct Counts<'inner> {
+    strong: &'inner Cell<usize>,
+    weak: &'inner Cell<usize>
+}
+
+
+// no allocation can end at the very last address, so this never collides with a real `RcInner`
+const DANGLING: usize = usize::MAX;
+
+
 impl<T> MyRc<T> {
+    #[cfg_attr(feature = "leak-check", track_caller)]
     pub fn new(value: T) -> Self {
-        let inner = Box::new(RcInner {value, ref_count: Cell::new(1)});
+        Self::new_in(value, Global)
+    }
+
+
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn try_new(value: T) -> Result<Self, AllocError> {
+        Self::try_new_in(value, Global)
+    }
+
+
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn new_uninit() -> MyRc<MaybeUninit<T>> {
+        Self::allocate_uninit(false)
+    }
+
+
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn new_zeroed() -> MyRc<MaybeUninit<T>> {
+        Self::allocate_uninit(true)
+    }
+
+
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn new_uninit_slice(len: usize) -> MyRc<[MaybeUninit<T>]> {
+        MyRc {
+            inner: unsafe { MyRc::<[MaybeUninit<T>]>::allocate_for_slice(len, false) },
+            alloc: Global,
+            _marker: PhantomData
+        }
+    }
+
+
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn new_zeroed_slice(len: usize) -> MyRc<[MaybeUninit<T>]> {
+        MyRc {
+            inner: unsafe { MyRc::<[MaybeUninit<T>]>::allocate_for_slice(len, true) },
+            alloc: Global,
+            _marker: PhantomData
+        }
+    }
+
+
+    // allocates the `RcInner` directly on the heap, so even a large value never passes through
+    // the stack
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    fn allocate_uninit(zeroed: bool) -> MyRc<MaybeUninit<T>> {
+        let inner = allocate(Layout::new::<RcInner<MaybeUninit<T>>>(), zeroed).cast();
+        unsafe { init_counts(inner.as_ptr()) };
+        track(inner);
+
+        MyRc {
+            inner,
+            alloc: Global,
+            _marker: PhantomData
+        }
+    }
+
+
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn pin(value: T) -> Pin<MyRc<T>> {
+        // the value never moves out of its allocation while it is shared, and every way of moving
+        // it out or mutating it (`try_unwrap`, `get_mut`, `make_mut`, ...) needs an unpinned `MyRc`
+        unsafe { Pin::new_unchecked(MyRc::new(value)) }
+    }
+
+
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn new_cyclic(f: impl FnOnce(&MyWeak<T>) -> T) -> Self {
+        // the allocation starts out without a value, so the weak reference handed to `f` cannot
+        // upgrade yet
+        let inner = allocate(Layout::new::<RcInner<T>>(), false).cast::<RcInner<T>>();
+        unsafe {
+            (&raw mut (*inner.as_ptr()).strong).write(Cell::new(0));
+            (&raw mut (*inner.as_ptr()).weak).write(Cell::new(1));
+        }
+        track(inner);
+
+        // if `f` panics, dropping the weak reference frees the allocation without touching the
+        // missing value
+        let weak = MyWeak {inner, alloc: Global, _marker: PhantomData};
+        let value = f(&weak);
+
+        unsafe {
+            (&raw mut (*inner.as_ptr()).value).write(ManuallyDrop::new(value));
+            (*inner.as_ptr()).strong.set(1);
+        }
+
+        // the weak reference used during construction becomes the implicit weak reference of the
+        // strong ones
+        mem::forget(weak);
+
         MyRc {
-            inner: unsafe { NonNull::new_unchecked(Box::into_raw(inner)) },
-            _marker: PhantomData,
+            inner,
+            alloc: Global,
+            _marker: PhantomData
         }
     }
+
+
+    pub fn into_raw(this: Self) -> *const T {
+        Self::into_raw_with_allocator(this).0
+    }
+
+
+    /// # Safety
+    ///
+    /// `ptr` must come from `MyRc::into_raw` for the same `T`, and each pointer may be turned back
+    /// into a `MyRc<T>` only once for every strong reference it represents.
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        Self::from_raw_in(ptr, Global)
+    }
+
+
+    /// # Safety
+    ///
+    /// `ptr` must come from `MyRc::into_raw` for the same `T`, and the value must still be alive.
+    pub unsafe fn increment_strong_count(ptr: *const T) {
+        let this = ManuallyDrop::new(Self::from_raw(ptr));
+        mem::forget(MyRc::clone(&this));
+    }
+
+
+    /// # Safety
+    ///
+    /// `ptr` must come from `MyRc::into_raw` for the same `T`, and it must represent a strong
+    /// reference that has not been given up yet, since this gives it up.
+    pub unsafe fn decrement_strong_count(ptr: *const T) {
+        drop(Self::from_raw(ptr));
+    }
 }
 
 
-impl<T> Clone for MyRc<T> {
-    fn clone(&self) -> Self {
-        let inner = unsafe { self.inner.as_ref() };
-        inner.ref_count.set(inner.ref_count.get() + 1);
+impl<T, A: Allocator> MyRc<T, A> {
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn new_in(value: T, alloc: A) -> Self {
+        match Self::try_new_in(value, alloc) {
+            Ok(my_rc) => my_rc,
+            Err(_) => alloc::handle_alloc_error(Layout::new::<RcInner<T>>())
+        }
+    }
+
+
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn try_new_in(value: T, alloc: A) -> Result<Self, AllocError> {
+        let layout = Layout::new::<RcInner<T>>();
+        let inner = if layout == rc_slab::BLOCK {
+            rc_slab::allocate()?.cast::<RcInner<T>>()
+        } else {
+            alloc.allocate(layout)?.cast::<RcInner<T>>()
+        };
+
+        unsafe {
+            inner.write(RcInner {
+                strong: Cell::new(1),
+                weak: Cell::new(1),
+                value: ManuallyDrop::new(value)
+            });
+        }
+        track(inner);
+
+        Ok(MyRc {
+            inner,
+            alloc,
+            _marker: PhantomData
+        })
+    }
 
+
+    /// # Safety
+    ///
+    /// `coerce` must return the pointer it is given, turned into a pointer to an unsized type by
+    /// an unsizing coercion (e.g. `*const T` to `*const dyn Trait`). The `coerce_rc!` macro calls
+    /// this function with exactly such a closure and is the safe way to use it.
+    pub unsafe fn unsize<U: ?Sized>(this: Self, coerce: impl FnOnce(*const T) -> *const U) -> MyRc<U, A> {
+        // `as_ptr` keeps the provenance of the whole allocation, so the counts in front of the
+        // value stay reachable from the coerced pointer
+        let value = coerce(MyRc::as_ptr(&this));
+        let (_, alloc) = MyRc::into_parts(this);
+
+        // an unsizing coercion keeps the address and the alignment, so the counts sit at the same
+        // offset in front of the value for `T` and `U`
         MyRc {
-            inner: self.inner,
+            inner: inner_from_value(value, mem::offset_of!(RcInner<T>, value)),
+            alloc,
+            _marker: PhantomData
+        }
+    }
+
+
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn make_mut(this: &mut Self) -> &mut T
+    where
+        T: Clone,
+        A: Clone
+    {
+        if Self::strong_count(this) != 1 {
+            // other owners keep the current value, this one continues with its own copy
+            *this = MyRc::new_in((**this).clone(), this.alloc.clone());
+        } else if Self::weak_count(this) != 0 {
+            // only weak references are left: the value moves to a new allocation, and the weak
+            // references stay behind with the old one, which no longer upgrades
+            let value = unsafe { ptr::read(&**this) };
+            let old = mem::replace(this, MyRc::new_in(value, this.alloc.clone()));
+
+            old.inner().strong.set(0);
+            let (inner, alloc) = MyRc::into_parts(old);
+            drop(MyWeak {inner, alloc, _marker: PhantomData});
+        }
+
+        unsafe { &mut (*this.inner.as_ptr()).value }
+    }
+
+
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        if Self::strong_count(&this) != 1 {
+            return Err(this);
+        }
+
+        Ok(Self::into_inner(this).unwrap())
+    }
+
+
+    pub fn into_inner(this: Self) -> Option<T> {
+        // the strong reference is given up by hand below, so `Drop` must not run
+        let (inner, alloc) = MyRc::into_parts(this);
+
+        let strong = unsafe { (*inner.as_ptr()).strong.get() } - 1;
+        unsafe { (*inner.as_ptr()).strong.set(strong) };
+
+        if strong != 0 {
+            return None;
+        }
+
+        // the last strong reference moves the value out instead of dropping it; weak references
+        // can no longer upgrade, since the strong count is already zero
+        let value = unsafe { ManuallyDrop::take(&mut (*inner.as_ptr()).value) };
+        drop(MyWeak {inner, alloc, _marker: PhantomData});
+
+        Some(value)
+    }
+
+
+    pub fn unwrap_or_clone(this: Self) -> T
+    where
+        T: Clone
+    {
+        Self::try_unwrap(this).unwrap_or_else(|this| (*this).clone())
+    }
+
+
+    pub fn into_raw_with_allocator(this: Self) -> (*const T, A) {
+        let ptr = Self::as_ptr(&this);
+        let (_, alloc) = MyRc::into_parts(this);
+        (ptr, alloc)
+    }
+
+
+    /// # Safety
+    ///
+    /// `ptr` must come from `MyRc::into_raw_with_allocator` for the same `T`, and `alloc` must be
+    /// the allocator it was handed out with (or a clone of that allocator).
+    pub unsafe fn from_raw_in(ptr: *const T, alloc: A) -> Self {
+        MyRc {
+            inner: inner_from_value(ptr, mem::offset_of!(RcInner<T>, value)),
+            alloc,
             _marker: PhantomData
         }
     }
 }
 
 
-impl<T> Deref for MyRc<T> {
-    type Target = T;
+impl<T: ?Sized, A: Allocator> MyRc<T, A> {
+    pub fn downgrade(this: &Self) -> MyWeak<T, A>
+    where
+        A: Clone
+    {
+        let inner = this.inner();
+        increment(&inner.weak);
+
+        MyWeak {
+            inner: this.inner,
+            alloc: this.alloc.clone(),
+            _marker: PhantomData
+        }
+    }
 
 
-    fn deref(&self) -> &Self::Target {
-        &unsafe { self.inner.as_ref() }.value
+    pub fn allocator(this: &Self) -> &A {
+        &this.alloc
+    }
+
+
+    pub fn strong_count(this: &Self) -> usize {
+        this.inner().strong.get()
+    }
+
+
+    pub fn weak_count(this: &Self) -> usize {
+        // leaves out the implicit weak reference held by the strong references
+        this.inner().weak.get() - 1
+    }
+
+
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        // compares addresses only, so the metadata of unsized values does not matter
+        ptr::addr_eq(this.inner.as_ptr(), other.inner.as_ptr())
+    }
+
+
+    pub fn as_ptr(this: &Self) -> *const T {
+        // projects through the raw pointer, so the result keeps the provenance of the allocation
+        unsafe { &raw const (*this.inner.as_ptr()).value as *const T }
+    }
+
+
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if Self::is_unique(this) {
+            // no other `MyRc` can read the value and no `MyWeak` can upgrade to one
+            Some(unsafe { &mut (*this.inner.as_ptr()).value })
+        } else {
+            None
+        }
+    }
+
+
+    fn is_unique(this: &Self) -> bool {
+        Self::strong_count(this) == 1 && Self::weak_count(this) == 0
+    }
+
+
+    fn inner(&self) -> &RcInner<T> {
+        unsafe { self.inner.as_ref() }
+    }
+
+
+    // takes `this` apart without touching the counts, handing its strong reference to the caller
+    fn into_parts(this: Self) -> (NonNull<RcInner<T>>, A) {
+        let this = ManuallyDrop::new(this);
+        (this.inner, unsafe { ptr::read(&this.alloc) })
     }
 }
 
 
-impl<T> Drop for MyRc<T> {
-    fn drop(&mut self) {
-        let ref_count = unsafe { self.inner.as_ref() }.ref_count.get();
+impl<T> MyRc<[T]> {
+    fn slice_layout(len: usize) -> Layout {
+        // `RcInner` is `repr(C)`, so the elements follow the counts at the first offset that is
+        // suitably aligned for `T`, exactly as `Layout::extend` computes it
+        Layout::new::<RcInner<()>>()
+            .extend(Layout::array::<T>(len).expect("slice too large"))
+            .expect("slice too large")
+            .0
+            .pad_to_align()
+    }
+
+
+    // allocates an `RcInner<[T]>` for `len` elements with both counts set to one; the elements
+    // are left uninitialized (or zeroed) for the caller to fill in
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    unsafe fn allocate_for_slice(len: usize, zeroed: bool) -> NonNull<RcInner<[T]>> {
+        let memory = allocate(Self::slice_layout(len), zeroed);
+        let inner = ptr::slice_from_raw_parts_mut(memory.cast::<T>().as_ptr(), len) as *mut RcInner<[T]>;
+        init_counts(inner);
+        track(NonNull::new_unchecked(inner));
+
+        NonNull::new_unchecked(inner)
+    }
+
+
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    fn copy_from_slice(values: &[T]) -> Self
+    where
+        T: Copy
+    {
+        unsafe {
+            let inner = Self::allocate_for_slice(values.len(), false);
+            let elements = &raw mut (*inner.as_ptr()).value as *mut T;
+            ptr::copy_nonoverlapping(values.as_ptr(), elements, values.len());
+
+            MyRc {
+                inner,
+                alloc: Global,
+                _marker: PhantomData
+            }
+        }
+    }
+
+
+    // fills a new allocation of exactly `len` elements from `iter`; if `iter` turns out to yield
+    // fewer or more elements, everything taken from it so far is handed back in order instead
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    fn from_iter_exact(iter: &mut impl Iterator<Item = T>, len: usize) -> Result<Self, Vec<T>> {
+        let mut guard = SliceGuard {
+            inner: unsafe { Self::allocate_for_slice(len, false) },
+            len,
+            written: 0
+        };
+
+        while guard.written < len {
+            let Some(value) = iter.next() else {
+                return Err(guard.into_vec());
+            };
+
+            unsafe { guard.elements().add(guard.written).write(value) };
+            guard.written += 1;
+        }
+
+        if let Some(value) = iter.next() {
+            let mut values = guard.into_vec();
+            values.push(value);
+            return Err(values);
+        }
 
-        if ref_count == 1 {
-            let _ = unsafe { Box::from_raw(self.inner.as_ptr()) };
+        let inner = guard.inner;
+        mem::forget(guard);
+
+        Ok(MyRc {
+            inner,
+            alloc: Global,
+            _marker: PhantomData
+        })
+    }
+}
+
+
+// allocates the memory of an `RcInner` from the global allocator, or from the slab if the value
+// takes no space; a slab block has no value bytes to zero, only the counts that are set afterwards
+fn allocate(layout: Layout, zeroed: bool) -> NonNull<u8> {
+    if layout == rc_slab::BLOCK {
+        return rc_slab::allocate().unwrap_or_else(|_| alloc::handle_alloc_error(layout));
+    }
+
+    let memory = unsafe {
+        if zeroed {
+            alloc::alloc_zeroed(layout)
         } else {
-            unsafe { self.inner.as_ref() }.ref_count.set(ref_count - 1);
+            alloc::alloc(layout)
         }
+    };
+
+    NonNull::new(memory).unwrap_or_else(|| alloc::handle_alloc_error(layout))
+}
+
+
+// gives back the memory of an `RcInner` of `layout`, to the slab if it came from there; `alloc`
+// is only asked for the others, which it handed out
+unsafe fn deallocate<A: Allocator>(alloc: &A, memory: NonNull<u8>, layout: Layout) {
+    if layout == rc_slab::BLOCK {
+        rc_slab::deallocate(memory);
+    } else {
+        alloc.deallocate(memory, layout);
     }
 }
 
 
-#[cfg(test)]
-mod tests {
-    use crate::rc::MyRc;
+// a count can only overflow after references were leaked with `mem::forget`, and carrying on
+// would free the value while references to it still exist once the count wraps around to zero, so
+// the process is aborted instead, as in std
+fn increment(count: &Cell<usize>) {
+    match count.get().checked_add(1) {
+        Some(value) => count.set(value),
+        None => process::abort()
+    }
+}
 
 
-    #[test]
-    fn my_rc_new() {
-        let my_rc = MyRc::new(String::from("Hello World!"));
+// records a new allocation for `rc_diagnostics`, together with the place where its `MyRc` was
+// created; without the `leak-check` feature there is nothing to record
+#[cfg_attr(feature = "leak-check", track_caller)]
+fn track<T: ?Sized>(inner: NonNull<RcInner<T>>) {
+    #[cfg(feature = "leak-check")]
+    crate::rc_diagnostics::register(inner.cast(), std::any::type_name::<T>(), std::panic::Location::caller());
+    #[cfg(not(feature = "leak-check"))]
+    let _ = inner;
+}
 
-        assert_eq!(unsafe { my_rc.inner.as_ref() }.ref_count.get(), 1);
-        assert_eq!(*my_rc, String::from("Hello World!"));
+
+fn untrack<T: ?Sized>(inner: NonNull<RcInner<T>>) {
+    #[cfg(feature = "leak-check")]
+    crate::rc_diagnostics::unregister(inner.cast());
+    #[cfg(not(feature = "leak-check"))]
+    let _ = inner;
+}
+
+
+// turns a pointer to the value back into a pointer to its `RcInner`, `offset` bytes in front of it.
+// The pointer arithmetic stays within the allocation the value pointer is derived from, so the
+// result keeps its provenance (no address ever goes through an integer), and the cast carries the
+// metadata of an unsized `T` over to the `RcInner<T>`
+unsafe fn inner_from_value<T: ?Sized>(value: *const T, offset: usize) -> NonNull<RcInner<T>> {
+    let inner = NonNull::new_unchecked(value.cast_mut()).byte_sub(offset);
+    NonNull::new_unchecked(inner.as_ptr() as *mut RcInner<T>)
+}
+
+
+// the layout of the allocation behind a `MyRc<T>`, for allocators that recycle exactly these
+pub(crate) fn inner_layout<T>() -> Layout {
+    Layout::new::<RcInner<T>>()
+}
+
+
+// sets both counts of a fresh allocation to one, as for a new `MyRc`
+unsafe fn init_counts<T: ?Sized>(inner: *mut RcInner<T>) {
+    (&raw mut (*inner).strong).write(Cell::new(1));
+    (&raw mut (*inner).weak).write(Cell::new(1));
+}
+
+
+// owns a slice allocation while it is being filled: if filling it is abandoned, or a panic unwinds
+// through it, the elements written so far are dropped and the allocation is freed
+struct SliceGuard<T> {
+    inner: NonNull<RcInner<[T]>>,
+    len: usize,
+    written: usize
+}
+
+
+impl<T> SliceGuard<T> {
+    fn elements(&self) -> *mut T {
+        unsafe { &raw mut (*self.inner.as_ptr()).value as *mut T }
     }
 
 
-    #[test]
-    fn my_rc_clone() {
-        let my_rc = MyRc::new(String::from("Hello World!"));
+    // moves the elements written so far into a vector and frees the allocation
+    fn into_vec(mut self) -> Vec<T> {
+        let mut values = Vec::with_capacity(self.written);
 
-        {
-            let my_rc_first_clone = my_rc.clone();
-            let my_rc_second_clone = my_rc_first_clone.clone();
+        unsafe {
+            ptr::copy_nonoverlapping(self.elements(), values.as_mut_ptr(), self.written);
+            values.set_len(self.written);
+        }
 
-            assert_eq!(unsafe { my_rc_first_clone.inner.as_ref() }.ref_count.get(), 3);
-            assert_eq!(*my_rc_second_clone, String::from("Hello World!"));
+        // the elements are owned by the vector now, so only the allocation is left to free
+        self.written = 0;
+        values
+    }
+}
+
+
+impl<T> Drop for SliceGuard<T> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.elements(), self.written));
+            untrack(self.inner);
+            deallocate(&Global, self.inner.cast(), MyRc::<[T]>::slice_layout(self.len));
         }
+    }
+}
 
-        assert_eq!(unsafe { my_rc.inner.as_ref() }.ref_count.get(), 1);
-        assert_eq!(*my_rc, String::from("Hello World!"));
+
+impl<T> MyUniqueRc<T> {
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn new(value: T) -> Self {
+        let inner = allocate(Layout::new::<RcInner<T>>(), false).cast::<RcInner<T>>();
+        unsafe {
+            inner.write(RcInner {
+                strong: Cell::new(0),
+                weak: Cell::new(1),
+                value: ManuallyDrop::new(value)
+            });
+        }
+        track(inner);
+
+        MyUniqueRc {
+            inner,
+            _marker: PhantomData
+        }
     }
-}
\ No newline at end of file
+}
+
+
+impl<T: ?Sized> MyUniqueRc<T> {
+    pub fn downgrade(this: &Self) -> MyWeak<T> {
+        let inner = unsafe { &*this.inner.as_ptr() };
+        increment(&inner.weak);
+
+        MyWeak {
+            inner: this.inner,
+            alloc: Global,
+            _marker: PhantomData
+        }
+    }
+
+
+    pub fn into_rc(this: Self) -> MyRc<T> {
+        let this = ManuallyDrop::new(this);
+
+        // from here on the weak references created so far can upgrade
+        unsafe { (*this.inner.as_ptr()).strong.set(1) };
+
+        MyRc {
+            inner: this.inner,
+            alloc: Global,
+            _marker: PhantomData
+        }
+    }
+}
+
+
+impl<T: ?Sized> Deref for MyUniqueRc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &(*self.inner.as_ptr()).value }
+    }
+}
+
+
+impl<T: ?Sized> DerefMut for MyUniqueRc<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // weak references cannot upgrade and never borrow the value, so this is the only
+        // reference to it
+        unsafe { &mut (*self.inner.as_ptr()).value }
+    }
+}
+
+
+impl<T: ?Sized> Drop for MyUniqueRc<T> {
+    fn drop(&mut self) {
+        unsafe { ManuallyDrop::drop(&mut (*self.inner.as_ptr()).value) };
+
+        // the weak reference held on behalf of the (future) strong references
+        drop(MyWeak {inner: self.inner, alloc: Global, _marker: PhantomData});
+    }
+}
+
+
+impl<T, A: Allocator> MyRc<MaybeUninit<T>, A> {
+    /// # Safety
+    ///
+    /// The value must have been initialized, e.g. through `MyRc::get_mut` while the
+    /// `MyRc<MaybeUninit<T>>` was unique, or be a valid `T` when all of its bytes are zero.
+    pub unsafe fn assume_init(self) -> MyRc<T, A> {
+        // `MaybeUninit<T>` has the same layout as `T`
+        let (inner, alloc) = MyRc::into_parts(self);
+
+        MyRc {
+            inner: inner.cast::<RcInner<T>>(),
+            alloc,
+            _marker: PhantomData
+        }
+    }
+}
+
+
+impl<T, A: Allocator> MyRc<[MaybeUninit<T>], A> {
+    /// # Safety
+    ///
+    /// Every element must have been initialized, as for `MyRc<MaybeUninit<T>>::assume_init`.
+    pub unsafe fn assume_init(self) -> MyRc<[T], A> {
+        let (inner, alloc) = MyRc::into_parts(self);
+
+        MyRc {
+            inner: NonNull::new_unchecked(inner.as_ptr() as *mut RcInner<[T]>),
+            alloc,
+            _marker: PhantomData
+        }
+    }
+}
+
+
+impl<A: Allocator> MyRc<dyn Any, A> {
+    pub fn downcast<T: Any>(self) -> Result<MyRc<T, A>, Self> {
+        if (*self).is::<T>() {
+            Ok(unsafe { self.downcast_unchecked() })
+        } else {
+            Err(self)
+        }
+    }
+
+
+    /// # Safety
+    ///
+    /// The value must be a `T`, as checked by `downcast`.
+    pub unsafe fn downcast_unchecked<T: Any>(self) -> MyRc<T, A> {
+        let (inner, alloc) = MyRc::into_parts(self);
+
+        MyRc {
+            inner: inner.cast::<RcInner<T>>(),
+            alloc,
+            _marker: PhantomData
+        }
+    }
+}
+
+
+impl<T> MyWeak<T> {
+    pub const fn new() -> Self {
+        MyWeak {
+            inner: unsafe { NonNull::new_unchecked(ptr::without_provenance_mut(DANGLING)) },
+            alloc: Global,
+            _marker: PhantomData
+        }
+    }
+
+
+    pub fn into_raw(self) -> *const T {
+        let ptr = self.as_ptr();
+        mem::forget(self);
+        ptr
+    }
+
+
+    /// # Safety
+    ///
+    /// `ptr` must come from `MyWeak::into_raw` for the same `T`, and each pointer may be turned
+    /// back into a `MyWeak<T>` only once, since the weak reference it represents is taken over.
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        // a dangling weak reference hands out the sentinel itself, which has no counts in front of it
+        let inner = if ptr.addr() == DANGLING {
+            NonNull::new_unchecked(ptr.cast_mut()).cast()
+        } else {
+            inner_from_value(ptr, mem::offset_of!(RcInner<T>, value))
+        };
+
+        MyWeak {
+            inner,
+            alloc: Global,
+            _marker: PhantomData
+        }
+    }
+}
+
+
+impl<T, A: Allocator> MyWeak<T, A> {
+    pub fn as_ptr(&self) -> *const T {
+        if self.is_dangling() {
+            return self.inner.as_ptr().cast_const().cast();
+        }
+
+        // `ManuallyDrop<T>` has the same layout as `T`; the projection never reads the value, which
+        // may already have been dropped
+        unsafe { &raw const (*self.inner.as_ptr()).value as *const T }
+    }
+}
+
+
+impl<T: ?Sized, A: Allocator> MyWeak<T, A> {
+    pub fn upgrade(&self) -> Option<MyRc<T, A>>
+    where
+        A: Clone
+    {
+        let inner = self.counts()?;
+
+        if inner.strong.get() == 0 {
+            return None;
+        }
+
+        increment(inner.strong);
+        Some(MyRc {
+            inner: self.inner,
+            alloc: self.alloc.clone(),
+            _marker: PhantomData
+        })
+    }
+
+
+    fn is_dangling(&self) -> bool {
+        self.inner.as_ptr().cast::<u8>().addr() == DANGLING
+    }
+
+
+    fn counts(&self) -> Option<Counts<'_>> {
+        if self.is_dangling() {
+            return None;
+        }
+
+        // the allocation stays alive for as long as any weak reference exists, but the value may
+        // be getting dropped or be mutably borrowed, so only the counts are borrowed
+        let inner = self.inner.as_ptr();
+        unsafe {
+            Some(Counts {
+                strong: &(*inner).strong,
+                weak: &(*inner).weak
+            })
+        }
+    }
+}
+
+
+impl<T: ?Sized, A: Allocator + Clone> Clone for MyRc<T, A> {
+    fn clone(&self) -> Self {
+        let inner = self.inner();
+        increment(&inner.strong);
+
+        MyRc {
+            inner: self.inner,
+            alloc: self.alloc.clone(),
+            _marker: PhantomData
+        }
+    }
+}
+
+
+impl<T: ?Sized, A: Allocator + Clone> Clone for MyWeak<T, A> {
+    fn clone(&self) -> Self {
+        if let Some(inner) = self.counts() {
+            increment(inner.weak);
+        }
+
+        MyWeak {
+            inner: self.inner,
+            alloc: self.alloc.clone(),
+            _marker: PhantomData
+        }
+    }
+}
+
+
+impl<T> Default for MyWeak<T> {
+    fn default() -> Self {
+        MyWeak::new()
+    }
+}
+
+
+impl<T: ?Sized, A: Allocator> Deref for MyRc<T, A> {
+    type Target = T;
+
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner().value
+    }
+}
+
+
+impl MyRc<str> {
+    // the bytes must be valid UTF-8
+    unsafe fn from_utf8_unchecked(bytes: MyRc<[u8]>) -> Self {
+        let bytes = ManuallyDrop::new(bytes);
+
+        // `str` has the same layout and metadata as `[u8]`
+        MyRc {
+            inner: NonNull::new_unchecked(bytes.inner.as_ptr() as *mut RcInner<str>),
+            alloc: Global,
+            _marker: PhantomData
+        }
+    }
+}
+
+
+impl From<&str> for MyRc<str> {
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    fn from(value: &str) -> Self {
+        // the bytes came from a `str`, so they are valid UTF-8
+        unsafe { MyRc::from_utf8_unchecked(MyRc::<[u8]>::copy_from_slice(value.as_bytes())) }
+    }
+}
+
+
+impl From<Box<str>> for MyRc<str> {
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    fn from(value: Box<str>) -> Self {
+        unsafe { MyRc::from_utf8_unchecked(MyRc::from(value.into_boxed_bytes())) }
+    }
+}
+
+
+impl From<String> for MyRc<str> {
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    fn from(value: String) -> Self {
+        MyRc::from(value.as_str())
+    }
+}
+
+
+impl<T> From<Vec<T>> for MyRc<[T]> {
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    fn from(mut values: Vec<T>) -> Self {
+        unsafe {
+            let inner = Self::allocate_for_slice(values.len(), false);
+            let elements = &raw mut (*inner.as_ptr()).value as *mut T;
+            ptr::copy_nonoverlapping(values.as_ptr(), elements, values.len());
+
+            // the elements moved into the new allocation, so the vector only frees its buffer
+            values.set_len(0);
+
+            MyRc {
+                inner,
+                alloc: Global,
+                _marker: PhantomData
+            }
+        }
+    }
+}
+
+
+impl<T> From<Box<[T]>> for MyRc<[T]> {
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    fn from(values: Box<[T]>) -> Self {
+        unsafe {
+            let inner = Self::allocate_for_slice(values.len(), false);
+            let elements = &raw mut (*inner.as_ptr()).value as *mut T;
+            ptr::copy_nonoverlapping(values.as_ptr(), elements, values.len());
+
+            // the elements moved into the new allocation, so only the box itself is freed
+            drop(Box::from_raw(Box::into_raw(values) as *mut [ManuallyDrop<T>]));
+
+            MyRc {
+                inner,
+                alloc: Global,
+                _marker: PhantomData
+            }
+        }
+    }
+}
+
+
+impl<T: Clone> From<&[T]> for MyRc<[T]> {
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    fn from(values: &[T]) -> Self {
+        match Self::from_iter_exact(&mut values.iter().cloned(), values.len()) {
+            Ok(my_rc) => my_rc,
+            Err(_) => unreachable!("a slice yields exactly its length")
+        }
+    }
+}
+
+
+impl<T> FromIterator<T> for MyRc<[T]> {
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut iter = iter.into_iter();
+
+        // an exact size hint allows writing the elements straight into their final allocation;
+        // a hint that turns out to be wrong falls back to collecting into a vector first
+        match iter.size_hint() {
+            (lower, Some(upper)) if lower == upper => match Self::from_iter_exact(&mut iter, lower) {
+                Ok(my_rc) => my_rc,
+                Err(mut values) => {
+                    values.extend(iter);
+                    MyRc::from(values)
+                }
+            },
+            _ => MyRc::from(iter.collect::<Vec<T>>())
+        }
+    }
+}
+
+
+// moving an `MyRc<T>` only moves the pointer, never the value behind it
+impl<T: ?Sized, A: Allocator> Unpin for MyRc<T, A> {}
+
+
+impl<T: ?Sized, A: Allocator> Unpin for MyWeak<T, A> {}
+
+
+impl<T: Default> Default for MyRc<T> {
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    fn default() -> Self {
+        MyRc::new(T::default())
+    }
+}
+
+
+impl<T: ?Sized + fmt::Debug, A: Allocator> fmt::Debug for MyRc<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+
+impl<T: ?Sized + fmt::Display, A: Allocator> fmt::Display for MyRc<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+
+impl<T: ?Sized, A: Allocator> fmt::Debug for MyWeak<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(MyWeak)")
+    }
+}
+
+
+impl<T: ?Sized + Hash, A: Allocator> Hash for MyRc<T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+
+impl<T: ?Sized + PartialEq, A: Allocator> PartialEq for MyRc<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        RcEq::eq(self, other)
+    }
+}
+
+
+// picks the comparison for `PartialEq`: with specialization, values that are `Eq` are equal to
+// themselves, so pointing at the same allocation settles it without looking at the values
+trait RcEq {
+    fn eq(&self, other: &Self) -> bool;
+}
+
+
+// `default fn` is not even valid syntax without specialization, so the impl is spelled out by a
+// macro that only adds the `default` with the feature
+macro_rules! rc_eq_by_value {
+    ($($default:ident)?) => {
+        impl<T: ?Sized + PartialEq, A: Allocator> RcEq for MyRc<T, A> {
+            $($default)? fn eq(&self, other: &Self) -> bool {
+                **self == **other
+            }
+        }
+    };
+}
+
+
+#[cfg(feature = "specialization")]
+rc_eq_by_value!(default);
+#[cfg(not(feature = "specialization"))]
+rc_eq_by_value!();
+
+
+#[cfg(feature = "specialization")]
+impl<T: ?Sized + Eq, A: Allocator> RcEq for MyRc<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        MyRc::ptr_eq(self, other) || **self == **other
+    }
+}
+
+
+impl<T: ?Sized + Eq, A: Allocator> Eq for MyRc<T, A> {}
+
+
+impl<T: ?Sized + PartialOrd, A: Allocator> PartialOrd for MyRc<T, A> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+
+impl<T: ?Sized + Ord, A: Allocator> Ord for MyRc<T, A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+
+impl<T: ?Sized, A: Allocator> Borrow<T> for MyRc<T, A> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+
+impl<T: ?Sized, A: Allocator> AsRef<T> for MyRc<T, A> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+
+impl<T> From<T> for MyRc<T> {
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    fn from(value: T) -> Self {
+        MyRc::new(value)
+    }
+}
+
+
+impl<T> From<Box<T>> for MyRc<T> {
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    fn from(value: Box<T>) -> Self {
+        // the box has no room for the counts, so the value is copied from one heap allocation
+        // straight into the other, without passing through the stack
+        let mut uninit = MyRc::<T>::new_uninit();
+
+        unsafe {
+            let value = Box::into_raw(value);
+            ptr::copy_nonoverlapping(value, MyRc::get_mut(&mut uninit).unwrap().as_mut_ptr(), 1);
+            drop(Box::from_raw(value as *mut ManuallyDrop<T>));
+
+            uninit.assume_init()
+        }
+    }
+}
+
+
+// turns an `MyRc<T>` into an `MyRc<U>` for an unsized `U` that `T` coerces to, e.g.
+// `coerce_rc!(MyRc::new(Logger) => dyn Plugin)`
+#[macro_export]
+macro_rules! coerce_rc {
+    ($rc:expr => $target:ty) => {
+        // `$rc` is evaluated outside of the `unsafe` block
+        match $rc {
+            // the only implicit coercion from one raw pointer type to another is an unsizing one
+            rc => unsafe { $crate::rc::MyRc::unsize(rc, |value| -> *const $target { value }) }
+        }
+    };
+}
+
+
+#[cfg(feature = "unsize")]
+impl<T: ?Sized + std::marker::Unsize<U>, U: ?Sized, A: Allocator> std::ops::CoerceUnsized<MyRc<U, A>> for MyRc<T, A> {}
+
+
+#[cfg(feature = "unsize")]
+impl<T: ?Sized + std::marker::Unsize<U>, U: ?Sized, A: Allocator> std::ops::CoerceUnsized<MyWeak<U, A>> for MyWeak<T, A> {}
+
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for MyRc<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (**self).serialize(serializer)
+    }
+}
+
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for MyRc<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(MyRc::new)
+    }
+}
+
+
+impl<T: ?Sized, A: Allocator> Drop for MyRc<T, A> {
+    fn drop(&mut self) {
+        let strong = self.inner().strong.get() - 1;
+        self.inner().strong.set(strong);
+
+        if strong == 0 {
+            // no `MyRc` is left to observe the value, and `MyWeak::upgrade` refuses to hand out
+            // new ones from here on
+            unsafe { ManuallyDrop::drop(&mut (*self.inner.as_ptr()).value) };
+
+            // the implicit weak reference of the strong references goes with the value; it borrows
+            // the allocator, which is dropped together with this `MyRc`
+            drop(MyWeak {inner: self.inner, alloc: &self.alloc, _marker: PhantomData});
+        }
+    }
+}
+
+
+impl<T: ?Sized, A: Allocator> Drop for MyWeak<T, A> {
+    fn drop(&mut self) {
+        let Some(inner) = self.counts() else {
+            return;
+        };
+
+        let weak = inner.weak.get() - 1;
+        inner.weak.set(weak);
+
+        if weak == 0 {
+            // the value is gone already, so only the memory is left to give back
+            untrack(self.inner);
+            unsafe {
+                let layout = Layout::for_value(&*self.inner.as_ptr());
+                deallocate(&self.alloc, self.inner.cast(), layout);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{env, mem, panic};
+    use std::process::{Command, Stdio};
+    use std::ffi::c_void;
+    use std::marker::PhantomPinned;
+    use std::any::{Any, TypeId};
+    use std::cell::{Cell, RefCell};
+    use std::collections::{HashMap, HashSet};
+    use std::alloc::Layout;
+    use std::ptr::NonNull;
+    use crate::allocator::{AllocError, Allocator, Global};
+    use crate::rc::{MyRc, MyUniqueRc, MyWeak};
+
+
+    #[test]
+    fn my_rc_new() {
+        let my_rc = MyRc::new(String::from("Hello World!"));
+
+        assert_eq!(MyRc::strong_count(&my_rc), 1);
+        assert_eq!(*my_rc, String::from("Hello World!"));
+    }
+
+
+    #[test]
+    fn my_rc_clone() {
+        let my_rc = MyRc::new(String::from("Hello World!"));
+
+        {
+            let my_rc_first_clone = my_rc.clone();
+            let my_rc_second_clone = my_rc_first_clone.clone();
+
+            assert_eq!(MyRc::strong_count(&my_rc_first_clone), 3);
+            assert_eq!(*my_rc_second_clone, String::from("Hello World!"));
+        }
+
+        assert_eq!(MyRc::strong_count(&my_rc), 1);
+        assert_eq!(*my_rc, String::from("Hello World!"));
+    }
+
+
+    #[test]
+    fn my_rc_new_cyclic() {
+        struct Node {
+            me: MyWeak<Node>,
+            name: String
+        }
+
+        let node = MyRc::new_cyclic(|me| {
+            assert!(me.upgrade().is_none());
+            Node {me: me.clone(), name: String::from("root")}
+        });
+
+        assert_eq!(MyRc::strong_count(&node), 1);
+        assert_eq!(MyRc::weak_count(&node), 1);
+
+        let me = node.me.upgrade().unwrap();
+        assert!(MyRc::ptr_eq(&node, &me));
+        assert_eq!(me.name, "root");
+    }
+
+
+    #[test]
+    #[should_panic(expected = "construction failed")]
+    fn my_rc_new_cyclic_panics() {
+        MyRc::<String>::new_cyclic(|_| panic!("construction failed"));
+    }
+
+
+    #[test]
+    fn my_unique_rc() {
+        struct Node {
+            parent: Option<MyWeak<Node>>,
+            children: Vec<MyRc<Node>>
+        }
+
+        let mut root = MyUniqueRc::new(Node {parent: None, children: Vec::new()});
+        for _ in 0..2 {
+            let child = MyRc::new(Node {parent: Some(MyUniqueRc::downgrade(&root)), children: Vec::new()});
+            // the parent is still under construction
+            assert!(child.parent.as_ref().unwrap().upgrade().is_none());
+            root.children.push(child);
+        }
+
+        let root = MyUniqueRc::into_rc(root);
+        assert_eq!(MyRc::strong_count(&root), 1);
+        assert_eq!(MyRc::weak_count(&root), 2);
+        for child in &root.children {
+            assert!(MyRc::ptr_eq(&child.parent.as_ref().unwrap().upgrade().unwrap(), &root));
+        }
+
+        let unique = MyUniqueRc::new(String::from("never shared"));
+        let my_weak = MyUniqueRc::downgrade(&unique);
+        drop(unique);
+        assert!(my_weak.upgrade().is_none());
+    }
+
+
+    #[test]
+    fn my_rc_new_uninit() {
+        let mut uninit = MyRc::<String>::new_uninit();
+        MyRc::get_mut(&mut uninit).unwrap().write(String::from("Hello World!"));
+        let my_rc = unsafe { uninit.assume_init() };
+        assert_eq!(*my_rc, String::from("Hello World!"));
+
+        let zeroed = unsafe { MyRc::<[u64; 512]>::new_zeroed().assume_init() };
+        assert!(zeroed.iter().all(|value| *value == 0));
+
+        let mut slice = MyRc::<String>::new_uninit_slice(3);
+        for (index, element) in MyRc::get_mut(&mut slice).unwrap().iter_mut().enumerate() {
+            element.write(index.to_string());
+        }
+        let slice = unsafe { slice.assume_init() };
+        assert_eq!(*slice, ["0", "1", "2"]);
+
+        let zeroed_slice = unsafe { MyRc::<u8>::new_zeroed_slice(4).assume_init() };
+        assert_eq!(*zeroed_slice, [0; 4]);
+    }
+
+
+    #[test]
+    fn my_rc_pin() {
+        // an intrusive node that must not move once other nodes may point at it
+        struct Node {
+            value: u32,
+            _pinned: PhantomPinned
+        }
+
+        let pinned = MyRc::pin(Node {value: 1, _pinned: PhantomPinned});
+        let address = &*pinned as *const Node;
+
+        let clone = pinned.clone();
+        let moved = Box::new(pinned);
+        assert_eq!(&**moved as *const Node, address);
+        assert_eq!(clone.as_ref().get_ref().value, 1);
+    }
+
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn my_rc_clone_overflow_aborts() {
+        // an abort takes down the whole process, so the overflow happens in a child process that
+        // runs only this test
+        if env::var_os("MY_RC_CLONE_OVERFLOW").is_some() {
+            let my_rc = MyRc::new(1);
+            my_rc.inner().strong.set(usize::MAX);
+            mem::forget(my_rc.clone());
+            return;
+        }
+
+        let status = Command::new(env::current_exe().unwrap())
+            .args(["--exact", "rc::tests::my_rc_clone_overflow_aborts"])
+            .env("MY_RC_CLONE_OVERFLOW", "1")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+
+        // a panic would exit with code 101, an abort kills the process without an exit code
+        // on unix
+        assert!(!status.success());
+        assert_ne!(status.code(), Some(101));
+    }
+
+
+    #[test]
+    fn my_rc_new_in() {
+        #[derive(Default)]
+        struct CountingAllocator {
+            allocations: Cell<usize>,
+            deallocations: Cell<usize>
+        }
+
+        unsafe impl Allocator for CountingAllocator {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                self.allocations.set(self.allocations.get() + 1);
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                self.deallocations.set(self.deallocations.get() + 1);
+                Global.deallocate(ptr, layout)
+            }
+        }
+
+        let arena = CountingAllocator::default();
+        let my_rc = MyRc::new_in(String::from("Hello World!"), &arena);
+        let mut my_rc_clone = my_rc.clone();
+        let my_weak = MyRc::downgrade(&my_rc);
+        assert_eq!(arena.allocations.get(), 1);
+
+        // a shared value is cloned into a second allocation from the same allocator
+        MyRc::make_mut(&mut my_rc_clone).push('!');
+        assert_eq!(arena.allocations.get(), 2);
+        assert_eq!(*my_rc_clone, "Hello World!!");
+
+        let (raw, alloc) = MyRc::into_raw_with_allocator(my_rc);
+        let my_rc = unsafe { MyRc::from_raw_in(raw, alloc) };
+        assert!(std::ptr::eq(*MyRc::allocator(&my_rc), &arena));
+
+        drop(my_rc);
+        drop(my_rc_clone);
+        // the weak reference keeps the first allocation alive
+        assert_eq!(arena.deallocations.get(), 1);
+        assert!(my_weak.upgrade().is_none());
+        drop(my_weak);
+        assert_eq!(arena.deallocations.get(), 2);
+    }
+
+
+    #[test]
+    fn my_rc_try_new() {
+        struct ExhaustedAllocator;
+
+        unsafe impl Allocator for ExhaustedAllocator {
+            fn allocate(&self, _layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                Err(AllocError)
+            }
+
+            unsafe fn deallocate(&self, _ptr: NonNull<u8>, _layout: Layout) {
+                unreachable!("nothing was allocated")
+            }
+        }
+
+        assert_eq!(*MyRc::try_new(5).unwrap(), 5);
+
+        let shared = MyRc::new(());
+        let result = MyRc::try_new_in(shared.clone(), ExhaustedAllocator);
+        assert_eq!(result.err(), Some(AllocError));
+        assert_eq!(MyRc::strong_count(&shared), 1);
+
+        // zero-sized values take their counts from the slab and never reach the allocator
+        let token = MyRc::try_new_in((), ExhaustedAllocator).unwrap();
+        assert_eq!(MyRc::strong_count(&token), 1);
+    }
+
+
+    #[test]
+    fn my_rc_zero_sized() {
+        let first = MyRc::new(());
+        let second = MyRc::new(());
+
+        // every value has counts of its own, even without a value to point at
+        assert!(!MyRc::ptr_eq(&first, &second));
+        let weak = MyRc::downgrade(&first);
+        assert_eq!((MyRc::strong_count(&first), MyRc::weak_count(&second)), (1, 0));
+
+        // a weak reference does not upgrade once its own value is gone, even when the block is
+        // reused right away
+        let address = MyRc::as_ptr(&first);
+        drop((first, weak));
+        let third = MyRc::new(());
+        assert_eq!(MyRc::as_ptr(&third), address);
+
+        let weak = MyRc::downgrade(&third);
+        drop(third);
+        assert!(weak.upgrade().is_none());
+
+        // all ways of creating a zero-sized `MyRc`, and giving it back as another type
+        let cyclic = MyRc::new_cyclic(|weak: &MyWeak<()>| assert!(weak.upgrade().is_none()));
+        let unique = MyUniqueRc::into_rc(MyUniqueRc::new(()));
+        let any = coerce_rc!(MyRc::new(()) => dyn Any);
+        let empty: MyRc<[u64]> = MyRc::from(Vec::new());
+        assert!(any.downcast::<()>().is_ok());
+        assert!(empty.is_empty());
+        drop((cyclic, unique, second));
+    }
+
+
+    #[test]
+    fn my_rc_ptr_eq_and_as_ptr() {
+        let my_rc = MyRc::new(String::from("Hello World!"));
+        let my_rc_clone = my_rc.clone();
+        let other = MyRc::new(String::from("Hello World!"));
+
+        assert!(MyRc::ptr_eq(&my_rc, &my_rc_clone));
+        assert!(!MyRc::ptr_eq(&my_rc, &other));
+        assert_eq!(MyRc::as_ptr(&my_rc), &*my_rc_clone as *const String);
+        assert_eq!(MyRc::as_ptr(&my_rc), MyRc::downgrade(&my_rc).as_ptr());
+    }
+
+
+    #[test]
+    fn my_rc_str() {
+        let my_rc: MyRc<str> = MyRc::from("Hello World!");
+        let my_rc_clone = my_rc.clone();
+
+        assert_eq!(&*my_rc, "Hello World!");
+        assert!(MyRc::ptr_eq(&my_rc, &my_rc_clone));
+        assert_eq!(MyRc::strong_count(&my_rc), 2);
+
+        let my_weak = MyRc::downgrade(&my_rc);
+        drop(my_rc);
+        drop(my_rc_clone);
+        assert!(my_weak.upgrade().is_none());
+
+        let identifier = MyRc::<str>::from(String::from("x"));
+        let empty = MyRc::<str>::from("");
+        assert_eq!(&*identifier, "x");
+        assert_eq!(&*empty, "");
+    }
+
+
+    #[test]
+    fn my_rc_slice() {
+        let from_vec: MyRc<[String]> = MyRc::from(vec![String::from("a"), String::from("b")]);
+        let from_slice: MyRc<[String]> = MyRc::from(&[String::from("a"), String::from("b")][..]);
+        let exact: MyRc<[u64]> = (1..=4).collect();
+        let filtered: MyRc<[u64]> = (1..=8).filter(|value| value % 2 == 0).collect();
+        let empty: MyRc<[u8]> = MyRc::from(Vec::new());
+
+        assert_eq!(*from_vec, *from_slice);
+        assert_eq!(*exact, [1, 2, 3, 4]);
+        assert_eq!(*filtered, [2, 4, 6, 8]);
+        assert!(empty.is_empty());
+
+        let my_weak = MyRc::downgrade(&from_vec);
+        let from_vec_clone = from_vec.clone();
+        drop(from_vec);
+        assert_eq!(my_weak.upgrade().unwrap()[1], "b");
+        drop(from_vec_clone);
+        assert!(my_weak.upgrade().is_none());
+    }
+
+
+    #[test]
+    fn my_rc_from_box() {
+        let my_rc: MyRc<String> = MyRc::from(Box::new(String::from("Hello World!")));
+        assert_eq!(*my_rc, "Hello World!");
+        assert_eq!(MyRc::strong_count(&my_rc), 1);
+
+        let large: MyRc<[u64; 512]> = MyRc::from(Box::new([7; 512]));
+        assert!(large.iter().all(|value| *value == 7));
+
+        let slice: MyRc<[String]> = MyRc::from(vec![String::from("a"), String::from("b")].into_boxed_slice());
+        assert_eq!(*slice, ["a", "b"]);
+
+        let text: MyRc<str> = MyRc::from(Box::<str>::from("boxed"));
+        let empty: MyRc<[u8]> = MyRc::from(Box::<[u8]>::default());
+        assert_eq!(&*text, "boxed");
+        assert!(empty.is_empty());
+    }
+
+
+    #[test]
+    fn my_rc_slice_from_wrong_size_hint() {
+        // claims an exact size that is off by `error`
+        struct Lying {
+            remaining: usize,
+            error: isize
+        }
+
+        impl Iterator for Lying {
+            type Item = String;
+
+            fn next(&mut self) -> Option<String> {
+                self.remaining = self.remaining.checked_sub(1)?;
+                Some(self.remaining.to_string())
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                let hint = self.remaining.saturating_add_signed(self.error);
+                (hint, Some(hint))
+            }
+        }
+
+        let too_few: MyRc<[String]> = Lying {remaining: 3, error: 2}.collect();
+        let too_many: MyRc<[String]> = Lying {remaining: 3, error: -2}.collect();
+        assert_eq!(*too_few, ["2", "1", "0"]);
+        assert_eq!(*too_many, ["2", "1", "0"]);
+    }
+
+
+    #[test]
+    fn my_rc_slice_clone_panics() {
+        struct Element<'drops> {
+            drops: &'drops Cell<usize>,
+            panics: bool
+        }
+
+        impl Clone for Element<'_> {
+            fn clone(&self) -> Self {
+                assert!(!self.panics, "clone failed");
+                Element {drops: self.drops, panics: false}
+            }
+        }
+
+        impl Drop for Element<'_> {
+            fn drop(&mut self) {
+                self.drops.set(self.drops.get() + 1);
+            }
+        }
+
+        let drops = Cell::new(0);
+        let elements = [
+            Element {drops: &drops, panics: false},
+            Element {drops: &drops, panics: false},
+            Element {drops: &drops, panics: true}
+        ];
+
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| MyRc::<[Element<'_>]>::from(&elements[..])));
+        assert!(result.is_err());
+        // exactly the two clones that were written before the panic are dropped
+        assert_eq!(drops.get(), 2);
+    }
+
+
+    #[test]
+    fn my_rc_coerce_to_trait_object() {
+        trait Plugin {
+            fn name(&self) -> String;
+        }
+
+        struct Logger;
+        struct Counter(u32);
+
+        impl Plugin for Logger {
+            fn name(&self) -> String {
+                String::from("logger")
+            }
+        }
+
+        impl Plugin for Counter {
+            fn name(&self) -> String {
+                format!("counter {}", self.0)
+            }
+        }
+
+        let counter = MyRc::new(Counter(3));
+        let plugins: Vec<MyRc<dyn Plugin>> = vec![
+            coerce_rc!(MyRc::new(Logger) => dyn Plugin),
+            coerce_rc!(counter.clone() => dyn Plugin)
+        ];
+        let names: Vec<String> = plugins.iter().map(|plugin| plugin.name()).collect();
+
+        assert_eq!(names, ["logger", "counter 3"]);
+        assert_eq!(MyRc::strong_count(&counter), 2);
+        assert!(std::ptr::addr_eq(MyRc::as_ptr(&plugins[1]), MyRc::as_ptr(&counter)));
+
+        drop(plugins);
+        assert_eq!(MyRc::strong_count(&counter), 1);
+
+        let array = MyRc::new([1u16, 2, 3]);
+        let slice = coerce_rc!(array => [u16]);
+        assert_eq!(*slice, [1, 2, 3]);
+    }
+
+
+    #[test]
+    fn my_rc_downcast() {
+        let mut registry: HashMap<TypeId, MyRc<dyn Any>> = HashMap::new();
+        registry.insert(TypeId::of::<String>(), coerce_rc!(MyRc::new(String::from("Hello World!")) => dyn Any));
+        registry.insert(TypeId::of::<u32>(), coerce_rc!(MyRc::new(42u32) => dyn Any));
+
+        let entry = registry[&TypeId::of::<String>()].clone();
+        let Err(entry) = entry.downcast::<u32>() else {
+            panic!("downcast to the wrong type");
+        };
+        let Ok(string) = entry.downcast::<String>() else {
+            panic!("downcast to the right type failed");
+        };
+
+        assert_eq!(*string, String::from("Hello World!"));
+        assert_eq!(MyRc::strong_count(&string), 2);
+
+        let number = unsafe { registry[&TypeId::of::<u32>()].clone().downcast_unchecked::<u32>() };
+        assert_eq!(*number, 42);
+    }
+
+
+    #[cfg(feature = "unsize")]
+    #[test]
+    fn my_rc_coerce_unsized() {
+        let my_rc: MyRc<dyn std::fmt::Display> = MyRc::new(42);
+        let value = MyRc::new(String::from("value"));
+        let my_weak = MyRc::downgrade(&value);
+        let my_weak: MyWeak<dyn std::fmt::Display> = my_weak;
+
+        assert_eq!(my_rc.to_string(), "42");
+        assert_eq!(my_weak.upgrade().unwrap().to_string(), "value");
+    }
+
+
+    #[test]
+    fn my_rc_get_mut() {
+        let mut my_rc = MyRc::new(String::from("Hello"));
+        MyRc::get_mut(&mut my_rc).unwrap().push_str(" World!");
+        assert_eq!(*my_rc, String::from("Hello World!"));
+
+        let my_rc_clone = my_rc.clone();
+        assert!(MyRc::get_mut(&mut my_rc).is_none());
+        drop(my_rc_clone);
+
+        let my_weak = MyRc::downgrade(&my_rc);
+        assert!(MyRc::get_mut(&mut my_rc).is_none());
+        drop(my_weak);
+
+        assert!(MyRc::get_mut(&mut my_rc).is_some());
+    }
+
+
+    #[test]
+    fn my_rc_make_mut() {
+        let mut my_rc = MyRc::new(String::from("Hello"));
+        let unique = MyRc::as_ptr(&my_rc);
+
+        MyRc::make_mut(&mut my_rc).push(' ');
+        assert_eq!(MyRc::as_ptr(&my_rc), unique);
+
+        let my_rc_clone = my_rc.clone();
+        MyRc::make_mut(&mut my_rc).push_str("World!");
+        assert_eq!(*my_rc, String::from("Hello World!"));
+        assert_eq!(*my_rc_clone, String::from("Hello "));
+        assert_eq!(MyRc::strong_count(&my_rc), 1);
+        assert_eq!(MyRc::strong_count(&my_rc_clone), 1);
+
+        let my_weak = MyRc::downgrade(&my_rc);
+        MyRc::make_mut(&mut my_rc).push('!');
+        assert_eq!(*my_rc, String::from("Hello World!!"));
+        assert_eq!(MyRc::weak_count(&my_rc), 0);
+        assert!(my_weak.upgrade().is_none());
+    }
+
+
+    #[test]
+    fn my_rc_try_unwrap() {
+        let my_rc = MyRc::new(String::from("Hello World!"));
+        let my_rc_clone = my_rc.clone();
+
+        let my_rc = MyRc::try_unwrap(my_rc).unwrap_err();
+        assert_eq!(MyRc::strong_count(&my_rc), 2);
+        drop(my_rc_clone);
+
+        let my_weak = MyRc::downgrade(&my_rc);
+        assert_eq!(MyRc::try_unwrap(my_rc).ok(), Some(String::from("Hello World!")));
+        assert!(my_weak.upgrade().is_none());
+    }
+
+
+    #[test]
+    fn my_rc_into_inner() {
+        let my_rc = MyRc::new(String::from("Hello World!"));
+        let my_rc_clone = my_rc.clone();
+        let my_weak = MyRc::downgrade(&my_rc);
+
+        assert_eq!(MyRc::into_inner(my_rc), None);
+        assert_eq!(MyRc::strong_count(&my_weak.upgrade().unwrap()), 2);
+        assert_eq!(MyRc::into_inner(my_rc_clone), Some(String::from("Hello World!")));
+        assert!(my_weak.upgrade().is_none());
+    }
+
+
+    #[test]
+    fn my_rc_unwrap_or_clone() {
+        let my_rc = MyRc::new(String::from("Hello World!"));
+        let my_rc_clone = my_rc.clone();
+
+        assert_eq!(MyRc::unwrap_or_clone(my_rc), String::from("Hello World!"));
+        assert_eq!(MyRc::strong_count(&my_rc_clone), 1);
+
+        let buffer = my_rc_clone.as_ptr();
+        let value = MyRc::unwrap_or_clone(my_rc_clone);
+        // the last strong reference moves the value out instead of cloning it
+        assert_eq!(value.as_ptr(), buffer);
+    }
+
+
+    #[test]
+    fn my_rc_downgrade_upgrade() {
+        let my_rc = MyRc::new(String::from("Hello World!"));
+        let my_weak = MyRc::downgrade(&my_rc);
+        let my_weak_clone = my_weak.clone();
+
+        assert_eq!(MyRc::weak_count(&my_rc), 2);
+
+        {
+            let upgraded = my_weak.upgrade().unwrap();
+            assert_eq!(*upgraded, String::from("Hello World!"));
+            assert_eq!(MyRc::strong_count(&my_rc), 2);
+        }
+
+        drop(my_rc);
+        assert!(my_weak.upgrade().is_none());
+        assert!(my_weak_clone.upgrade().is_none());
+    }
+
+
+    #[test]
+    fn my_rc_value_dropped_before_allocation() {
+        struct Tracked<'log>(&'log RefCell<Vec<&'static str>>);
+
+        impl Drop for Tracked<'_> {
+            fn drop(&mut self) {
+                self.0.borrow_mut().push("value dropped");
+            }
+        }
+
+        let log = RefCell::new(Vec::new());
+        let my_rc = MyRc::new(Tracked(&log));
+        let my_weak = MyRc::downgrade(&my_rc);
+
+        drop(my_rc);
+        // the value is gone even though a weak reference still holds on to the allocation
+        assert_eq!(*log.borrow(), ["value dropped"]);
+        assert!(my_weak.upgrade().is_none());
+
+        drop(my_weak);
+        assert_eq!(*log.borrow(), ["value dropped"]);
+    }
+
+
+    #[test]
+    fn my_weak_new_is_dangling() {
+        let my_weak: MyWeak<String> = MyWeak::new();
+        let my_weak_clone = my_weak.clone();
+
+        assert!(my_weak.upgrade().is_none());
+        assert!(my_weak_clone.upgrade().is_none());
+        assert!(MyWeak::<u8>::default().upgrade().is_none());
+    }
+
+
+    #[test]
+    fn my_rc_raw_round_trip() {
+        // stands in for a C library that stores user data and hands it back to a callback
+        extern "C" fn callback(user_data: *const c_void) -> usize {
+            let my_rc = unsafe { MyRc::<String>::from_raw(user_data as *const String) };
+            my_rc.len()
+        }
+
+        let my_rc = MyRc::new(String::from("Hello World!"));
+        let raw = MyRc::into_raw(my_rc.clone());
+        assert_eq!(raw, MyRc::as_ptr(&my_rc));
+        assert_eq!(unsafe { &*raw }, "Hello World!");
+        assert_eq!(MyRc::strong_count(&my_rc), 2);
+
+        unsafe { MyRc::increment_strong_count(raw) };
+        assert_eq!(MyRc::strong_count(&my_rc), 3);
+        unsafe { MyRc::decrement_strong_count(raw) };
+        assert_eq!(MyRc::strong_count(&my_rc), 2);
+
+        // the callback takes over the strong reference and drops it
+        assert_eq!(callback(raw as *const c_void), 12);
+        assert_eq!(MyRc::strong_count(&my_rc), 1);
+    }
+
+
+    #[test]
+    fn my_weak_raw_round_trip() {
+        let my_rc = MyRc::new(String::from("Hello World!"));
+        let raw = MyRc::downgrade(&my_rc).into_raw();
+
+        assert_eq!(raw, MyRc::downgrade(&my_rc).as_ptr());
+        assert_eq!(unsafe { &*raw }, "Hello World!");
+        // the raw pointer still holds its weak reference
+        assert_eq!(MyRc::weak_count(&my_rc), 1);
+
+        let my_weak = unsafe { MyWeak::from_raw(raw) };
+        assert_eq!(*my_weak.upgrade().unwrap(), "Hello World!");
+
+        drop(my_rc);
+        assert!(my_weak.upgrade().is_none());
+
+        let dangling = unsafe { MyWeak::<String>::from_raw(MyWeak::new().into_raw()) };
+        assert!(dangling.upgrade().is_none());
+    }
+
+
+    #[test]
+    fn my_rc_parent_child_does_not_leak() {
+        struct Node {
+            parent: RefCell<Option<MyWeak<Node>>>,
+            children: RefCell<Vec<MyRc<Node>>>
+        }
+
+        let parent = MyRc::new(Node {parent: RefCell::new(None), children: RefCell::new(Vec::new())});
+        let child = MyRc::new(Node {parent: RefCell::new(None), children: RefCell::new(Vec::new())});
+
+        *child.parent.borrow_mut() = Some(MyRc::downgrade(&parent));
+        parent.children.borrow_mut().push(child.clone());
+
+        let child_weak = MyRc::downgrade(&child);
+        let back = child.parent.borrow().as_ref().unwrap().upgrade().unwrap();
+        assert_eq!(back.children.borrow().len(), 1);
+        drop(back);
+
+        // the parent is only kept alive by strong references, so dropping it frees the child too
+        drop(child);
+        drop(parent);
+        assert!(child_weak.upgrade().is_none());
+    }
+
+
+    #[test]
+    fn my_rc_trait_impls() {
+        let my_rc = MyRc::new(String::from("b"));
+
+        assert_eq!(format!("{:?}", my_rc), "\"b\"");
+        assert_eq!(my_rc.to_string(), "b");
+        assert_eq!(*MyRc::<Vec<u8>>::default(), Vec::<u8>::new());
+        assert_eq!(MyRc::from(String::from("b")), my_rc);
+        assert!(MyRc::new(String::from("a")) < my_rc);
+        assert_eq!(my_rc.cmp(&MyRc::new(String::from("c"))), std::cmp::Ordering::Less);
+        assert_eq!(AsRef::<String>::as_ref(&my_rc), "b");
+        assert_eq!(format!("{:?}", MyRc::downgrade(&my_rc)), "(MyWeak)");
+
+        let mut counts: HashMap<MyRc<String>, usize> = HashMap::new();
+        *counts.entry(my_rc.clone()).or_default() += 1;
+        *counts.entry(MyRc::new(String::from("b"))).or_default() += 1;
+        assert_eq!(counts[&String::from("b")], 2);
+
+        let set: HashSet<MyRc<str>> = [MyRc::from("x"), MyRc::from("y")].into_iter().collect();
+        assert!(set.contains("x"));
+    }
+
+
+    #[test]
+    fn my_rc_eq_same_allocation() {
+        struct Counted<'a>(&'a Cell<usize>);
+
+        impl PartialEq for Counted<'_> {
+            fn eq(&self, _: &Self) -> bool {
+                self.0.set(self.0.get() + 1);
+                true
+            }
+        }
+
+        impl Eq for Counted<'_> {}
+
+        let comparisons = Cell::new(0);
+        let my_rc = MyRc::new(Counted(&comparisons));
+        assert!(my_rc == my_rc.clone());
+        assert_eq!(comparisons.get(), if cfg!(feature = "specialization") { 0 } else { 1 });
+
+        // other allocations are always compared by value
+        assert!(my_rc == MyRc::new(Counted(&comparisons)));
+        assert_eq!(comparisons.get(), if cfg!(feature = "specialization") { 1 } else { 2 });
+
+        // without `Eq`, the value decides even for the same allocation
+        let nan = MyRc::new(f64::NAN);
+        assert!(nan != nan.clone());
+    }
+
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn my_rc_serde() {
+        let my_rc = MyRc::new(String::from("Hello World!"));
+        let pair = (my_rc.clone(), my_rc);
+        assert_eq!(serde_json::to_string(&pair).unwrap(), r#"["Hello World!","Hello World!"]"#);
+
+        let my_rc: MyRc<String> = serde_json::from_str(r#""Hello World!""#).unwrap();
+        assert_eq!(MyRc::strong_count(&my_rc), 1);
+        assert_eq!(*my_rc, String::from("Hello World!"));
+    }
+
+
+    // mixes every way of getting from a `MyRc` to its counts and back, so that
+    // `MIRIFLAGS=-Zmiri-strict-provenance cargo +nightly miri test --lib rc::` checks their
+    // provenance in one place
+    #[test]
+    fn my_rc_pointer_round_trips() {
+        let my_rc = MyRc::new(String::from("Hello World!"));
+        let weak = MyRc::downgrade(&my_rc);
+        let raw_weak = weak.clone().into_raw();
+        let raw = MyRc::into_raw(my_rc.clone());
+
+        let my_rc_clone = unsafe { MyRc::from_raw(raw) };
+        let weak_clone = unsafe { MyWeak::from_raw(raw_weak) };
+        assert!(MyRc::ptr_eq(&weak_clone.upgrade().unwrap(), &my_rc_clone));
+        drop(my_rc);
+        drop(my_rc_clone);
+        assert!(weak.upgrade().is_none());
+        assert!(weak_clone.upgrade().is_none());
+
+        // coercing goes from the value back to the counts too, and carries over the vtable
+        let shared: MyRc<dyn Any> = coerce_rc!(MyRc::new(7u64) => dyn Any);
+        let weak: MyWeak<dyn Any> = MyRc::downgrade(&shared);
+        assert_eq!(*weak.upgrade().unwrap().downcast::<u64>().ok().unwrap(), 7);
+        drop(shared);
+        assert!(weak.upgrade().is_none());
+
+        let slice: MyRc<[u8]> = MyRc::from(&b"bytes"[..]);
+        let weak = MyRc::downgrade(&slice);
+        assert_eq!(&*weak.upgrade().unwrap(), b"bytes");
+        drop(slice);
+        assert!(weak.upgrade().is_none());
+
+        struct Cyclic {
+            this: MyWeak<Cyclic>
+        }
+
+        let cyclic = MyRc::new_cyclic(|this| Cyclic {this: this.clone()});
+        assert!(MyRc::ptr_eq(&cyclic.this.upgrade().unwrap(), &cyclic));
+    }
+}
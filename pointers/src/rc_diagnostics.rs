@@ -0,0 +1,242 @@
+/*
+- With the `leak-check` feature enabled, every `MyRc` allocation is recorded in a registry together
+with the name of its value type and the place where the first `MyRc` of it was created. The record
+is removed when the allocation is freed, so the registry always lists exactly the allocations that
+are still around, e.g. the members of a reference cycle that nothing but the cycle refers to.
+
+- `MyRc` is neither `Send` nor `Sync`, so an allocation never leaves the thread that created it,
+and the registry is kept per thread. Tests running in parallel each see only their own
+allocations.
+
+- `live` takes a snapshot of the recorded allocations with their current counts, in the order they
+were created, `report` formats it and `dump` prints it to stderr. `assert_no_leaks` panics with
+that report if any allocation of the thread is still around.
+
+- A `LeakCheck` guard narrows the check down to a scope: dropping the guard panics if an
+allocation created while it was alive has not been freed. Created at the start of a test, it runs
+after every other local of the test is gone and turns a leak into a test failure. The check is
+skipped while the thread is already panicking.
+
+- The creation location is the caller of the constructor (`MyRc::new`, `MyRc::from`,
+`collect`, ...), since the constructors are `#[track_caller]` while the feature is enabled.
+*/
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::panic::Location;
+use std::ptr::NonNull;
+use std::thread;
+
+
+thread_local! {
+    static REGISTRY: RefCell<Registry> = RefCell::new(Registry {next_id: 0, records: HashMap::new()});
+}
+
+
+struct Registry {
+    next_id: u64,
+    records: HashMap<usize, Record>
+}
+
+
+struct Record {
+    id: u64,
+    // the counts at the start of the allocation, strong first and weak second
+    counts: NonNull<Cell<usize>>,
+    type_name: &'static str,
+    location: &'static Location<'static>
+}
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Allocation {
+    pub type_name: &'static str,
+    pub location: &'static Location<'static>,
+    pub strong: usize,
+    pub weak: usize
+}
+
+
+impl fmt::Display for Allocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f, "MyRc<{}> created at {} (strong: {}, weak: {})",
+            self.type_name, self.location, self.strong, self.weak
+        )
+    }
+}
+
+
+pub(crate) fn register(inner: NonNull<u8>, type_name: &'static str, location: &'static Location<'static>) {
+    // registrations made while the thread is being torn down are lost, which only happens for
+    // `MyRc`s created by destructors of other thread locals
+    let _ = REGISTRY.try_with(|registry| {
+        let mut registry = registry.borrow_mut();
+        let id = registry.next_id;
+        registry.next_id += 1;
+        registry.records.insert(inner.addr().get(), Record {id, counts: inner.cast(), type_name, location});
+    });
+}
+
+
+pub(crate) fn unregister(inner: NonNull<u8>) {
+    let _ = REGISTRY.try_with(|registry| registry.borrow_mut().records.remove(&inner.addr().get()));
+}
+
+
+pub fn live() -> Vec<Allocation> {
+    live_since(0)
+}
+
+
+pub fn report() -> String {
+    format_report(&live())
+}
+
+
+pub fn dump() {
+    eprint!("{}", report());
+}
+
+
+#[track_caller]
+pub fn assert_no_leaks() {
+    let live = live();
+    assert!(live.is_empty(), "leaked MyRc allocations:\n{}", format_report(&live));
+}
+
+
+pub struct LeakCheck {
+    start: u64
+}
+
+
+impl LeakCheck {
+    pub fn new() -> Self {
+        let start = REGISTRY.with(|registry| registry.borrow().next_id);
+        LeakCheck {start}
+    }
+}
+
+
+impl Default for LeakCheck {
+    fn default() -> Self {
+        LeakCheck::new()
+    }
+}
+
+
+impl Drop for LeakCheck {
+    fn drop(&mut self) {
+        // a second panic would abort and hide the first one
+        if thread::panicking() {
+            return;
+        }
+
+        let leaked = live_since(self.start);
+        assert!(leaked.is_empty(), "leaked MyRc allocations:\n{}", format_report(&leaked));
+    }
+}
+
+
+fn live_since(start: u64) -> Vec<Allocation> {
+    REGISTRY.with(|registry| {
+        let registry = registry.borrow();
+        let mut records: Vec<&Record> = registry.records.values().filter(|record| record.id >= start).collect();
+        records.sort_by_key(|record| record.id);
+
+        records.into_iter().map(|record| {
+            // the allocation is still there for as long as it is registered
+            let (strong, weak) = unsafe {
+                (record.counts.as_ref().get(), record.counts.add(1).as_ref().get())
+            };
+
+            Allocation {
+                type_name: record.type_name,
+                location: record.location,
+                strong,
+                // the strong references share one weak reference between them
+                weak: if strong > 0 { weak - 1 } else { weak }
+            }
+        }).collect()
+    })
+}
+
+
+fn format_report(allocations: &[Allocation]) -> String {
+    let mut report = String::new();
+    for allocation in allocations {
+        let _ = writeln!(report, "{}", allocation);
+    }
+    report
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use crate::rc::{MyRc, MyWeak};
+    use crate::rc_diagnostics::{self, LeakCheck};
+
+
+    struct Node {
+        next: RefCell<Option<MyRc<Node>>>
+    }
+
+
+    #[test]
+    fn rc_diagnostics_tracks_live_allocations() {
+        let _check = LeakCheck::new();
+        let start = rc_diagnostics::live().len();
+
+        let line = line!() + 1;
+        let value = MyRc::new(5_u32);
+        let weak = MyRc::downgrade(&value);
+        let clone = value.clone();
+
+        let live = rc_diagnostics::live();
+        assert_eq!(live.len(), start + 1);
+        let allocation = live.last().unwrap();
+        assert_eq!(allocation.type_name, "u32");
+        assert_eq!(allocation.location.file(), file!());
+        assert_eq!(allocation.location.line(), line);
+        assert_eq!((allocation.strong, allocation.weak), (2, 1));
+        assert!(rc_diagnostics::report().contains(&format!("MyRc<u32> created at {}:{}", file!(), line)));
+
+        // an allocation kept by a weak reference only is still around, but its value is gone
+        drop(value);
+        drop(clone);
+        let allocation = rc_diagnostics::live().pop().unwrap();
+        assert_eq!((allocation.strong, allocation.weak), (0, 1));
+
+        drop(weak);
+        assert_eq!(rc_diagnostics::live().len(), start);
+        let _slice: MyRc<[u8]> = MyRc::from(vec![1, 2, 3]);
+        let _empty = MyWeak::<u8>::new();
+        assert_eq!(rc_diagnostics::live().last().unwrap().type_name, "[u8]");
+    }
+
+
+    #[test]
+    #[should_panic(expected = "leaked MyRc allocations")]
+    // the cycle is leaked for real, which Miri reports as an error of its own
+    #[cfg_attr(miri, ignore)]
+    fn rc_diagnostics_leak_check_catches_cycle() {
+        let _check = LeakCheck::new();
+
+        let first = MyRc::new(Node {next: RefCell::new(None)});
+        let second = MyRc::new(Node {next: RefCell::new(Some(first.clone()))});
+        *first.next.borrow_mut() = Some(second);
+    }
+
+
+    #[test]
+    fn rc_diagnostics_leak_check_passes_clean_scope() {
+        let _check = LeakCheck::new();
+
+        let first = MyRc::new(Node {next: RefCell::new(None)});
+        let second = MyRc::new(Node {next: RefCell::new(Some(first.clone()))});
+        drop(second);
+        assert_eq!(MyRc::strong_count(&first), 1);
+    }
+}
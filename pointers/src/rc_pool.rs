@@ -0,0 +1,217 @@
+/*
+- An `RcPool<T>` recycles the allocations behind `MyRc<T>`s. It is an `Allocator`:
+`pool.new_rc(value)` creates a `MyRc<T, &RcPool<T>>` whose allocation comes from the pool, and when
+the last reference to it is gone, the memory goes back to the pool instead of to the allocator
+underneath. The next `new_rc` takes it from there again, so a workload that creates and drops
+millions of short-lived values reaches the real allocator only about as often as it holds many of
+them at once.
+
+- The pool only recycles blocks of the exact layout of a `MyRc<T>` allocation; anything else is
+passed through to the allocator underneath, `Global` unless `with_limit_in` picks another one. A
+zero-sized `T` never reaches the pool at all, since `MyRc` takes its counts from its own slab.
+
+- Free blocks are kept in an intrusive list: the first word of a free block points at the next
+one, so the pool itself never allocates, and recycling a block is a couple of pointer writes. At
+most `limit` free blocks are kept (1024 by default); beyond that, freed blocks go back to the
+allocator underneath, so a burst of values does not pin that much memory forever. `clear` and
+dropping the pool hand every free block back.
+
+- The `MyRc`s borrow the pool, so they can never outlive it. Like `MyRc` itself the pool is
+single-threaded: it is neither `Send` nor `Sync`.
+*/
+use std::alloc::Layout;
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::ptr::{self, NonNull};
+use crate::allocator::{AllocError, Allocator, Global};
+use crate::rc::{self, MyRc};
+
+
+const DEFAULT_LIMIT: usize = 1024;
+
+
+pub struct RcPool<T, A: Allocator = Global> {
+    // the first free block, whose first word points at the next one
+    free: Cell<Option<NonNull<u8>>>,
+    cached: Cell<usize>,
+    limit: usize,
+    alloc: A,
+    _marker: PhantomData<T>
+}
+
+
+impl<T> RcPool<T> {
+    pub fn new() -> Self {
+        Self::with_limit(DEFAULT_LIMIT)
+    }
+
+
+    pub fn with_limit(limit: usize) -> Self {
+        Self::with_limit_in(limit, Global)
+    }
+}
+
+
+impl<T, A: Allocator> RcPool<T, A> {
+    pub fn with_limit_in(limit: usize, alloc: A) -> Self {
+        RcPool {
+            free: Cell::new(None),
+            cached: Cell::new(0),
+            limit,
+            alloc,
+            _marker: PhantomData
+        }
+    }
+
+
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn new_rc(&self, value: T) -> MyRc<T, &Self> {
+        MyRc::new_in(value, self)
+    }
+
+
+    // the number of free blocks waiting to be reused
+    pub fn cached(&self) -> usize {
+        self.cached.get()
+    }
+
+
+    pub fn clear(&self) {
+        while let Some(block) = self.pop() {
+            unsafe { self.alloc.deallocate(block, rc::inner_layout::<T>()) };
+        }
+    }
+
+
+    fn pop(&self) -> Option<NonNull<u8>> {
+        let block = self.free.get()?;
+        self.free.set(unsafe { block.cast::<Option<NonNull<u8>>>().read() });
+        self.cached.set(self.cached.get() - 1);
+        Some(block)
+    }
+}
+
+
+unsafe impl<T, A: Allocator> Allocator for RcPool<T, A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout != rc::inner_layout::<T>() {
+            return self.alloc.allocate(layout);
+        }
+
+        match self.pop() {
+            Some(block) => Ok(NonNull::slice_from_raw_parts(block, layout.size())),
+            None => self.alloc.allocate(layout)
+        }
+    }
+
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout != rc::inner_layout::<T>() || self.cached.get() >= self.limit {
+            return self.alloc.deallocate(ptr, layout);
+        }
+
+        // the block holds two counts at least, so there is room for a pointer at its start
+        ptr::write(ptr.cast::<Option<NonNull<u8>>>().as_ptr(), self.free.get());
+        self.free.set(Some(ptr));
+        self.cached.set(self.cached.get() + 1);
+    }
+}
+
+
+impl<T> Default for RcPool<T> {
+    fn default() -> Self {
+        RcPool::new()
+    }
+}
+
+
+impl<T, A: Allocator> Drop for RcPool<T, A> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::Layout;
+    use std::cell::Cell;
+    use std::ptr::NonNull;
+    use crate::allocator::{AllocError, Allocator, Global};
+    use crate::rc::MyRc;
+    use crate::rc_pool::RcPool;
+
+
+    #[derive(Default)]
+    struct CountingAllocator {
+        allocations: Cell<usize>,
+        deallocations: Cell<usize>
+    }
+
+
+    unsafe impl Allocator for CountingAllocator {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.allocations.set(self.allocations.get() + 1);
+            Global.allocate(layout)
+        }
+
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.deallocations.set(self.deallocations.get() + 1);
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+
+    #[test]
+    fn rc_pool_recycles_allocations() {
+        let heap = CountingAllocator::default();
+        let pool = RcPool::with_limit_in(16, &heap);
+
+        // one value at a time needs a single block, however many values come and go
+        for value in 0..1_000 {
+            let my_rc = pool.new_rc(value);
+            let clone = my_rc.clone();
+            assert_eq!(*clone, value);
+        }
+        assert_eq!((heap.allocations.get(), heap.deallocations.get()), (1, 0));
+        assert_eq!(pool.cached(), 1);
+
+        // a weak reference keeps its block out of the pool until it is gone too
+        let weak = MyRc::downgrade(&pool.new_rc(7));
+        assert_eq!(pool.cached(), 0);
+        drop(weak);
+        assert_eq!(pool.cached(), 1);
+
+        // beyond the limit, freed blocks go back to the allocator underneath
+        let values: Vec<_> = (0..20).map(|value| pool.new_rc(value)).collect();
+        assert_eq!(heap.allocations.get(), 20);
+        drop(values);
+        assert_eq!((pool.cached(), heap.deallocations.get()), (16, 4));
+
+        drop(pool);
+        assert_eq!(heap.deallocations.get(), 20);
+    }
+
+
+    #[test]
+    fn rc_pool_passes_other_layouts_through() {
+        let heap = CountingAllocator::default();
+        let pool: RcPool<u64, _> = RcPool::with_limit_in(16, &heap);
+
+        // a `MyRc<[u8; 64]>` does not fit the blocks of a `MyRc<u64>`
+        let bytes = MyRc::new_in([0u8; 64], &pool);
+        drop(bytes);
+        assert_eq!((heap.allocations.get(), heap.deallocations.get(), pool.cached()), (1, 1, 0));
+
+        let mut value = pool.new_rc(1);
+        let shared = value.clone();
+        *MyRc::make_mut(&mut value) += 1;
+        assert_eq!((*value, *shared), (2, 1));
+        drop((value, shared));
+
+        pool.clear();
+        assert_eq!(pool.cached(), 0);
+        assert_eq!(heap.allocations.get(), heap.deallocations.get());
+    }
+}
@@ -0,0 +1,158 @@
+/*
+- `RcRef<T, U>` is an owning reference: it keeps a `MyRc<T>` alive and dereferences to some `U`
+inside of it, e.g. one field of a shared struct. A function can then hand out a borrow of
+`Rc`-owned data without a lifetime tied to the caller and without exposing the whole struct.
+
+- `RcRef::new(rc)` starts out pointing at the whole value, and `map` moves the reference to a part
+of it, as in `RcRef::new(config).map(|config| &config.name)`. `try_map` does the same for a
+projection that can fail, such as looking up a key, and gives the `RcRef` back if it does.
+
+- The projected reference stays valid because the value never moves while it is shared: the heap
+allocation stays where it is for as long as the `MyRc<T>` lives, and nothing can get a `&mut T`
+through an `RcRef`, since `MyRc::get_mut` needs the `MyRc<T>` itself, which the `RcRef` keeps to
+itself.
+
+- Cloning an `RcRef<T, U>` clones the `MyRc<T>` and keeps pointing at the same `U`, and `owner`
+gives access to the `MyRc<T>`, e.g. to clone it.
+*/
+use std::fmt;
+use std::ops::Deref;
+use std::ptr::NonNull;
+use crate::rc::MyRc;
+
+
+pub struct RcRef<T: ?Sized, U: ?Sized = T> {
+    owner: MyRc<T>,
+    // points into the value owned by `owner`
+    value: NonNull<U>
+}
+
+
+impl<T: ?Sized> RcRef<T> {
+    pub fn new(owner: MyRc<T>) -> Self {
+        let value = NonNull::from(&*owner);
+        RcRef {owner, value}
+    }
+}
+
+
+impl<T: ?Sized, U: ?Sized> RcRef<T, U> {
+    pub fn map<V: ?Sized>(self, f: impl FnOnce(&U) -> &V) -> RcRef<T, V> {
+        // `f` may only return something borrowed from its argument (or something `'static`), so
+        // the new reference stays inside of the value as well
+        let value = NonNull::from(f(&*self));
+        RcRef {owner: self.owner, value}
+    }
+
+
+    pub fn try_map<V: ?Sized>(self, f: impl FnOnce(&U) -> Option<&V>) -> Result<RcRef<T, V>, Self> {
+        match f(&*self) {
+            Some(value) => {
+                let value = NonNull::from(value);
+                Ok(RcRef {owner: self.owner, value})
+            },
+            None => Err(self)
+        }
+    }
+
+
+    pub fn owner(&self) -> &MyRc<T> {
+        &self.owner
+    }
+
+
+    pub fn into_owner(self) -> MyRc<T> {
+        self.owner
+    }
+}
+
+
+impl<T: ?Sized, U: ?Sized> Clone for RcRef<T, U> {
+    fn clone(&self) -> Self {
+        RcRef {
+            owner: self.owner.clone(),
+            value: self.value
+        }
+    }
+}
+
+
+impl<T: ?Sized, U: ?Sized> Deref for RcRef<T, U> {
+    type Target = U;
+
+
+    fn deref(&self) -> &Self::Target {
+        // `owner` keeps the value alive and unchanged for as long as this `RcRef` exists
+        unsafe { self.value.as_ref() }
+    }
+}
+
+
+impl<T: ?Sized, U: ?Sized + fmt::Debug> fmt::Debug for RcRef<T, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+
+impl<T: ?Sized, U: ?Sized + fmt::Display> fmt::Display for RcRef<T, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::rc::MyRc;
+    use crate::rc_ref::RcRef;
+
+
+    struct Config {
+        name: String,
+        settings: HashMap<&'static str, String>
+    }
+
+
+    fn config() -> MyRc<Config> {
+        MyRc::new(Config {
+            name: String::from("server"),
+            settings: HashMap::from([("port", String::from("8080"))])
+        })
+    }
+
+
+    #[test]
+    fn rc_ref_map() {
+        let config = config();
+
+        // the name outlives the local `MyRc`, because the `RcRef` owns a clone of it
+        let name: RcRef<Config, str> = RcRef::new(config.clone()).map(|config| &config.name).map(|name| name.as_str());
+        drop(config);
+
+        assert_eq!(&*name, "server");
+        assert_eq!(MyRc::strong_count(name.owner()), 1);
+
+        let name_clone = name.clone();
+        assert_eq!(MyRc::strong_count(name.owner()), 2);
+        assert_eq!(name_clone.to_string(), "server");
+        assert_eq!(name_clone.into_owner().settings.len(), 1);
+    }
+
+
+    #[test]
+    fn rc_ref_try_map() {
+        let settings = RcRef::new(config()).map(|config| &config.settings);
+
+        let Ok(port) = settings.clone().try_map(|settings| settings.get("port")) else {
+            panic!("the port is set");
+        };
+        assert_eq!(*port, "8080");
+
+        let Err(settings) = settings.try_map(|settings| settings.get("host")) else {
+            panic!("the host is not set");
+        };
+        assert_eq!(settings.len(), 1);
+    }
+}
@@ -0,0 +1,152 @@
+/*
+- The slab holds the allocations behind `MyRc`s of zero-sized values. Such an allocation is just
+the two counts, so all of them have the same layout, and `MyRc::new(())` takes a block from the
+slab instead of going to the allocator. Every value still gets counts of its own: two `MyRc<()>`s
+are never `ptr_eq`, and a `MyWeak<()>` only upgrades while its own value is alive.
+
+- Each thread keeps a free list of blocks, linked through their first word like in `RcPool`.
+Taking a block and giving it back are a couple of pointer writes on the calling thread's list, with
+no lock and no allocator involved. When the list runs empty, `CHUNK` blocks are allocated in one
+piece and linked into it, so the allocator is reached once per `CHUNK` values alive at the same
+time, not once per value.
+
+- When a thread exits, its free list is handed over to the orphans, a shared list of chains that a
+thread which runs empty adopts before it allocates a new chunk. Blocks are never given back to the
+allocator, since the blocks of one chunk end up spread over the lists of any number of threads, but
+threads coming and going do not add up: the slab holds about as many blocks as there were
+zero-sized values alive at once, rounded up to whole chunks.
+
+- A thread-local destructor can still drop or create a `MyRc<()>` after the free list of its
+thread is gone. A block given back at that point goes straight to the orphans, and a block taken is
+allocated on its own.
+*/
+use std::alloc::{self, Layout};
+use std::cell::Cell;
+use std::ptr::NonNull;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use crate::allocator::AllocError;
+
+
+// the layout of a block: the counts of an `RcInner` whose value takes no space
+pub(crate) const BLOCK: Layout = Layout::new::<[usize; 2]>();
+
+const CHUNK: usize = 64;
+
+
+struct FreeList {
+    // the first free block, whose first word points at the next one
+    head: Cell<Option<NonNull<u8>>>
+}
+
+
+// the first block of a free list that no thread owns
+struct Chain(NonNull<u8>);
+
+
+// the blocks of a chain are only touched by the thread that adopts it
+unsafe impl Send for Chain {}
+
+
+static ORPHANS: Mutex<Vec<Chain>> = Mutex::new(Vec::new());
+
+
+thread_local! {
+    static FREE: FreeList = const { FreeList {head: Cell::new(None)} };
+}
+
+
+pub(crate) fn allocate() -> Result<NonNull<u8>, AllocError> {
+    FREE.try_with(FreeList::take).unwrap_or_else(|_| allocate_chain(1))
+}
+
+
+// `block` must come from `allocate` and must not be used afterwards
+pub(crate) unsafe fn deallocate(block: NonNull<u8>) {
+    if FREE.try_with(|free| free.give_back(block)).is_err() {
+        block.cast::<Option<NonNull<u8>>>().write(None);
+        orphans().push(Chain(block));
+    }
+}
+
+
+impl FreeList {
+    fn take(&self) -> Result<NonNull<u8>, AllocError> {
+        let block = match self.head.get() {
+            Some(block) => block,
+            None => match orphans().pop() {
+                Some(Chain(block)) => block,
+                None => allocate_chain(CHUNK)?
+            }
+        };
+
+        self.head.set(unsafe { block.cast::<Option<NonNull<u8>>>().read() });
+        Ok(block)
+    }
+
+
+    fn give_back(&self, block: NonNull<u8>) {
+        unsafe { block.cast::<Option<NonNull<u8>>>().write(self.head.get()) };
+        self.head.set(Some(block));
+    }
+}
+
+
+impl Drop for FreeList {
+    fn drop(&mut self) {
+        if let Some(block) = self.head.take() {
+            orphans().push(Chain(block));
+        }
+    }
+}
+
+
+// the orphans stay usable after a panic elsewhere, since pushing and popping a chain cannot leave
+// the list half updated
+fn orphans() -> MutexGuard<'static, Vec<Chain>> {
+    ORPHANS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+
+// allocates `len` blocks in one piece and links them into a chain, the first block first
+fn allocate_chain(len: usize) -> Result<NonNull<u8>, AllocError> {
+    let size = BLOCK.size().checked_mul(len).ok_or(AllocError)?;
+    let layout = Layout::from_size_align(size, BLOCK.align()).map_err(|_| AllocError)?;
+    let chunk = NonNull::new(unsafe { alloc::alloc(layout) }).ok_or(AllocError)?;
+
+    for index in 0..len {
+        unsafe {
+            let next = (index + 1 < len).then(|| chunk.add((index + 1) * BLOCK.size()));
+            chunk.add(index * BLOCK.size()).cast::<Option<NonNull<u8>>>().write(next);
+        }
+    }
+
+    Ok(chunk)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::rc_slab::{self, CHUNK};
+
+
+    #[test]
+    fn rc_slab_reuses_blocks() {
+        let block = rc_slab::allocate().unwrap();
+        unsafe { rc_slab::deallocate(block) };
+
+        // the block given back is the next one handed out
+        let again = rc_slab::allocate().unwrap();
+        assert_eq!(again, block);
+
+        // a chunk covers many blocks, which are all distinct
+        let mut blocks: Vec<_> = (0..CHUNK).map(|_| rc_slab::allocate().unwrap()).collect();
+        blocks.push(again);
+        blocks.sort();
+        blocks.dedup();
+        assert_eq!(blocks.len(), CHUNK + 1);
+
+        for block in blocks {
+            unsafe { rc_slab::deallocate(block) };
+        }
+    }
+}
@@ -0,0 +1,249 @@
+/*
+- An `RcSlice` is a view of a range of bytes in a shared `MyRc<[u8]>` buffer, in the spirit of
+`Bytes` from the bytes crate. `slice` and `split_at` hand out views of parts of a view without
+copying a byte: they only clone the `MyRc` and narrow the range, so a parser can read a whole
+message into one buffer and hand out its fields as independent values.
+
+- Every view keeps the whole buffer alive, not just its own bytes. A small view of a large buffer
+that is kept around for long should be copied into a buffer of its own with `RcSlice::from(&*view)`.
+
+- `RcSlice` derefs to `[u8]`, and `Debug`, `PartialEq`, `Eq`, `PartialOrd`, `Ord` and `Hash` go by
+the viewed bytes, like for `Vec<u8>`, so two views are equal when they show the same bytes, no
+matter which buffers they come from.
+
+- Ranges follow the rules of slice indexing: they are relative to the view, and a range that is
+out of bounds or decreasing panics.
+*/
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Bound, Deref, RangeBounds};
+use crate::rc::MyRc;
+
+
+#[derive(Clone)]
+pub struct RcSlice {
+    buffer: MyRc<[u8]>,
+    start: usize,
+    end: usize
+}
+
+
+impl RcSlice {
+    pub fn new(buffer: MyRc<[u8]>) -> Self {
+        let end = buffer.len();
+        RcSlice {buffer, start: 0, end}
+    }
+
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+
+    #[track_caller]
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Self {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.checked_add(1).expect("range start overflows"),
+            Bound::Unbounded => 0
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end.checked_add(1).expect("range end overflows"),
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.len()
+        };
+
+        assert!(start <= end, "slice index starts at {} but ends at {}", start, end);
+        assert!(end <= self.len(), "range end index {} out of range for slice of length {}", end, self.len());
+
+        RcSlice {
+            buffer: self.buffer.clone(),
+            start: self.start + start,
+            end: self.start + end
+        }
+    }
+
+
+    // the views of the bytes in front of `mid` and from `mid` on
+    #[track_caller]
+    pub fn split_at(&self, mid: usize) -> (Self, Self) {
+        assert!(mid <= self.len(), "mid {} out of range for slice of length {}", mid, self.len());
+        (self.slice(..mid), self.slice(mid..))
+    }
+
+
+    // the buffer the view points into, which may be larger than the view
+    pub fn buffer(&self) -> &MyRc<[u8]> {
+        &self.buffer
+    }
+}
+
+
+impl Deref for RcSlice {
+    type Target = [u8];
+
+
+    fn deref(&self) -> &[u8] {
+        &self.buffer[self.start..self.end]
+    }
+}
+
+
+impl AsRef<[u8]> for RcSlice {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+
+impl Borrow<[u8]> for RcSlice {
+    fn borrow(&self) -> &[u8] {
+        self
+    }
+}
+
+
+impl Default for RcSlice {
+    fn default() -> Self {
+        RcSlice::from(Vec::new())
+    }
+}
+
+
+impl From<MyRc<[u8]>> for RcSlice {
+    fn from(buffer: MyRc<[u8]>) -> Self {
+        RcSlice::new(buffer)
+    }
+}
+
+
+impl From<Vec<u8>> for RcSlice {
+    fn from(bytes: Vec<u8>) -> Self {
+        RcSlice::new(MyRc::from(bytes))
+    }
+}
+
+
+impl From<&[u8]> for RcSlice {
+    fn from(bytes: &[u8]) -> Self {
+        RcSlice::new(MyRc::from(bytes))
+    }
+}
+
+
+impl From<&str> for RcSlice {
+    fn from(string: &str) -> Self {
+        RcSlice::from(string.as_bytes())
+    }
+}
+
+
+impl fmt::Debug for RcSlice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        <[u8] as fmt::Debug>::fmt(self, f)
+    }
+}
+
+
+impl PartialEq for RcSlice {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+
+impl Eq for RcSlice {}
+
+
+impl PartialEq<[u8]> for RcSlice {
+    fn eq(&self, other: &[u8]) -> bool {
+        **self == *other
+    }
+}
+
+
+impl PartialOrd for RcSlice {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+
+impl Ord for RcSlice {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+
+impl Hash for RcSlice {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::rc::MyRc;
+    use crate::rc_slice::RcSlice;
+
+
+    #[test]
+    fn rc_slice_views_share_buffer() {
+        let message = RcSlice::from("GET /index.html HTTP/1.1");
+
+        // a tiny parser that hands out its fields as views of the one buffer
+        let (method, rest) = message.split_at(3);
+        let path = rest.slice(1..=11);
+        let version = rest.slice(13..);
+
+        assert_eq!(&*method, b"GET");
+        assert_eq!(&*path, b"/index.html");
+        assert_eq!(&*version, b"HTTP/1.1");
+        assert_eq!(version.slice(5..).len(), 3);
+        assert!(version.slice(8..).is_empty());
+
+        assert!(MyRc::ptr_eq(path.buffer(), message.buffer()));
+        assert_eq!(MyRc::strong_count(message.buffer()), 5);
+
+        // a view outlives the view it was taken from, and keeps the buffer alive
+        drop(message);
+        drop(rest);
+        assert_eq!(&*path, b"/index.html");
+    }
+
+
+    #[test]
+    fn rc_slice_traits_compare_bytes() {
+        let first = RcSlice::from(&b"abcabc"[..]);
+        let (left, right) = first.split_at(3);
+
+        assert_eq!(left, right);
+        assert_eq!(left, *b"abc".as_slice());
+        assert!(first.slice(1..) > first);
+        assert_eq!(format!("{:?}", left), "[97, 98, 99]");
+        assert_eq!(RcSlice::default().len(), 0);
+
+        let views: HashSet<RcSlice> = [left, right, RcSlice::from("abc")].into_iter().collect();
+        assert_eq!(views.len(), 1);
+        assert!(views.contains(b"abc".as_slice()));
+    }
+
+
+    #[test]
+    #[should_panic(expected = "range end index 4 out of range for slice of length 3")]
+    fn rc_slice_out_of_bounds_panics() {
+        let view = RcSlice::from("abcdef").slice(3..);
+
+        view.slice(..4);
+    }
+}
@@ -8,174 +8,1578 @@ at runtime rather than compile time.
 
 - `RefCell<T>` uses `borrow()` for immutable access and `borrow_mut()` for mutable access.
 These methods track borrows and panic if the borrowing rules are violated (e.g., if you try to
-borrow mutably while there’s an active immutable borrow).
+borrow mutably while there’s an active immutable borrow). They are `#[track_caller]`, so the
+panic points at the conflicting call site rather than at the cell's internals.
 
 - Like `Cell<T>`, `RefCell<T>` is marked as `!Sync` and cannot be shared between threads due to
 the potential for unsynchronized mutation.
 
+- `try_borrow()` and `try_borrow_mut()` report a violation as a `BorrowError` or `BorrowMutError`
+instead, so callers can recover from a conflicting borrow.
+
+- `try_borrow_unguarded()` is the unsafe escape hatch: it checks for a mutable borrow once and then
+hands out a plain `&T` without recording a borrow, so hot read paths pay for one comparison and
+no guard. The caller takes over the job of keeping mutable borrows away while the reference lives.
+
+- `with_borrow()` and `with_borrow_mut()` scope a borrow to a closure: the guard is released as
+soon as the closure returns, so it cannot accidentally be held across unrelated code.
+
+- The guards `Ref<'_, T>` and `RefMut<'_, T>` are public so functions can return borrows of data
+stored in a cell. A guard keeps the cell borrowed for exactly as long as it is alive, can never
+outlive the cell, and is `!Send` and `!Sync`. `Debug` and `Display` forward to the borrowed value,
+so a guard prints exactly like the value it points at.
+
+- `Ref::map` narrows a shared borrow down to a part of the value (e.g. one field), and the cell
+stays borrowed until the projected guard is dropped. `RefMut::map` does the same for exclusive
+borrows. `map_split` turns one guard into two guards over disjoint parts (e.g. two halves of a
+slice), and the cell stays borrowed until both of them are dropped.
+
+- `Ref::clone` duplicates a shared guard without going back through the cell, so one borrow can
+be handed to several readers.
+
+- `filter_map` is the fallible projection: it narrows a guard into an `Option` or an enum variant,
+and hands the original guard back when there is nothing to project into.
+
+- `replace()`, `replace_with()`, `swap()` and `take()` exchange values through temporary mutable
+borrows, so they panic just like `borrow_mut()` when a cell involved is already borrowed.
+
+- `into_inner()` and `get_mut()` skip the runtime check entirely: owning the cell, or holding a
+`&mut` to it, statically rules out any outstanding borrow.
+
+- `leak` turns a guard into a plain reference that lives as long as the cell, leaving the cell
+borrowed for good. `undo_leak()` resets the borrow state once exclusive access proves that the
+leaked references are gone.
+
+- `Clone`, `PartialEq`, `Eq`, `PartialOrd` and `Ord` take shared borrows of the cells involved, so
+they panic if a cell is mutably borrowed. `Hash` is deliberately not implemented: the contents can
+change through a shared reference while the cell sits in a `HashMap` or `HashSet`, silently
+breaking the collection's invariants. Hash a snapshot of the borrowed value instead.
+
+- With the `debug-refcell` feature enabled, every cell records where each of its outstanding
+borrows was taken. A conflicting borrow then reports those locations in its panic message and in
+`BorrowError`/`BorrowMutError` (`conflicting_borrows()`), turning "already borrowed" into a
+pointer at the guard that is held too long.
+
+- `RefCell<T>` also accepts unsized types, which allows runtime-checked borrows of trait objects
+and slices behind a pointer, e.g. `Box<RefCell<dyn Trait>>` or `&RefCell<[T]>`. Methods that move
+the value in or out remain restricted to sized types.
+
+- The borrow state is a single `isize`, like in std: zero means unshared, positive values count
+shared borrows and negative values count exclusive borrows. Every borrow and release is a single
+integer update, and the counts are guarded so that they can never overflow into each other.
+
+- With the `tracing` feature enabled, every borrow that is acquired, released or denied emits a
+`tracing` event carrying the cell's address and its shared and exclusive counts, so a storm of
+conflicting borrows can be followed through an event loop without a debugger.
+
+- With the `serde` feature enabled, a `RefCell<T>` serializes as the value it holds by taking a
+shared borrow for the duration of the call. A cell that is mutably borrowed at that point fails
+with a serialization error rather than panicking.
+
 - At its core, `RefCell<T>` leverages `UnsafeCell<T>` to provide safe interior mutability while
 enforcing borrowing rules dynamically.
 */
+use std::{fmt, mem, ptr};
+use std::error::Error;
+use std::cmp::Ordering;
+use std::ptr::NonNull;
+use std::marker::PhantomData;
+#[cfg(feature = "debug-refcell")]
+use std::panic::Location;
 use std::ops::{Deref, DerefMut};
 use std::cell::{Cell, UnsafeCell};
 
 
-pub struct MyRefCell<T> {
-    value: UnsafeCell<T>,
-    state: Cell<RefState>
+// the value comes last so that `T` may be unsized (`[T]`, `str`, `dyn Trait`)
+pub struct MyRefCell<T: ?Sized> {
+    state: Cell<BorrowFlag>,
+    #[cfg(feature = "debug-refcell")]
+    borrowed_at: BorrowLocations,
+    value: UnsafeCell<T>
 }
 
 
 impl<T> MyRefCell<T> {
-    pub fn new(value: T) -> Self {
+    pub const fn new(value: T) -> Self {
         Self {
-            value: UnsafeCell::new(value),
-            state: Cell::new(RefState::Unshared)
+            state: Cell::new(UNSHARED),
+            #[cfg(feature = "debug-refcell")]
+            borrowed_at: BorrowLocations::new(),
+            value: UnsafeCell::new(value)
+        }
+    }
+
+
+    #[track_caller]
+    pub fn replace(&self, value: T) -> T {
+        mem::replace(&mut *self.borrow_mut(), value)
+    }
+
+
+    #[track_caller]
+    pub fn replace_with<F>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut T) -> T
+    {
+        let mut borrow = self.borrow_mut();
+        let replacement = f(&mut borrow);
+        mem::replace(&mut *borrow, replacement)
+    }
+
+
+    #[track_caller]
+    pub fn swap(&self, other: &MyRefCell<T>) {
+        // swapping a cell with itself is a no-op, but it must still fail if the cell is borrowed
+        if ptr::eq(self, other) {
+            let _borrow = self.borrow_mut();
+            return;
+        }
+
+        mem::swap(&mut *self.borrow_mut(), &mut *other.borrow_mut());
+    }
+
+
+    #[track_caller]
+    pub fn take(&self) -> T
+    where
+        T: Default
+    {
+        self.replace(T::default())
+    }
+
+
+    pub fn into_inner(self) -> T {
+        // taking `self` by value proves that no `Ref` or `RefMut` can still be alive
+        self.value.into_inner()
+    }
+}
+
+
+impl<T: ?Sized> MyRefCell<T> {
+    #[track_caller]
+    pub fn borrow(&self) -> Ref<'_, T> {
+        match self.try_borrow() {
+            Ok(borrow) => borrow,
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+
+    #[track_caller]
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        match self.try_borrow_mut() {
+            Ok(borrow) => borrow,
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+
+    #[track_caller]
+    pub fn try_borrow(&self) -> Result<Ref<'_, T>, BorrowError> {
+        Ok(Ref {
+            borrow: BorrowRef::new(self)?,
+            value: unsafe { NonNull::new_unchecked(self.value.get()) }
+        })
+    }
+
+
+    #[track_caller]
+    pub fn try_borrow_mut(&self) -> Result<RefMut<'_, T>, BorrowMutError> {
+        Ok(RefMut {
+            borrow: BorrowRefMut::new(self)?,
+            value: unsafe { NonNull::new_unchecked(self.value.get()) },
+            _marker: PhantomData
+        })
+    }
+
+
+    /// # Safety
+    ///
+    /// The borrow state is not updated, so nothing stops a later `borrow_mut()` from succeeding
+    /// while the returned reference is alive. The caller must ensure that the cell is not mutably
+    /// borrowed, and that no `&mut` to the value is created, until that reference is dropped.
+    pub unsafe fn try_borrow_unguarded(&self) -> Result<&T, BorrowError> {
+        if self.state.get() < UNSHARED {
+            return Err(BorrowError {
+                #[cfg(feature = "debug-refcell")]
+                conflicts: self.borrowed_at.get(),
+                _private: ()
+            });
+        }
+
+        Ok(&*self.value.get())
+    }
+
+
+    #[track_caller]
+    pub fn with_borrow<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.borrow())
+    }
+
+
+    #[track_caller]
+    pub fn with_borrow_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut self.borrow_mut())
+    }
+
+
+    // the pointer bypasses the borrow state entirely; dereferencing it is only sound while no
+    // guard that could conflict with the access is alive
+    pub const fn as_ptr(&self) -> *mut T {
+        self.value.get()
+    }
+
+
+    pub fn get_mut(&mut self) -> &mut T {
+        // `&mut self` is checked at compile time, so the borrow state is neither read nor updated
+        self.value.get_mut()
+    }
+
+
+    pub fn undo_leak(&mut self) -> &mut T {
+        // `&mut self` proves that every reference handed out by `leak` is dead by now
+        self.state.set(UNSHARED);
+        #[cfg(feature = "debug-refcell")]
+        self.borrowed_at.clear();
+        self.value.get_mut()
+    }
+}
+
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for MyRefCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // a cell that is mutably borrowed is reported instead of panicking, so formatting a
+        // struct that contains cells is always safe
+        match self.try_borrow() {
+            Ok(borrow) => f.debug_struct("MyRefCell").field("value", &&*borrow).finish(),
+            Err(_) => f.debug_struct("MyRefCell").field("value", &format_args!("<borrowed>")).finish()
+        }
+    }
+}
+
+
+impl<T: Clone> Clone for MyRefCell<T> {
+    #[track_caller]
+    fn clone(&self) -> Self {
+        MyRefCell::new(self.borrow().clone())
+    }
+}
+
+
+impl<T: Default> Default for MyRefCell<T> {
+    fn default() -> Self {
+        MyRefCell::new(T::default())
+    }
+}
+
+
+impl<T: ?Sized + PartialEq> PartialEq for MyRefCell<T> {
+    #[track_caller]
+    fn eq(&self, other: &Self) -> bool {
+        *self.borrow() == *other.borrow()
+    }
+}
+
+
+impl<T: ?Sized + Eq> Eq for MyRefCell<T> {}
+
+
+impl<T: ?Sized + PartialOrd> PartialOrd for MyRefCell<T> {
+    #[track_caller]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.borrow().partial_cmp(&*other.borrow())
+    }
+}
+
+
+impl<T: ?Sized + Ord> Ord for MyRefCell<T> {
+    #[track_caller]
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.borrow().cmp(&*other.borrow())
+    }
+}
+
+
+impl<T> From<T> for MyRefCell<T> {
+    fn from(value: T) -> Self {
+        MyRefCell::new(value)
+    }
+}
+
+
+#[cfg(feature = "serde")]
+impl<T: ?Sized + serde::Serialize> serde::Serialize for MyRefCell<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.try_borrow() {
+            Ok(borrow) => borrow.serialize(serializer),
+            Err(error) => Err(serde::ser::Error::custom(error))
         }
     }
+}
+
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for MyRefCell<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(MyRefCell::new)
+    }
+}
+
+
+// the borrow state packed into a single integer: zero means unshared, a positive value counts the
+// shared borrows and a negative value counts the exclusive borrows (more than one exclusive borrow
+// only exists after `RefMut::map_split`, and each of them covers a disjoint part of the value)
+type BorrowFlag = isize;
+
 
+const UNSHARED: BorrowFlag = 0;
 
-    pub fn borrow(&self) -> Option<Ref<'_, T>> {
-        match self.state.get() {
-            RefState::Unshared => {
-                self.state.set(RefState::Shared(1));
-                Some(Ref {refcell: self})
-            },
-            RefState::Shared(count) => {
-                self.state.set(RefState::Shared(count + 1));
-                Some(Ref {refcell: self})
-            },
-            RefState::Exclusive => None
+
+// the source locations of all outstanding borrows of one cell; a borrow that conflicts with them
+// reports these locations, which points straight at the guard that is being held too long
+#[cfg(feature = "debug-refcell")]
+struct BorrowLocations {
+    locations: UnsafeCell<Vec<&'static Location<'static>>>
+}
+
+
+#[cfg(feature = "debug-refcell")]
+impl BorrowLocations {
+    const fn new() -> Self {
+        Self {
+            locations: UnsafeCell::new(Vec::new())
         }
     }
 
 
-    pub fn borrow_mut(&self) -> Option<RefMut<'_, T>> {
-        if let RefState::Unshared = self.state.get() {
-            self.state.set(RefState::Exclusive);
-            Some(RefMut {refcell: self})
-        } else {
-            None
+    // none of these methods call back into user code, so no two of them can overlap and
+    // alias the vector
+    fn push(&self, location: &'static Location<'static>) {
+        unsafe { (*self.locations.get()).push(location) }
+    }
+
+
+    fn remove(&self, location: &'static Location<'static>) {
+        let locations = unsafe { &mut *self.locations.get() };
+
+        if let Some(index) = locations.iter().position(|borrowed_at| *borrowed_at == location) {
+            locations.remove(index);
         }
     }
+
+
+    fn clear(&mut self) {
+        self.locations.get_mut().clear();
+    }
+
+
+    fn get(&self) -> Vec<&'static Location<'static>> {
+        unsafe { (*self.locations.get()).clone() }
+    }
+}
+
+
+// emits a `tracing` event describing a borrow of the cell whose state is `$state`; expands to
+// nothing without the `tracing` feature
+macro_rules! trace_borrow {
+    ($level:ident, $state:expr, $kind:literal, $event:literal) => {
+        #[cfg(feature = "tracing")]
+        {
+            let state: &Cell<BorrowFlag> = $state;
+            let flag = state.get();
+            tracing::event!(
+                tracing::Level::$level,
+                cell = ?(state as *const Cell<BorrowFlag>),
+                kind = $kind,
+                shared = flag.max(UNSHARED),
+                exclusive = -flag.min(UNSHARED),
+                $event
+            );
+        }
+    };
 }
 
 
-#[derive(Debug, Copy, Clone, PartialEq)]
-enum RefState {
-    Unshared,
-    Shared(usize),
-    Exclusive
+// tracks a single shared borrow of the cell's state and releases it on drop, independently of
+// which part of the value a `Ref` currently points at
+struct BorrowRef<'refcell> {
+    state: &'refcell Cell<BorrowFlag>,
+    #[cfg(feature = "debug-refcell")]
+    borrowed_at: (&'refcell BorrowLocations, &'static Location<'static>)
+}
+
+
+impl<'refcell> BorrowRef<'refcell> {
+    #[track_caller]
+    fn new<T: ?Sized>(refcell: &'refcell MyRefCell<T>) -> Result<Self, BorrowError> {
+        let state = &refcell.state;
+        let flag = state.get().wrapping_add(1);
+
+        // a non-positive result means the cell was exclusively borrowed, or that `isize::MAX`
+        // shared borrows already exist and one more would wrap around into the exclusive range
+        if flag <= UNSHARED {
+            trace_borrow!(DEBUG, state, "shared", "borrow denied");
+            return Err(BorrowError {
+                #[cfg(feature = "debug-refcell")]
+                conflicts: refcell.borrowed_at.get(),
+                _private: ()
+            });
+        }
+
+        state.set(flag);
+        trace_borrow!(TRACE, state, "shared", "borrow acquired");
+
+        #[cfg(feature = "debug-refcell")]
+        refcell.borrowed_at.push(Location::caller());
+
+        Ok(BorrowRef {
+            state,
+            #[cfg(feature = "debug-refcell")]
+            borrowed_at: (&refcell.borrowed_at, Location::caller())
+        })
+    }
 }
 
 
-struct Ref<'refcell, T> {
-    refcell: &'refcell MyRefCell<T>
+impl Clone for BorrowRef<'_> {
+    fn clone(&self) -> Self {
+        let flag = self.state.get();
+        debug_assert!(flag > UNSHARED);
+        assert!(flag != BorrowFlag::MAX, "too many shared borrows");
+        self.state.set(flag + 1);
+        trace_borrow!(TRACE, self.state, "shared", "borrow acquired");
+
+        #[cfg(feature = "debug-refcell")]
+        self.borrowed_at.0.push(self.borrowed_at.1);
+
+        BorrowRef {
+            state: self.state,
+            #[cfg(feature = "debug-refcell")]
+            borrowed_at: self.borrowed_at
+        }
+    }
 }
 
 
-impl<T> Drop for Ref<'_, T> {
+impl Drop for BorrowRef<'_> {
     fn drop(&mut self) {
-        match self.refcell.state.get() {
-            RefState::Exclusive | RefState::Unshared => unreachable!(),
-            RefState::Shared(1) => {
-                self.refcell.state.set(RefState::Unshared);
-            },
-            RefState::Shared(n) => {
-                self.refcell.state.set(RefState::Shared(n - 1))
-            }
+        #[cfg(feature = "debug-refcell")]
+        self.borrowed_at.0.remove(self.borrowed_at.1);
+
+        let flag = self.state.get();
+        debug_assert!(flag > UNSHARED);
+        self.state.set(flag - 1);
+        trace_borrow!(TRACE, self.state, "shared", "borrow released");
+    }
+}
+
+
+pub struct Ref<'refcell, T: ?Sized> {
+    // points into the cell's value; stays valid for as long as `borrow` keeps the cell shared
+    value: NonNull<T>,
+    borrow: BorrowRef<'refcell>
+}
+
+
+impl<'refcell, T: ?Sized> Ref<'refcell, T> {
+    // an associated function rather than a `Clone` impl, so `guard.clone()` keeps resolving to the
+    // inner value's `clone` through `Deref`
+    #[allow(clippy::should_implement_trait)]
+    pub fn clone(orig: &Ref<'refcell, T>) -> Ref<'refcell, T> {
+        Ref {
+            value: orig.value,
+            borrow: orig.borrow.clone()
+        }
+    }
+
+
+    pub fn map<U: ?Sized, F>(orig: Ref<'refcell, T>, f: F) -> Ref<'refcell, U>
+    where
+        F: FnOnce(&T) -> &U
+    {
+        Ref {
+            value: NonNull::from(f(&*orig)),
+            borrow: orig.borrow
+        }
+    }
+
+
+    pub fn leak(orig: Ref<'refcell, T>) -> &'refcell T {
+        // the forgotten borrow is never released, so the cell stays shared until `undo_leak`
+        mem::forget(orig.borrow);
+        unsafe { orig.value.as_ref() }
+    }
+
+
+    pub fn filter_map<U: ?Sized, F>(
+        orig: Ref<'refcell, T>,
+        f: F
+    ) -> Result<Ref<'refcell, U>, Ref<'refcell, T>>
+    where
+        F: FnOnce(&T) -> Option<&U>
+    {
+        // converting to `NonNull` ends the borrow of `orig`, so it can be handed back on failure
+        match f(&*orig).map(NonNull::from) {
+            Some(value) => Ok(Ref {value, borrow: orig.borrow}),
+            None => Err(orig)
         }
     }
+
+
+    pub fn map_split<U: ?Sized, V: ?Sized, F>(
+        orig: Ref<'refcell, T>,
+        f: F
+    ) -> (Ref<'refcell, U>, Ref<'refcell, V>)
+    where
+        F: FnOnce(&T) -> (&U, &V)
+    {
+        let borrow = orig.borrow.clone();
+        let (first, second) = f(&*orig);
+
+        (
+            Ref {value: NonNull::from(first), borrow},
+            Ref {value: NonNull::from(second), borrow: orig.borrow}
+        )
+    }
 }
 
 
-impl<T> Deref for Ref<'_, T> {
+impl<T: ?Sized> Deref for Ref<'_, T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        unsafe { & *self.refcell.value.get() }
+        unsafe { self.value.as_ref() }
+    }
+}
+
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Ref<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+
+impl<T: ?Sized + fmt::Display> fmt::Display for Ref<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
     }
 }
 
 
-struct RefMut<'refcell, T> {
-    refcell: &'refcell MyRefCell<T>
+// tracks the exclusive borrow of the cell's state and releases it on drop
+struct BorrowRefMut<'refcell> {
+    state: &'refcell Cell<BorrowFlag>,
+    #[cfg(feature = "debug-refcell")]
+    borrowed_at: (&'refcell BorrowLocations, &'static Location<'static>)
+}
+
+
+impl<'refcell> BorrowRefMut<'refcell> {
+    #[track_caller]
+    fn new<T: ?Sized>(refcell: &'refcell MyRefCell<T>) -> Result<Self, BorrowMutError> {
+        let state = &refcell.state;
+
+        if state.get() != UNSHARED {
+            trace_borrow!(DEBUG, state, "exclusive", "borrow denied");
+            return Err(BorrowMutError {
+                #[cfg(feature = "debug-refcell")]
+                conflicts: refcell.borrowed_at.get(),
+                _private: ()
+            });
+        }
+
+        state.set(UNSHARED - 1);
+        trace_borrow!(TRACE, state, "exclusive", "borrow acquired");
+
+        #[cfg(feature = "debug-refcell")]
+        refcell.borrowed_at.push(Location::caller());
+
+        Ok(BorrowRefMut {
+            state,
+            #[cfg(feature = "debug-refcell")]
+            borrowed_at: (&refcell.borrowed_at, Location::caller())
+        })
+    }
+
+
+    // only used to split an existing exclusive borrow into guards over disjoint parts
+    fn clone(&self) -> Self {
+        let flag = self.state.get();
+        debug_assert!(flag < UNSHARED);
+        assert!(flag != BorrowFlag::MIN, "too many mutable borrows");
+        self.state.set(flag - 1);
+        trace_borrow!(TRACE, self.state, "exclusive", "borrow acquired");
+
+        #[cfg(feature = "debug-refcell")]
+        self.borrowed_at.0.push(self.borrowed_at.1);
+
+        BorrowRefMut {
+            state: self.state,
+            #[cfg(feature = "debug-refcell")]
+            borrowed_at: self.borrowed_at
+        }
+    }
 }
 
 
-impl<T> Drop for RefMut<'_, T> {
+impl Drop for BorrowRefMut<'_> {
     fn drop(&mut self) {
-        match self.refcell.state.get() {
-            RefState::Shared(_) | RefState::Unshared => unreachable!(),
-            RefState::Exclusive => {
-                self.refcell.state.set(RefState::Unshared);
-            }
+        #[cfg(feature = "debug-refcell")]
+        self.borrowed_at.0.remove(self.borrowed_at.1);
+
+        let flag = self.state.get();
+        debug_assert!(flag < UNSHARED);
+        self.state.set(flag + 1);
+        trace_borrow!(TRACE, self.state, "exclusive", "borrow released");
+    }
+}
+
+
+pub struct RefMut<'refcell, T: ?Sized> {
+    value: NonNull<T>,
+    borrow: BorrowRefMut<'refcell>,
+    // makes `RefMut` invariant over `T`, like the `&mut T` it stands in for
+    _marker: PhantomData<&'refcell mut T>
+}
+
+
+impl<'refcell, T: ?Sized> RefMut<'refcell, T> {
+    pub fn map<U: ?Sized, F>(mut orig: RefMut<'refcell, T>, f: F) -> RefMut<'refcell, U>
+    where
+        F: FnOnce(&mut T) -> &mut U
+    {
+        RefMut {
+            value: NonNull::from(f(&mut *orig)),
+            borrow: orig.borrow,
+            _marker: PhantomData
+        }
+    }
+
+
+    pub fn leak(mut orig: RefMut<'refcell, T>) -> &'refcell mut T {
+        mem::forget(orig.borrow);
+        unsafe { orig.value.as_mut() }
+    }
+
+
+    pub fn filter_map<U: ?Sized, F>(
+        mut orig: RefMut<'refcell, T>,
+        f: F
+    ) -> Result<RefMut<'refcell, U>, RefMut<'refcell, T>>
+    where
+        F: FnOnce(&mut T) -> Option<&mut U>
+    {
+        match f(&mut *orig).map(NonNull::from) {
+            Some(value) => Ok(RefMut {value, borrow: orig.borrow, _marker: PhantomData}),
+            None => Err(orig)
         }
     }
+
+
+    pub fn map_split<U: ?Sized, V: ?Sized, F>(
+        mut orig: RefMut<'refcell, T>,
+        f: F
+    ) -> (RefMut<'refcell, U>, RefMut<'refcell, V>)
+    where
+        F: FnOnce(&mut T) -> (&mut U, &mut V)
+    {
+        let borrow = orig.borrow.clone();
+        let (first, second) = f(&mut *orig);
+
+        (
+            RefMut {value: NonNull::from(first), borrow, _marker: PhantomData},
+            RefMut {value: NonNull::from(second), borrow: orig.borrow, _marker: PhantomData}
+        )
+    }
 }
 
 
-impl<T> Deref for RefMut<'_, T> {
+impl<T: ?Sized> Deref for RefMut<'_, T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        unsafe { & *self.refcell.value.get() }
+        unsafe { self.value.as_ref() }
     }
 }
 
 
-impl<T> DerefMut for RefMut<'_, T> {
+impl<T: ?Sized> DerefMut for RefMut<'_, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { &mut *self.refcell.value.get() }
+        unsafe { self.value.as_mut() }
     }
 }
 
 
-#[cfg(test)]
-mod tests {
-    use super::{MyRefCell, RefState};
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RefMut<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
 
 
-    #[test]
-    fn my_ref_cell_new() {
-        let ref_cell = MyRefCell::new(String::from("MyRefCell"));
+impl<T: ?Sized + fmt::Display> fmt::Display for RefMut<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+
+#[derive(Debug)]
+pub struct BorrowError {
+    #[cfg(feature = "debug-refcell")]
+    conflicts: Vec<&'static Location<'static>>,
+    _private: ()
+}
 
-        assert_eq!(ref_cell.borrow().unwrap().as_str(), "MyRefCell");
-        assert_eq!(ref_cell.state.get(), RefState::Unshared);
+
+#[cfg(feature = "debug-refcell")]
+impl BorrowError {
+    pub fn conflicting_borrows(&self) -> &[&'static Location<'static>] {
+        &self.conflicts
     }
+}
 
 
-    #[test]
-    fn my_ref_cell_borrow() {
-        let ref_cell = MyRefCell::new(String::from("MyRefCell"));
+impl fmt::Display for BorrowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("already mutably borrowed")?;
 
-        let ref_cell_borrow_1 = ref_cell.borrow().unwrap();
-        let ref_cell_borrow_2 = ref_cell.borrow().unwrap();
+        #[cfg(feature = "debug-refcell")]
+        write_conflicts(f, &self.conflicts)?;
 
-        {
-            let ref_cell_borrow_3 = ref_cell.borrow().unwrap();
-            assert_eq!(ref_cell.state.get(), RefState::Shared(3));
-        }
+        Ok(())
+    }
+}
 
-        let ref_cell_borrow_mut = ref_cell.borrow_mut();
 
-        assert_eq!(ref_cell_borrow_1.as_str(), "MyRefCell");
-        assert_eq!(ref_cell_borrow_2.as_str(), "MyRefCell");
-        assert_eq!(ref_cell.state.get(), RefState::Shared(2));
-        assert!(ref_cell_borrow_mut.is_none());
+impl Error for BorrowError {}
+
+
+#[derive(Debug)]
+pub struct BorrowMutError {
+    #[cfg(feature = "debug-refcell")]
+    conflicts: Vec<&'static Location<'static>>,
+    _private: ()
+}
+
+
+#[cfg(feature = "debug-refcell")]
+impl BorrowMutError {
+    pub fn conflicting_borrows(&self) -> &[&'static Location<'static>] {
+        &self.conflicts
     }
+}
 
 
-    #[test]
-    fn my_ref_cell_borrow_mut() {
-        let ref_cell = MyRefCell::new(String::from("MyRefCell"));
+impl fmt::Display for BorrowMutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("already borrowed")?;
 
-        let ref_cell_borrow_mut = ref_cell.borrow_mut();
-        let ref_cell_borrow_1 = ref_cell.borrow();
-        let ref_cell_borrow_2 = ref_cell.borrow();
+        #[cfg(feature = "debug-refcell")]
+        write_conflicts(f, &self.conflicts)?;
 
-        assert_eq!(ref_cell.state.get(), RefState::Exclusive);
-        assert!(ref_cell_borrow_1.is_none());
-        assert!(ref_cell_borrow_2.is_none());
+        Ok(())
+    }
+}
+
+
+impl Error for BorrowMutError {}
+
+
+#[cfg(feature = "debug-refcell")]
+fn write_conflicts(
+    f: &mut fmt::Formatter<'_>,
+    conflicts: &[&'static Location<'static>]
+) -> fmt::Result {
+    f.write_str(" (conflicting borrows at ")?;
+
+    for (index, location) in conflicts.iter().enumerate() {
+        if index > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", location)?;
     }
-}
\ No newline at end of file
+
+    f.write_str(")")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::ops::DerefMut;
+    use super::{BorrowError, BorrowFlag, BorrowMutError, MyRefCell, Ref, RefMut, UNSHARED};
+
+
+    #[test]
+    fn my_ref_cell_new() {
+        let ref_cell = MyRefCell::new(String::from("MyRefCell"));
+
+        assert_eq!(ref_cell.borrow().as_str(), "MyRefCell");
+        assert_eq!(ref_cell.state.get(), UNSHARED);
+    }
+
+
+    #[test]
+    fn my_ref_cell_const_new() {
+        thread_local! {
+            static NAMES: MyRefCell<Vec<&'static str>> = const { MyRefCell::new(Vec::new()) };
+        }
+
+        NAMES.with(|names| names.borrow_mut().push("MyRefCell"));
+        NAMES.with(|names| assert_eq!(*names.borrow(), ["MyRefCell"]));
+    }
+
+
+    #[test]
+    fn my_ref_cell_borrow() {
+        let ref_cell = MyRefCell::new(String::from("MyRefCell"));
+
+        let ref_cell_borrow_1 = ref_cell.borrow();
+        let ref_cell_borrow_2 = ref_cell.borrow();
+
+        {
+            let ref_cell_borrow_3 = ref_cell.borrow();
+            assert_eq!(ref_cell.state.get(), 3);
+        }
+
+        let ref_cell_borrow_mut = ref_cell.try_borrow_mut();
+
+        assert_eq!(ref_cell_borrow_1.as_str(), "MyRefCell");
+        assert_eq!(ref_cell_borrow_2.as_str(), "MyRefCell");
+        assert_eq!(ref_cell.state.get(), 2);
+        assert!(ref_cell_borrow_mut.is_err());
+    }
+
+
+    #[test]
+    fn my_ref_cell_borrow_mut() {
+        let ref_cell = MyRefCell::new(String::from("MyRefCell"));
+
+        let ref_cell_borrow_mut = ref_cell.borrow_mut();
+        let ref_cell_borrow_1 = ref_cell.try_borrow();
+        let ref_cell_borrow_2 = ref_cell.try_borrow();
+
+        assert_eq!(ref_cell.state.get(), -1);
+        assert!(ref_cell_borrow_1.is_err());
+        assert!(ref_cell_borrow_2.is_err());
+    }
+
+
+    #[test]
+    #[should_panic(expected = "already mutably borrowed")]
+    fn my_ref_cell_borrow_panics_while_mutably_borrowed() {
+        let ref_cell = MyRefCell::new(0);
+
+        let _ref_cell_borrow_mut = ref_cell.borrow_mut();
+        let _ref_cell_borrow = ref_cell.borrow();
+    }
+
+
+    #[test]
+    #[should_panic(expected = "already borrowed")]
+    fn my_ref_cell_borrow_mut_panics_while_borrowed() {
+        let ref_cell = MyRefCell::new(0);
+
+        let _ref_cell_borrow = ref_cell.borrow();
+        let _ref_cell_borrow_mut = ref_cell.borrow_mut();
+    }
+
+
+    #[test]
+    fn my_ref_cell_try_borrow() {
+        let ref_cell = MyRefCell::new(String::from("MyRefCell"));
+
+        {
+            let _ref_cell_borrow = ref_cell.try_borrow().unwrap();
+            let error: BorrowMutError = ref_cell.try_borrow_mut().err().unwrap();
+            assert!(error.to_string().starts_with("already borrowed"));
+        }
+
+        let _ref_cell_borrow_mut = ref_cell.try_borrow_mut().unwrap();
+        let error: Box<dyn Error> = Box::new(ref_cell.try_borrow().err().unwrap());
+        assert!(error.to_string().starts_with("already mutably borrowed"));
+        assert!(matches!(ref_cell.try_borrow(), Err(BorrowError {..})));
+    }
+
+
+    #[test]
+    fn my_ref_cell_ref_map() {
+        let ref_cell = MyRefCell::new((String::from("name"), 42));
+
+        {
+            let name = Ref::map(ref_cell.borrow(), |pair| pair.0.as_str());
+            assert_eq!(&*name, "name");
+            assert_eq!(ref_cell.state.get(), 1);
+            assert!(ref_cell.try_borrow_mut().is_err());
+        }
+
+        assert_eq!(ref_cell.state.get(), UNSHARED);
+        ref_cell.borrow_mut().1 += 1;
+        assert_eq!(*Ref::map(ref_cell.borrow(), |pair| &pair.1), 43);
+    }
+
+
+    #[test]
+    fn my_ref_cell_ref_mut_map() {
+        let ref_cell = MyRefCell::new((String::from("name"), 42));
+
+        {
+            let mut count = RefMut::map(ref_cell.borrow_mut(), |pair| &mut pair.1);
+            *count += 1;
+            assert_eq!(ref_cell.state.get(), -1);
+            assert!(ref_cell.try_borrow().is_err());
+        }
+
+        assert_eq!(ref_cell.state.get(), UNSHARED);
+        RefMut::map(ref_cell.borrow_mut(), |pair| &mut pair.0).push('!');
+        assert_eq!(*ref_cell.borrow(), (String::from("name!"), 43));
+    }
+
+
+    #[test]
+    fn my_ref_cell_map_split() {
+        let ref_cell = MyRefCell::new(vec![1, 2, 3, 4]);
+
+        {
+            let (mut left, mut right) =
+                RefMut::map_split(ref_cell.borrow_mut(), |values| values.split_at_mut(2));
+            assert_eq!(ref_cell.state.get(), -2);
+
+            left[0] = 10;
+            right[1] = 40;
+            drop(left);
+
+            assert_eq!(ref_cell.state.get(), -1);
+            assert!(ref_cell.try_borrow().is_err());
+        }
+
+        let (left, right) = Ref::map_split(ref_cell.borrow(), |values| values.split_at(1));
+        assert_eq!(ref_cell.state.get(), 2);
+        assert_eq!(*left, [10]);
+        assert_eq!(*right, [2, 3, 40]);
+
+        drop(left);
+        drop(right);
+        assert_eq!(ref_cell.state.get(), UNSHARED);
+    }
+
+
+    #[test]
+    fn my_ref_cell_filter_map() {
+        let ref_cell: MyRefCell<Result<String, u32>> = MyRefCell::new(Ok(String::from("ok")));
+
+        let error = Ref::filter_map(ref_cell.borrow(), |value| value.as_ref().err());
+        let borrow = error.err().unwrap();
+        assert_eq!(ref_cell.state.get(), 1);
+
+        let message = Ref::filter_map(borrow, |value| value.as_ref().ok()).ok().unwrap();
+        assert_eq!(message.as_str(), "ok");
+        drop(message);
+
+        match RefMut::filter_map(ref_cell.borrow_mut(), |value| value.as_mut().ok()) {
+            Ok(mut message) => message.push('!'),
+            Err(_) => unreachable!()
+        }
+
+        assert!(RefMut::filter_map(ref_cell.borrow_mut(), |value| value.as_mut().err()).is_err());
+        assert_eq!(ref_cell.state.get(), UNSHARED);
+        assert_eq!(*ref_cell.borrow(), Ok(String::from("ok!")));
+    }
+
+
+    #[test]
+    fn my_ref_cell_ref_clone() {
+        let ref_cell = MyRefCell::new(String::from("MyRefCell"));
+
+        let borrow = ref_cell.borrow();
+        let borrow_clone = Ref::clone(&borrow);
+        assert_eq!(ref_cell.state.get(), 2);
+
+        // method syntax still clones the inner value
+        let value: String = borrow.clone();
+        drop(borrow);
+
+        assert_eq!(*borrow_clone, value);
+        assert_eq!(ref_cell.state.get(), 1);
+    }
+
+
+    #[test]
+    fn my_ref_cell_replace() {
+        let ref_cell = MyRefCell::new(vec![1]);
+
+        assert_eq!(ref_cell.replace(vec![2]), [1]);
+        assert_eq!(ref_cell.replace_with(|values| values.iter().map(|value| value * 10).collect()), [2]);
+        assert_eq!(*ref_cell.borrow(), [20]);
+        assert_eq!(ref_cell.state.get(), UNSHARED);
+    }
+
+
+    #[test]
+    #[should_panic(expected = "already borrowed")]
+    fn my_ref_cell_replace_panics_while_borrowed() {
+        let ref_cell = MyRefCell::new(0);
+
+        let _ref_cell_borrow = ref_cell.borrow();
+        ref_cell.replace(1);
+    }
+
+
+    #[test]
+    fn my_ref_cell_swap() {
+        let first = MyRefCell::new(String::from("first"));
+        let second = MyRefCell::new(String::from("second"));
+
+        first.swap(&second);
+        first.swap(&first);
+
+        assert_eq!(*first.borrow(), "second");
+        assert_eq!(*second.borrow(), "first");
+    }
+
+
+    #[test]
+    #[should_panic(expected = "already borrowed")]
+    fn my_ref_cell_swap_panics_while_borrowed() {
+        let first = MyRefCell::new(1);
+        let second = MyRefCell::new(2);
+
+        let _second_borrow = second.borrow();
+        first.swap(&second);
+    }
+
+
+    #[test]
+    #[should_panic(expected = "already borrowed")]
+    fn my_ref_cell_swap_with_itself_panics_while_borrowed() {
+        let ref_cell = MyRefCell::new(1);
+
+        let _ref_cell_borrow = ref_cell.borrow();
+        ref_cell.swap(&ref_cell);
+    }
+
+
+    #[test]
+    fn my_ref_cell_take() {
+        let ref_cell = MyRefCell::new(vec![1, 2]);
+
+        assert_eq!(ref_cell.take(), [1, 2]);
+        assert!(ref_cell.borrow().is_empty());
+
+        let _ref_cell_borrow = ref_cell.borrow();
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| ref_cell.take())).is_err());
+    }
+
+
+    #[test]
+    fn my_ref_cell_into_inner() {
+        let ref_cell = MyRefCell::new(String::from("MyRefCell"));
+        ref_cell.borrow_mut().push('!');
+
+        assert_eq!(ref_cell.into_inner(), "MyRefCell!");
+    }
+
+
+    #[test]
+    fn my_ref_cell_get_mut() {
+        let mut ref_cell = MyRefCell::new(vec![1]);
+
+        ref_cell.get_mut().push(2);
+        assert_eq!(ref_cell.state.get(), UNSHARED);
+        assert_eq!(*ref_cell.borrow(), [1, 2]);
+    }
+
+
+    #[test]
+    fn my_ref_cell_leak() {
+        let mut ref_cell = MyRefCell::new(1);
+
+        {
+            let first: &i32 = Ref::leak(ref_cell.borrow());
+            let second: &i32 = Ref::leak(ref_cell.borrow());
+            assert_eq!(*first + *second, 2);
+            assert_eq!(ref_cell.state.get(), 2);
+            assert!(ref_cell.try_borrow_mut().is_err());
+        }
+
+        *ref_cell.undo_leak() += 1;
+        assert_eq!(ref_cell.state.get(), UNSHARED);
+
+        let value: &mut i32 = RefMut::leak(ref_cell.borrow_mut());
+        *value += 1;
+        assert_eq!(ref_cell.state.get(), -1);
+
+        assert_eq!(*ref_cell.undo_leak(), 3);
+        assert_eq!(*ref_cell.borrow(), 3);
+    }
+
+
+    #[test]
+    fn my_ref_cell_try_borrow_unguarded() {
+        let ref_cell = MyRefCell::new(5);
+
+        {
+            let _ref_cell_borrow = ref_cell.borrow();
+            let value = unsafe { ref_cell.try_borrow_unguarded() }.unwrap();
+            assert_eq!(*value, 5);
+            assert_eq!(ref_cell.state.get(), 1);
+        }
+
+        let _ref_cell_borrow_mut = ref_cell.borrow_mut();
+        assert!(unsafe { ref_cell.try_borrow_unguarded() }.is_err());
+    }
+
+
+    #[test]
+    fn my_ref_cell_debug() {
+        let ref_cell = MyRefCell::new(vec![1, 2]);
+
+        {
+            let _ref_cell_borrow = ref_cell.borrow();
+            assert_eq!(format!("{:?}", ref_cell), "MyRefCell { value: [1, 2] }");
+        }
+
+        let _ref_cell_borrow_mut = ref_cell.borrow_mut();
+        assert_eq!(format!("{:?}", ref_cell), "MyRefCell { value: <borrowed> }");
+    }
+
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn my_ref_cell_serde() {
+        let ref_cell: MyRefCell<Vec<u32>> = serde_json::from_str("[1,2]").unwrap();
+        assert_eq!(*ref_cell.borrow(), [1, 2]);
+
+        {
+            let _ref_cell_borrow = ref_cell.borrow();
+            assert_eq!(serde_json::to_string(&ref_cell).unwrap(), "[1,2]");
+        }
+
+        let _ref_cell_borrow_mut = ref_cell.borrow_mut();
+        let error = serde_json::to_string(&ref_cell).unwrap_err();
+        assert!(error.to_string().starts_with("already mutably borrowed"));
+    }
+
+
+    #[test]
+    fn my_ref_cell_clone_default_and_from() {
+        let ref_cell = MyRefCell::from(vec![1]);
+        let clone = ref_cell.clone();
+
+        ref_cell.borrow_mut().push(2);
+        assert_eq!(*clone.borrow(), [1]);
+        assert!(MyRefCell::<Vec<i32>>::default().borrow().is_empty());
+    }
+
+
+    #[test]
+    fn my_ref_cell_comparisons() {
+        let small = MyRefCell::new(1);
+        let large = MyRefCell::new(2);
+
+        assert_eq!(small, MyRefCell::new(1));
+        assert_ne!(small, large);
+        assert!(small < large);
+        assert_eq!(large.cmp(&small), std::cmp::Ordering::Greater);
+
+        // comparing only needs shared borrows, which coexist with other shared borrows
+        let _small_borrow = small.borrow();
+        assert!(small <= small);
+    }
+
+
+    #[test]
+    #[should_panic(expected = "already mutably borrowed")]
+    fn my_ref_cell_eq_panics_while_mutably_borrowed() {
+        let ref_cell = MyRefCell::new(1);
+
+        let _ref_cell_borrow_mut = ref_cell.borrow_mut();
+        let _ = ref_cell == MyRefCell::new(1);
+    }
+
+
+    #[test]
+    fn my_ref_cell_guards_in_signatures() {
+        struct User {
+            name: MyRefCell<String>
+        }
+
+        impl User {
+            fn name(&self) -> Ref<'_, str> {
+                Ref::map(self.name.borrow(), String::as_str)
+            }
+
+            fn name_mut(&self) -> RefMut<'_, String> {
+                self.name.borrow_mut()
+            }
+        }
+
+        let user = User {name: MyRefCell::new(String::from("user"))};
+        user.name_mut().push_str("_1");
+
+        assert_eq!(format!("{} {:?}", user.name(), user.name()), "user_1 \"user_1\"");
+        assert_eq!(format!("{}", user.name_mut()), "user_1");
+    }
+
+
+    #[test]
+    #[cfg(feature = "debug-refcell")]
+    fn my_ref_cell_reports_conflicting_borrows() {
+        let ref_cell = MyRefCell::new(vec![1, 2]);
+
+        let first_line = line!() + 1;
+        let first = ref_cell.borrow();
+        let (second, third) = Ref::map_split(Ref::clone(&first), |values| values.split_at(1));
+
+        let error = ref_cell.try_borrow_mut().err().unwrap();
+        assert_eq!(error.conflicting_borrows().len(), 3);
+        assert!(error.conflicting_borrows().iter().all(|location| location.line() == first_line));
+        assert!(error.to_string().starts_with("already borrowed (conflicting borrows at src/refcell.rs:"));
+
+        drop(first);
+        drop(second);
+        assert_eq!(ref_cell.try_borrow_mut().err().unwrap().conflicting_borrows().len(), 1);
+        drop(third);
+
+        let borrow_mut_line = line!() + 1;
+        let _borrow_mut = ref_cell.borrow_mut();
+        let error = ref_cell.try_borrow().err().unwrap();
+        assert_eq!(error.conflicting_borrows()[0].line(), borrow_mut_line);
+        assert_eq!(
+            error.to_string(),
+            format!("already mutably borrowed (conflicting borrows at {})", error.conflicting_borrows()[0])
+        );
+    }
+
+
+    #[test]
+    fn my_ref_cell_as_ptr() {
+        let ref_cell = MyRefCell::new(5);
+        let ptr = ref_cell.as_ptr();
+
+        // no guard is alive, so accessing the value through the pointer cannot alias a borrow
+        unsafe {
+            assert_eq!(ptr.read(), 5);
+            *ptr += 1;
+        }
+
+        assert_eq!(ref_cell.state.get(), UNSHARED);
+        assert_eq!(*ref_cell.borrow(), 6);
+        assert_eq!(ptr, ref_cell.borrow_mut().deref_mut() as *mut i32);
+    }
+
+
+    #[test]
+    fn my_ref_cell_unsized() {
+        trait Shape {
+            fn area(&self) -> u32;
+            fn scale(&mut self, factor: u32);
+        }
+
+        struct Square(u32);
+        struct Rectangle(u32, u32);
+
+        impl Shape for Square {
+            fn area(&self) -> u32 { self.0 * self.0 }
+            fn scale(&mut self, factor: u32) { self.0 *= factor; }
+        }
+
+        impl Shape for Rectangle {
+            fn area(&self) -> u32 { self.0 * self.1 }
+            fn scale(&mut self, factor: u32) { self.0 *= factor; }
+        }
+
+        let shapes: Vec<Box<MyRefCell<dyn Shape>>> = vec![
+            Box::new(MyRefCell::new(Square(2))),
+            Box::new(MyRefCell::new(Rectangle(2, 3)))
+        ];
+
+        for shape in &shapes {
+            shape.borrow_mut().scale(2);
+        }
+        assert_eq!(shapes.iter().map(|shape| shape.borrow().area()).collect::<Vec<_>>(), [16, 12]);
+
+        let values = MyRefCell::new([1, 2, 3]);
+        let slice: &MyRefCell<[i32]> = &values;
+        slice.borrow_mut()[0] = 10;
+
+        let _slice_borrow = slice.borrow();
+        assert!(slice.try_borrow_mut().is_err());
+        assert_eq!(*values.borrow(), [10, 2, 3]);
+    }
+
+
+    #[test]
+    fn my_ref_cell_borrow_flag_overflow() {
+        let ref_cell = MyRefCell::new(0);
+        ref_cell.state.set(BorrowFlag::MAX - 1);
+
+        let borrow = ref_cell.borrow();
+        assert_eq!(ref_cell.state.get(), BorrowFlag::MAX);
+        assert!(ref_cell.try_borrow().is_err());
+        assert!(ref_cell.try_borrow_mut().is_err());
+        assert_eq!(ref_cell.state.get(), BorrowFlag::MAX);
+
+        drop(borrow);
+        assert_eq!(ref_cell.state.get(), BorrowFlag::MAX - 1);
+    }
+
+
+    #[test]
+    #[should_panic(expected = "too many shared borrows")]
+    fn my_ref_cell_ref_clone_overflow_panics() {
+        let ref_cell = MyRefCell::new(0);
+        ref_cell.state.set(BorrowFlag::MAX - 1);
+
+        let borrow = ref_cell.borrow();
+        let _ = Ref::clone(&borrow);
+    }
+
+
+    #[test]
+    #[cfg(not(feature = "debug-refcell"))]
+    fn my_ref_cell_size() {
+        use std::cell::RefCell;
+        use std::mem::size_of;
+
+        assert_eq!(size_of::<MyRefCell<u64>>(), size_of::<RefCell<u64>>());
+        assert_eq!(size_of::<MyRefCell<u8>>(), 2 * size_of::<usize>());
+    }
+
+
+    #[test]
+    fn my_ref_cell_with_borrow() {
+        let ref_cell = MyRefCell::new(vec![1, 2]);
+
+        let len = ref_cell.with_borrow(|values| {
+            assert_eq!(ref_cell.state.get(), 1);
+            values.len()
+        });
+        assert_eq!(len, 2);
+
+        ref_cell.with_borrow_mut(|values| values.push(len + 1));
+        assert_eq!(ref_cell.state.get(), UNSHARED);
+
+        // the closure's borrow is already released, so a mutable borrow right after succeeds
+        let sum: usize = ref_cell.with_borrow(|values| values.iter().sum());
+        ref_cell.borrow_mut().push(sum);
+        assert_eq!(*ref_cell.borrow(), [1, 2, 3, 6]);
+    }
+}
+
+#[cfg(test)]
+mod state_machine_tests {
+    use proptest::prelude::*;
+    use super::{MyRefCell, Ref, RefMut, UNSHARED};
+
+
+    #[derive(Debug, Clone)]
+    enum Operation {
+        Borrow,
+        BorrowMut,
+        // guard operations pick a live guard by index, modulo the number of live guards
+        CloneRef(usize),
+        MapSplit(usize),
+        Drop(usize)
+    }
+
+
+    enum Guard<'refcell> {
+        Shared(Ref<'refcell, [u8]>),
+        Exclusive(RefMut<'refcell, [u8]>)
+    }
+
+
+    // the reference model: how many shared and exclusive guards are alive
+    #[derive(Default)]
+    struct Model {
+        shared: isize,
+        exclusive: isize
+    }
+
+
+    impl Model {
+        fn expected_state(&self) -> isize {
+            assert!(self.shared == 0 || self.exclusive == 0);
+            self.shared - self.exclusive
+        }
+    }
+
+
+    fn operation() -> impl Strategy<Value = Operation> {
+        prop_oneof![
+            Just(Operation::Borrow),
+            Just(Operation::BorrowMut),
+            any::<usize>().prop_map(Operation::CloneRef),
+            any::<usize>().prop_map(Operation::MapSplit),
+            any::<usize>().prop_map(Operation::Drop)
+        ]
+    }
+
+
+    proptest! {
+        #[test]
+        fn my_ref_cell_borrow_state_matches_model(operations in prop::collection::vec(operation(), 1..64)) {
+            let ref_cell = MyRefCell::new(vec![0u8; 8]);
+            let mut guards: Vec<Guard<'_>> = Vec::new();
+            let mut model = Model::default();
+
+            for operation in operations {
+                match operation {
+                    Operation::Borrow => match ref_cell.try_borrow() {
+                        Ok(borrow) => {
+                            prop_assert_eq!(model.exclusive, 0);
+                            model.shared += 1;
+                            guards.push(Guard::Shared(Ref::map(borrow, Vec::as_slice)));
+                        },
+                        Err(_) => prop_assert!(model.exclusive > 0)
+                    },
+                    Operation::BorrowMut => match ref_cell.try_borrow_mut() {
+                        Ok(borrow) => {
+                            prop_assert_eq!((model.shared, model.exclusive), (0, 0));
+                            model.exclusive += 1;
+                            guards.push(Guard::Exclusive(RefMut::map(borrow, Vec::as_mut_slice)));
+                        },
+                        Err(_) => prop_assert!(model.shared > 0 || model.exclusive > 0)
+                    },
+                    Operation::CloneRef(index) if !guards.is_empty() => {
+                        let index = index % guards.len();
+                        if let Guard::Shared(borrow) = &guards[index] {
+                            let clone = Ref::clone(borrow);
+                            model.shared += 1;
+                            guards.push(Guard::Shared(clone));
+                        }
+                    },
+                    Operation::MapSplit(index) if !guards.is_empty() => {
+                        let (first, second) = match guards.swap_remove(index % guards.len()) {
+                            Guard::Shared(borrow) => {
+                                let (first, second) = Ref::map_split(borrow, |values| values.split_at(values.len() / 2));
+                                model.shared += 1;
+                                (Guard::Shared(first), Guard::Shared(second))
+                            },
+                            Guard::Exclusive(borrow) => {
+                                let (first, second) = RefMut::map_split(borrow, |values| {
+                                    let middle = values.len() / 2;
+                                    values.split_at_mut(middle)
+                                });
+                                model.exclusive += 1;
+                                (Guard::Exclusive(first), Guard::Exclusive(second))
+                            }
+                        };
+                        guards.push(first);
+                        guards.push(second);
+                    },
+                    Operation::Drop(index) if !guards.is_empty() => {
+                        match guards.swap_remove(index % guards.len()) {
+                            Guard::Shared(_) => model.shared -= 1,
+                            Guard::Exclusive(_) => model.exclusive -= 1
+                        }
+                    },
+                    Operation::CloneRef(_) | Operation::MapSplit(_) | Operation::Drop(_) => {}
+                }
+
+                prop_assert_eq!(ref_cell.state.get(), model.expected_state());
+            }
+
+            drop(guards);
+            prop_assert_eq!(ref_cell.state.get(), UNSHARED);
+        }
+    }
+}
+
+
+#[cfg(all(test, feature = "tracing"))]
+mod tracing_tests {
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+    use tracing::{Event, Metadata, Subscriber};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use super::MyRefCell;
+
+
+    // collects every event as a "message kind shared exclusive" line
+    #[derive(Clone, Default)]
+    struct Recorder {
+        events: Arc<Mutex<Vec<String>>>
+    }
+
+
+    struct Fields(Vec<String>);
+
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            if field.name() != "cell" {
+                self.0.push(format!("{:?}", value));
+            }
+        }
+
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.record_debug(field, &format_args!("{}", value));
+        }
+    }
+
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+
+        fn new_span(&self, _span: &Attributes<'_>) -> Id {
+            Id::from_u64(1)
+        }
+
+
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Fields(Vec::new());
+            event.record(&mut fields);
+            self.events.lock().unwrap().push(fields.0.join(" "));
+        }
+
+
+        fn enter(&self, _span: &Id) {}
+
+
+        fn exit(&self, _span: &Id) {}
+    }
+
+
+    #[test]
+    fn my_ref_cell_traces_borrow_events() {
+        let recorder = Recorder::default();
+        let ref_cell = MyRefCell::new(5);
+
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let borrow = ref_cell.borrow();
+            let clone = super::Ref::clone(&borrow);
+            assert!(ref_cell.try_borrow_mut().is_err());
+            drop(borrow);
+            drop(clone);
+
+            let _borrow_mut = ref_cell.borrow_mut();
+            assert!(ref_cell.try_borrow().is_err());
+        });
+
+        assert_eq!(*recorder.events.lock().unwrap(), [
+            "borrow acquired shared 1 0",
+            "borrow acquired shared 2 0",
+            "borrow denied exclusive 2 0",
+            "borrow released shared 1 0",
+            "borrow released shared 0 0",
+            "borrow acquired exclusive 0 1",
+            "borrow denied shared 0 1",
+            "borrow released exclusive 0 0"
+        ]);
+    }
+}
@@ -0,0 +1,212 @@
+/*
+- `Shared<T>` is the `Rc<RefCell<T>>` pattern in one type: a value with several owners that any of
+them can mutate. It wraps a `MyRc<MyRefCell<T>>` and offers `borrow` and `borrow_mut` directly, so
+call sites read `shared.borrow_mut()` instead of going through the `MyRc` to the cell first.
+
+- Cloning a `Shared<T>` clones the pointer, never the value, and `ptr_eq` tells whether two handles
+own the same value. `downgrade` hands out a `WeakShared<T>`, the non-owning counterpart, which
+upgrades back to a `Shared<T>` while the value is still alive.
+
+- The borrow rules are those of `MyRefCell`: conflicting borrows panic, `try_borrow` and
+`try_borrow_mut` report them instead, and the borrow methods are `#[track_caller]`, so panics (and
+the locations recorded by the `debug-refcell` feature) point at the caller rather than at
+`Shared<T>` itself.
+
+- `Shared<T>` is neither `Send` nor `Sync`, like both of its parts.
+*/
+use std::fmt;
+use crate::rc::{MyRc, MyWeak};
+use crate::refcell::{BorrowError, BorrowMutError, MyRefCell, Ref, RefMut};
+
+
+pub struct Shared<T: ?Sized> {
+    inner: MyRc<MyRefCell<T>>
+}
+
+
+pub struct WeakShared<T: ?Sized> {
+    inner: MyWeak<MyRefCell<T>>
+}
+
+
+impl<T> Shared<T> {
+    pub fn new(value: T) -> Self {
+        Shared {inner: MyRc::new(MyRefCell::new(value))}
+    }
+
+
+    // the value, if this is its last owner; otherwise the handle is handed back
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        match MyRc::try_unwrap(this.inner) {
+            Ok(cell) => Ok(cell.into_inner()),
+            Err(inner) => Err(Shared {inner})
+        }
+    }
+}
+
+
+impl<T: ?Sized> Shared<T> {
+    #[track_caller]
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.inner.borrow()
+    }
+
+
+    #[track_caller]
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        self.inner.borrow_mut()
+    }
+
+
+    #[track_caller]
+    pub fn try_borrow(&self) -> Result<Ref<'_, T>, BorrowError> {
+        self.inner.try_borrow()
+    }
+
+
+    #[track_caller]
+    pub fn try_borrow_mut(&self) -> Result<RefMut<'_, T>, BorrowMutError> {
+        self.inner.try_borrow_mut()
+    }
+
+
+    pub fn downgrade(this: &Self) -> WeakShared<T> {
+        WeakShared {inner: MyRc::downgrade(&this.inner)}
+    }
+
+
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        MyRc::ptr_eq(&this.inner, &other.inner)
+    }
+
+
+    pub fn strong_count(this: &Self) -> usize {
+        MyRc::strong_count(&this.inner)
+    }
+
+
+    pub fn weak_count(this: &Self) -> usize {
+        MyRc::weak_count(&this.inner)
+    }
+}
+
+
+impl<T: ?Sized> WeakShared<T> {
+    pub fn upgrade(&self) -> Option<Shared<T>> {
+        Some(Shared {inner: self.inner.upgrade()?})
+    }
+}
+
+
+impl<T> WeakShared<T> {
+    pub fn new() -> Self {
+        WeakShared {inner: MyWeak::new()}
+    }
+}
+
+
+impl<T: ?Sized> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Shared {inner: self.inner.clone()}
+    }
+}
+
+
+impl<T: ?Sized> Clone for WeakShared<T> {
+    fn clone(&self) -> Self {
+        WeakShared {inner: self.inner.clone()}
+    }
+}
+
+
+impl<T: Default> Default for Shared<T> {
+    fn default() -> Self {
+        Shared::new(T::default())
+    }
+}
+
+
+impl<T> Default for WeakShared<T> {
+    fn default() -> Self {
+        WeakShared::new()
+    }
+}
+
+
+impl<T> From<T> for Shared<T> {
+    fn from(value: T) -> Self {
+        Shared::new(value)
+    }
+}
+
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Shared<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // like for the cell, a value that is mutably borrowed is reported instead of panicking
+        match self.try_borrow() {
+            Ok(borrow) => f.debug_struct("Shared").field("value", &&*borrow).finish(),
+            Err(_) => f.debug_struct("Shared").field("value", &format_args!("<borrowed>")).finish()
+        }
+    }
+}
+
+
+impl<T: ?Sized> fmt::Debug for WeakShared<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(WeakShared)")
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::shared::{Shared, WeakShared};
+
+
+    #[test]
+    fn shared_borrow_and_mutate() {
+        let shared = Shared::new(vec![1, 2]);
+        let other = shared.clone();
+
+        other.borrow_mut().push(3);
+        assert_eq!(*shared.borrow(), [1, 2, 3]);
+        assert!(Shared::ptr_eq(&shared, &other));
+        assert!(!Shared::ptr_eq(&shared, &Shared::new(vec![1, 2, 3])));
+        assert_eq!(Shared::strong_count(&shared), 2);
+
+        // conflicting borrows are reported like for the cell underneath
+        let guard = shared.borrow();
+        assert!(other.try_borrow_mut().is_err());
+        assert!(other.try_borrow().is_ok());
+        drop(guard);
+
+        assert!(Shared::try_unwrap(shared).is_err());
+        assert_eq!(Shared::try_unwrap(other).unwrap(), [1, 2, 3]);
+    }
+
+
+    #[test]
+    fn shared_downgrade() {
+        let shared = Shared::new(String::from("Hello"));
+        let weak = Shared::downgrade(&shared);
+        assert_eq!(Shared::weak_count(&shared), 1);
+
+        weak.upgrade().unwrap().borrow_mut().push_str(" World!");
+        assert_eq!(*shared.borrow(), "Hello World!");
+        assert_eq!(format!("{:?}", shared), "Shared { value: \"Hello World!\" }");
+
+        drop(shared);
+        assert!(weak.upgrade().is_none());
+        assert!(WeakShared::<u8>::new().upgrade().is_none());
+    }
+
+
+    #[test]
+    #[should_panic(expected = "already borrowed")]
+    fn shared_conflicting_borrow_panics() {
+        let shared = Shared::new(0);
+        let _guard = shared.borrow();
+
+        *shared.borrow_mut() += 1;
+    }
+}
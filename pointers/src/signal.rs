@@ -0,0 +1,185 @@
+/*
+- A `Signal<T>` notifies its listeners of every value passed to `emit`. `subscribe` registers a
+callback and returns a `Subscription`, which owns the callback: the signal itself only keeps a
+`MyWeak` to it. Dropping the subscription, e.g. together with the object that subscribed, stops the
+notifications without unsubscribing by hand, and a signal never keeps its listeners alive.
+
+- Each callback lives in a `MyRc<MyRefCell<dyn FnMut(&T)>>`, so it can keep mutable state of its
+own between calls. Listeners whose subscription is gone are pruned from the list whenever the
+signal walks it.
+
+- `emit` is re-entrancy safe: it takes a snapshot of the live listeners first and releases the list
+before calling any of them, so a callback may subscribe, drop subscriptions or emit on the same
+signal again. A listener subscribed during an `emit` is first called by the next one, and a listener
+whose subscription is dropped during an `emit` is not called by it anymore. A callback that emits on
+its own signal is skipped by the nested `emit` instead of being re-entered, since it is still
+running.
+*/
+use crate::coerce_rc;
+use crate::rc::{MyRc, MyWeak};
+use crate::refcell::MyRefCell;
+
+
+type Listener<T> = MyRefCell<dyn FnMut(&T)>;
+
+
+pub struct Signal<T> {
+    listeners: MyRefCell<Vec<MyWeak<Listener<T>>>>
+}
+
+
+pub struct Subscription<T> {
+    _listener: MyRc<Listener<T>>
+}
+
+
+impl<T> Signal<T> {
+    pub fn new() -> Self {
+        Signal {listeners: MyRefCell::new(Vec::new())}
+    }
+
+
+    pub fn subscribe(&self, callback: impl FnMut(&T) + 'static) -> Subscription<T> {
+        let listener = coerce_rc!(MyRc::new(MyRefCell::new(callback)) => Listener<T>);
+        self.listeners.borrow_mut().push(MyRc::downgrade(&listener));
+
+        Subscription {_listener: listener}
+    }
+
+
+    pub fn emit(&self, value: &T) {
+        // the list is not borrowed while the callbacks run, so they may change it
+        let listeners = self.live_listeners();
+
+        for listener in listeners {
+            // a subscription dropped by an earlier callback is skipped
+            let Some(listener) = listener.upgrade() else {
+                continue;
+            };
+
+            // a listener that is still running is the one emitting this value, and is not entered
+            // a second time
+            let callback = listener.try_borrow_mut();
+            if let Ok(mut callback) = callback {
+                callback(value);
+            }
+        }
+    }
+
+
+    pub fn listener_count(&self) -> usize {
+        self.live_listeners().len()
+    }
+
+
+    // prunes the listeners whose subscription is gone and returns the rest
+    fn live_listeners(&self) -> Vec<MyWeak<Listener<T>>> {
+        let mut listeners = self.listeners.borrow_mut();
+        listeners.retain(|listener| listener.upgrade().is_some());
+        listeners.clone()
+    }
+}
+
+
+impl<T> Default for Signal<T> {
+    fn default() -> Self {
+        Signal::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use crate::rc::MyRc;
+    use crate::signal::Signal;
+
+
+    #[test]
+    fn signal_emit_and_unsubscribe() {
+        let signal = Signal::new();
+        let received = MyRc::new(RefCell::new(Vec::new()));
+
+        let first = {
+            let received = received.clone();
+            signal.subscribe(move |value: &i32| received.borrow_mut().push(("first", *value)))
+        };
+        let second = {
+            let received = received.clone();
+            let mut total = 0;
+            signal.subscribe(move |value: &i32| {
+                total += value;
+                received.borrow_mut().push(("second", total));
+            })
+        };
+
+        signal.emit(&1);
+        drop(first);
+        signal.emit(&2);
+        assert_eq!(*received.borrow(), [("first", 1), ("second", 1), ("second", 3)]);
+        assert_eq!(signal.listener_count(), 1);
+
+        drop(second);
+        signal.emit(&3);
+        assert_eq!(signal.listener_count(), 0);
+        // the callbacks and everything they captured are gone
+        assert_eq!(MyRc::strong_count(&received), 1);
+    }
+
+
+    #[test]
+    fn signal_is_reentrancy_safe() {
+        let signal = MyRc::new(Signal::new());
+        let calls = MyRc::new(Cell::new(0));
+        let late = MyRc::new(RefCell::new(None));
+        let subscribed = MyRc::new(Cell::new(false));
+
+        let _subscription = {
+            let signal_weak = MyRc::downgrade(&signal);
+            let (calls, late, subscribed) = (calls.clone(), late.clone(), subscribed.clone());
+            signal.subscribe(move |depth: &u32| {
+                calls.set(calls.get() + 1);
+                let signal = signal_weak.upgrade().unwrap();
+
+                // subscribing and emitting from inside a callback neither panics nor re-enters the
+                // running callback
+                if !subscribed.replace(true) {
+                    let calls = calls.clone();
+                    *late.borrow_mut() = Some(signal.subscribe(move |_: &u32| calls.set(calls.get() + 10)));
+                }
+                signal.emit(&(depth + 1));
+            })
+        };
+
+        // the nested emit only reaches the listener subscribed during the outer one
+        signal.emit(&0);
+        assert_eq!(calls.get(), 11);
+
+        drop(late.borrow_mut().take());
+        signal.emit(&0);
+        assert_eq!(calls.get(), 12);
+    }
+
+
+    #[test]
+    fn signal_skips_listener_dropped_during_emit() {
+        let signal = Signal::new();
+        let calls = MyRc::new(Cell::new(0));
+        let victim = MyRc::new(RefCell::new(None));
+
+        // the first listener drops the subscription of the second one
+        let _first = {
+            let victim = victim.clone();
+            signal.subscribe(move |_: &()| drop(victim.borrow_mut().take()))
+        };
+        let second = {
+            let calls = calls.clone();
+            signal.subscribe(move |_: &()| calls.set(calls.get() + 1))
+        };
+        *victim.borrow_mut() = Some(second);
+
+        signal.emit(&());
+        assert_eq!(calls.get(), 0);
+        assert_eq!(signal.listener_count(), 1);
+    }
+}
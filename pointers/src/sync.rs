@@ -0,0 +1,10 @@
+/*
+- Synchronization primitives used by the thread-safe pointer types are imported through this
+module, so the `loom` feature can swap in loom's model-checked versions without touching the
+code that uses them.
+*/
+#[cfg(feature = "loom")]
+pub(crate) use loom::{hint, sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering}};
+
+#[cfg(not(feature = "loom"))]
+pub(crate) use std::{hint, sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering}};
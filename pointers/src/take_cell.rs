@@ -0,0 +1,100 @@
+/*
+- `TakeCell<T>` holds a value that is handed to exactly one consumer: `take` moves the value out
+and leaves the cell empty, so every later `take` returns `None` until a new value is `put` in.
+
+- It is a thin layer over `MyCell<Option<T>>`, naming the "full" and "empty" states instead of
+making every user reimplement the `Option` juggling (e.g. moving a callback into a handler that
+must run at most once).
+
+- `put` refuses to overwrite a value that has not been taken yet and hands the new value back,
+so a value can never be dropped by accident.
+
+- As with `MyCell<T>`, no reference to the inner value is ever handed out, so `TakeCell<T>` works
+with any `T` through a shared reference.
+*/
+use crate::cell::MyCell;
+
+
+pub struct TakeCell<T> {
+    value: MyCell<Option<T>>
+}
+
+
+impl<T> TakeCell<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            value: MyCell::new(Some(value))
+        }
+    }
+
+
+    pub const fn empty() -> Self {
+        Self {
+            value: MyCell::new(None)
+        }
+    }
+
+
+    pub fn take(&self) -> Option<T> {
+        self.value.take()
+    }
+
+
+    pub fn put(&self, value: T) -> Result<(), T> {
+        match self.value.take() {
+            Some(existing) => {
+                self.value.set(Some(existing));
+                Err(value)
+            },
+            None => {
+                self.value.set(Some(value));
+                Ok(())
+            }
+        }
+    }
+
+
+    pub fn is_taken(&self) -> bool {
+        let value = self.value.take();
+        let taken = value.is_none();
+        self.value.set(value);
+        taken
+    }
+}
+
+
+impl<T> Default for TakeCell<T> {
+    fn default() -> Self {
+        TakeCell::empty()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::take_cell::TakeCell;
+
+
+    #[test]
+    fn take_cell_take_once() {
+        let callback: TakeCell<Box<dyn FnOnce() -> u32>> = TakeCell::new(Box::new(|| 42));
+        let handler = || callback.take().map(|callback| callback());
+
+        assert!(!callback.is_taken());
+        assert_eq!(handler(), Some(42));
+        assert_eq!(handler(), None);
+        assert!(callback.is_taken());
+    }
+
+
+    #[test]
+    fn take_cell_put() {
+        let cell = TakeCell::empty();
+
+        assert_eq!(cell.put(String::from("first")), Ok(()));
+        assert_eq!(cell.put(String::from("second")), Err(String::from("second")));
+        assert_eq!(cell.take(), Some(String::from("first")));
+        assert_eq!(cell.put(String::from("third")), Ok(()));
+        assert_eq!(cell.take(), Some(String::from("third")));
+    }
+}
@@ -0,0 +1,324 @@
+/*
+- `ThinArc<T>` is `MyArc<T>` without weak references, in the spirit of `triomphe::Arc`. The
+allocation holds the strong count and the value, nothing else: there is no weak count, so every
+allocation is a word smaller, and none of the paths have to look after one.
+
+- The saving shows on the hot paths. Dropping the last reference frees the allocation right after
+the value instead of giving up the implicit weak reference first, which saves an atomic
+read-modify-write. `get_mut` and `make_mut` check the strong count with a single load, since with
+no weak references around, a `ThinArc<T>` that is the only strong one cannot gain another owner
+behind its back. There is no lock on the weak count, no compare-exchange loop and no allocation
+that outlives its value. Cloning costs the same as for `MyArc<T>`.
+
+- The price is that nothing can observe a value without keeping it alive, so `ThinArc<T>` suits
+values that are shared but never watched from the side: interned strings, immutable
+configuration, the nodes of persistent data structures. Like `ThinRc<T>` for `MyRc<T>`, it is the
+lean sibling to pick when `downgrade` is never called.
+
+- The orderings are those of `MyArc<T>`: `Relaxed` increments, `Release` decrements and an
+`Acquire` fence before the value is dropped, and counts that pass `isize::MAX` abort. Counts are
+imported through `crate::sync`, so loom can stand in for the std atomics.
+
+- `Default`, `Debug`, `Display`, `Hash`, `PartialEq`, `Eq`, `PartialOrd`, `Ord`, `Borrow<T>` and
+`AsRef<T>` forward to the shared value.
+*/
+use std::{fmt, process, ptr};
+use std::borrow::Borrow;
+use std::cmp::Ordering as CmpOrdering;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem::{self, ManuallyDrop};
+use std::ops::Deref;
+use std::ptr::NonNull;
+use crate::sync::{fence, AtomicUsize, Ordering};
+
+
+// far enough below `usize::MAX` that threads racing past it before they abort cannot overflow it
+const MAX_REFCOUNT: usize = isize::MAX as usize;
+
+
+pub struct ThinArc<T> {
+    inner: NonNull<ThinArcInner<T>>,
+    _marker: PhantomData<ThinArcInner<T>>
+}
+
+
+struct ThinArcInner<T> {
+    strong: AtomicUsize,
+    value: T
+}
+
+
+unsafe impl<T: Send + Sync> Send for ThinArc<T> {}
+unsafe impl<T: Send + Sync> Sync for ThinArc<T> {}
+
+
+impl<T> ThinArc<T> {
+    pub fn new(value: T) -> Self {
+        let inner = Box::new(ThinArcInner {
+            strong: AtomicUsize::new(1),
+            value
+        });
+
+        ThinArc {
+            inner: NonNull::from(Box::leak(inner)),
+            _marker: PhantomData
+        }
+    }
+
+
+    // a snapshot only: other threads may clone or drop in the meantime
+    pub fn strong_count(this: &Self) -> usize {
+        this.inner().strong.load(Ordering::Acquire)
+    }
+
+
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        ptr::eq(this.inner.as_ptr(), other.inner.as_ptr())
+    }
+
+
+    pub fn as_ptr(this: &Self) -> *const T {
+        &this.inner().value
+    }
+
+
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        // acquires the decrements of the other references, so their reads of the value happen
+        // before the writes through the result
+        if this.inner().strong.load(Ordering::Acquire) != 1 {
+            return None;
+        }
+
+        Some(unsafe { &mut (*this.inner.as_ptr()).value })
+    }
+
+
+    pub fn make_mut(this: &mut Self) -> &mut T
+    where
+        T: Clone
+    {
+        if this.inner().strong.load(Ordering::Acquire) != 1 {
+            // other owners keep the current value, this one continues with its own copy
+            *this = ThinArc::new((**this).clone());
+        }
+
+        unsafe { &mut (*this.inner.as_ptr()).value }
+    }
+
+
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        let strong = &this.inner().strong;
+        if strong.compare_exchange(1, 0, Ordering::Relaxed, Ordering::Relaxed).is_err() {
+            return Err(this);
+        }
+
+        fence(Ordering::Acquire);
+        Ok(unsafe { Self::take_value(this) })
+    }
+
+
+    pub fn into_inner(this: Self) -> Option<T> {
+        if this.inner().strong.fetch_sub(1, Ordering::Release) != 1 {
+            // the reference was given up by hand above
+            mem::forget(this);
+            return None;
+        }
+
+        fence(Ordering::Acquire);
+        Some(unsafe { Self::take_value(this) })
+    }
+
+
+    // moves the value out once the strong count reached zero and frees the allocation
+    unsafe fn take_value(this: Self) -> T {
+        let this = ManuallyDrop::new(this);
+        let inner = Box::from_raw(this.inner.as_ptr());
+        inner.value
+    }
+
+
+    fn inner(&self) -> &ThinArcInner<T> {
+        // the allocation lives for as long as this reference does
+        unsafe { self.inner.as_ref() }
+    }
+}
+
+
+impl<T> Clone for ThinArc<T> {
+    fn clone(&self) -> Self {
+        // holding `self` keeps the value alive, so the increment needs no synchronization
+        if self.inner().strong.fetch_add(1, Ordering::Relaxed) > MAX_REFCOUNT {
+            process::abort();
+        }
+
+        ThinArc {
+            inner: self.inner,
+            _marker: PhantomData
+        }
+    }
+}
+
+
+impl<T> Drop for ThinArc<T> {
+    fn drop(&mut self) {
+        if self.inner().strong.fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+
+        // synchronizes with the decrements of all other references, so their uses of the value
+        // happen before it is dropped; no weak reference can be left, so the memory goes too
+        fence(Ordering::Acquire);
+        unsafe { drop(Box::from_raw(self.inner.as_ptr())) };
+    }
+}
+
+
+impl<T> Deref for ThinArc<T> {
+    type Target = T;
+
+
+    fn deref(&self) -> &T {
+        &self.inner().value
+    }
+}
+
+
+impl<T: Default> Default for ThinArc<T> {
+    fn default() -> Self {
+        ThinArc::new(T::default())
+    }
+}
+
+
+impl<T: fmt::Debug> fmt::Debug for ThinArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+
+impl<T: fmt::Display> fmt::Display for ThinArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+
+impl<T: Hash> Hash for ThinArc<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+
+impl<T: PartialEq> PartialEq for ThinArc<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+
+impl<T: Eq> Eq for ThinArc<T> {}
+
+
+impl<T: PartialOrd> PartialOrd for ThinArc<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+
+impl<T: Ord> Ord for ThinArc<T> {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        (**self).cmp(&**other)
+    }
+}
+
+
+impl<T> Borrow<T> for ThinArc<T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+
+impl<T> AsRef<T> for ThinArc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+
+impl<T> From<T> for ThinArc<T> {
+    fn from(value: T) -> Self {
+        ThinArc::new(value)
+    }
+}
+
+
+#[cfg(all(test, not(feature = "loom")))]
+mod tests {
+    use std::mem;
+    use std::thread;
+    use crate::thin_arc::{ThinArc, ThinArcInner};
+
+
+    #[test]
+    fn thin_arc_shared_between_threads() {
+        let thin_arc = ThinArc::new(vec![1, 2, 3]);
+
+        let sums: Vec<i32> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..4).map(|_| {
+                let clone = thin_arc.clone();
+                scope.spawn(move || clone.iter().sum())
+            }).collect();
+
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+
+        assert_eq!(sums, [6; 4]);
+        assert_eq!(ThinArc::strong_count(&thin_arc), 1);
+        assert!(ThinArc::ptr_eq(&thin_arc, &thin_arc.clone()));
+        assert_eq!(ThinArc::as_ptr(&thin_arc), &*thin_arc as *const Vec<i32>);
+
+        // the allocation has no room for a weak count
+        assert_eq!(mem::size_of::<ThinArcInner<u64>>(), 2 * mem::size_of::<usize>());
+    }
+
+
+    #[test]
+    fn thin_arc_ownership() {
+        let mut thin_arc = ThinArc::new(String::from("a"));
+        ThinArc::get_mut(&mut thin_arc).unwrap().push('b');
+
+        let clone = thin_arc.clone();
+        assert!(ThinArc::get_mut(&mut thin_arc).is_none());
+        ThinArc::make_mut(&mut thin_arc).push('c');
+        assert_eq!((&*thin_arc, &*clone), (&String::from("abc"), &String::from("ab")));
+        assert!(!ThinArc::ptr_eq(&thin_arc, &clone));
+
+        let ptr = ThinArc::as_ptr(&thin_arc);
+        ThinArc::make_mut(&mut thin_arc).push('d');
+        assert_eq!(ThinArc::as_ptr(&thin_arc), ptr);
+
+        let shared = clone.clone();
+        let clone = ThinArc::try_unwrap(clone).unwrap_err();
+        assert_eq!(ThinArc::into_inner(shared), None);
+        assert_eq!(ThinArc::try_unwrap(clone).unwrap(), "ab");
+        assert_eq!(ThinArc::into_inner(thin_arc).unwrap(), "abcd");
+    }
+
+
+    #[test]
+    fn thin_arc_trait_impls() {
+        let thin_arc = ThinArc::from(String::from("b"));
+
+        assert_eq!(format!("{:?}", thin_arc), "\"b\"");
+        assert_eq!(thin_arc.to_string(), "b");
+        assert_eq!(*ThinArc::<u8>::default(), 0);
+        assert!(ThinArc::new(String::from("a")) < thin_arc);
+        assert_eq!(thin_arc, ThinArc::new(String::from("b")));
+        assert_eq!(AsRef::<String>::as_ref(&thin_arc), "b");
+    }
+}
@@ -0,0 +1,385 @@
+/*
+- `ThinRc<T>` is a reference-counted pointer that is a single machine word even for unsized values
+such as `str`, `[T]` or `dyn Trait`. A `MyRc<str>` is a fat pointer, twice the size of a `usize`,
+because the length travels with every copy of the pointer; `ThinRc<str>` stores it once in the
+heap allocation instead, which halves the size of keys in a `HashMap<ThinRc<str>, V>`.
+
+- The allocation starts with a header holding the strong count and a full (fat) pointer to the
+value, which sits right behind the header. The `ThinRc<T>` itself only points at the header, so
+every access to the value loads its pointer from there first: a smaller handle in exchange for one
+extra load.
+
+- A `ThinRc<str>` or `ThinRc<[T]>` is built from a string or a slice as with `MyRc`, and a
+`ThinRc<dyn Trait>` with the `thin_rc!` macro, e.g. `thin_rc!(Logger => dyn Plugin)`, which moves
+the value into the allocation and records its vtable in the header.
+
+- `ThinRc<T>` is a lean sibling of `MyRc<T>`: it has no weak references, so the value and the
+allocation go away together once the last `ThinRc<T>` is dropped.
+
+- `Clone`, `Debug`, `Display`, `Hash`, `PartialEq`, `Eq`, `PartialOrd`, `Ord`, `Borrow<T>` and
+`AsRef<T>` forward to the value, so a `HashMap<ThinRc<str>, V>` can be looked up with a `&str`.
+*/
+use std::{fmt, ptr};
+use std::alloc::{self, Layout};
+use std::borrow::Borrow;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::ptr::NonNull;
+
+
+#[repr(C)]
+struct ThinInner<T: ?Sized> {
+    strong: Cell<usize>,
+    // points at the value right behind the header, together with its length or vtable
+    value: NonNull<T>
+}
+
+
+pub struct ThinRc<T: ?Sized> {
+    inner: NonNull<ThinInner<T>>,
+    _marker: PhantomData<T>
+}
+
+
+impl<T> ThinRc<T> {
+    pub fn new(value: T) -> Self {
+        unsafe { Self::new_unsize(value, |value| value) }
+    }
+}
+
+
+impl<T: ?Sized> ThinRc<T> {
+    /// # Safety
+    ///
+    /// `coerce` must return the pointer it is given, either unchanged or turned into a pointer to
+    /// an unsized type by an unsizing coercion (e.g. `*mut V` to `*mut dyn Trait`). The `thin_rc!`
+    /// macro calls this function with exactly such a closure and is the safe way to use it.
+    pub unsafe fn new_unsize<V>(value: V, coerce: impl FnOnce(*mut V) -> *mut T) -> Self {
+        let inner = Self::allocate(Layout::new::<V>(), |memory| coerce(memory as *mut V));
+        ((*inner.as_ptr()).value.as_ptr() as *mut V).write(value);
+
+        ThinRc {
+            inner,
+            _marker: PhantomData
+        }
+    }
+
+
+    pub fn strong_count(this: &Self) -> usize {
+        this.inner().strong.get()
+    }
+
+
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        ptr::eq(this.inner.as_ptr(), other.inner.as_ptr())
+    }
+
+
+    pub fn as_ptr(this: &Self) -> *const T {
+        this.inner().value.as_ptr()
+    }
+
+
+    fn inner(&self) -> &ThinInner<T> {
+        unsafe { self.inner.as_ref() }
+    }
+
+
+    // the header and the value are laid out as if they were the fields of one `repr(C)` struct
+    fn layout(value: Layout) -> (Layout, usize) {
+        let (layout, offset) = Layout::new::<ThinInner<T>>()
+            .extend(value)
+            .expect("value too large");
+
+        (layout.pad_to_align(), offset)
+    }
+
+
+    // allocates a header followed by room for a value with the layout `value`, and points the
+    // header at that room through `pointer`, which adds the length or vtable to its address; the
+    // value itself is left for the caller to write
+    unsafe fn allocate(value: Layout, pointer: impl FnOnce(*mut u8) -> *mut T) -> NonNull<ThinInner<T>> {
+        let (layout, offset) = Self::layout(value);
+
+        let memory = alloc::alloc(layout);
+        if memory.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+
+        let inner = memory as *mut ThinInner<T>;
+        (&raw mut (*inner).strong).write(Cell::new(1));
+        (&raw mut (*inner).value).write(NonNull::new_unchecked(pointer(memory.add(offset))));
+
+        NonNull::new_unchecked(inner)
+    }
+}
+
+
+impl<T> ThinRc<[T]> {
+    // moves `len` elements from `values` into a new allocation
+    unsafe fn copy_from(values: *const T, len: usize) -> Self {
+        let layout = Layout::array::<T>(len).expect("slice too large");
+        let inner = Self::allocate(layout, |memory| ptr::slice_from_raw_parts_mut(memory as *mut T, len));
+        ptr::copy_nonoverlapping(values, (*inner.as_ptr()).value.as_ptr() as *mut T, len);
+
+        ThinRc {
+            inner,
+            _marker: PhantomData
+        }
+    }
+}
+
+
+// builds a `ThinRc<U>` from a value that coerces to the unsized `U`, e.g.
+// `thin_rc!(Logger => dyn Plugin)`
+#[macro_export]
+macro_rules! thin_rc {
+    ($value:expr => $target:ty) => {
+        // `$value` is evaluated outside of the `unsafe` block
+        match $value {
+            // the only implicit coercion from one raw pointer type to another is an unsizing one
+            value => unsafe { $crate::thin_rc::ThinRc::<$target>::new_unsize(value, |value| -> *mut $target { value }) }
+        }
+    };
+}
+
+
+impl<T> From<Vec<T>> for ThinRc<[T]> {
+    fn from(mut values: Vec<T>) -> Self {
+        unsafe {
+            let thin_rc = Self::copy_from(values.as_ptr(), values.len());
+
+            // the elements moved into the new allocation, so the vector only frees its buffer
+            values.set_len(0);
+            thin_rc
+        }
+    }
+}
+
+
+impl<T: Clone> From<&[T]> for ThinRc<[T]> {
+    fn from(values: &[T]) -> Self {
+        // cloning into a vector first keeps a panicking `clone` from leaving a half-filled
+        // allocation behind
+        ThinRc::from(values.to_vec())
+    }
+}
+
+
+impl From<&str> for ThinRc<str> {
+    fn from(value: &str) -> Self {
+        let bytes = unsafe { ThinRc::<[u8]>::copy_from(value.as_ptr(), value.len()) };
+        let bytes = ManuallyDrop::new(bytes);
+
+        // `ThinInner<str>` has the same layout as `ThinInner<[u8]>`, and the bytes came from a
+        // `str`, so they are valid UTF-8
+        ThinRc {
+            inner: bytes.inner.cast::<ThinInner<str>>(),
+            _marker: PhantomData
+        }
+    }
+}
+
+
+impl From<String> for ThinRc<str> {
+    fn from(value: String) -> Self {
+        ThinRc::from(value.as_str())
+    }
+}
+
+
+impl<T: ?Sized> Clone for ThinRc<T> {
+    fn clone(&self) -> Self {
+        let strong = &self.inner().strong;
+
+        // an overflow needs leaked references, and wrapping around would free a live value
+        match strong.get().checked_add(1) {
+            Some(count) => strong.set(count),
+            None => std::process::abort()
+        }
+
+        ThinRc {
+            inner: self.inner,
+            _marker: PhantomData
+        }
+    }
+}
+
+
+impl<T: ?Sized> Deref for ThinRc<T> {
+    type Target = T;
+
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.inner().value.as_ref() }
+    }
+}
+
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for ThinRc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+
+impl<T: ?Sized + fmt::Display> fmt::Display for ThinRc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+
+impl<T: ?Sized + Hash> Hash for ThinRc<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+
+impl<T: ?Sized + PartialEq> PartialEq for ThinRc<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+
+impl<T: ?Sized + Eq> Eq for ThinRc<T> {}
+
+
+impl<T: ?Sized + PartialOrd> PartialOrd for ThinRc<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+
+impl<T: ?Sized + Ord> Ord for ThinRc<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+
+impl<T: ?Sized> Borrow<T> for ThinRc<T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+
+impl<T: ?Sized> AsRef<T> for ThinRc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+
+impl<T: ?Sized> Drop for ThinRc<T> {
+    fn drop(&mut self) {
+        let strong = self.inner().strong.get() - 1;
+        self.inner().strong.set(strong);
+
+        if strong == 0 {
+            unsafe {
+                let value = self.inner().value.as_ptr();
+                let (layout, _) = Self::layout(Layout::for_value(&*value));
+
+                ptr::drop_in_place(value);
+                alloc::dealloc(self.inner.as_ptr() as *mut u8, layout);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use crate::rc::MyRc;
+    use crate::thin_rc::ThinRc;
+
+
+    #[test]
+    fn thin_rc_is_one_word() {
+        assert_eq!(mem::size_of::<ThinRc<str>>(), mem::size_of::<usize>());
+        assert_eq!(mem::size_of::<ThinRc<[u64]>>(), mem::size_of::<usize>());
+        assert_eq!(mem::size_of::<ThinRc<dyn Fn()>>(), mem::size_of::<usize>());
+        assert_eq!(mem::size_of::<Option<ThinRc<str>>>(), mem::size_of::<usize>());
+        assert_eq!(mem::size_of::<MyRc<str>>(), 2 * mem::size_of::<usize>());
+    }
+
+
+    #[test]
+    fn thin_rc_str_keys() {
+        let mut counts: HashMap<ThinRc<str>, usize> = HashMap::new();
+        for word in "the quick fox jumps over the lazy dog the end".split(' ') {
+            *counts.entry(ThinRc::from(word)).or_default() += 1;
+        }
+
+        assert_eq!(counts["the"], 3);
+        assert_eq!(counts.get("fox"), Some(&1));
+        assert!(!counts.contains_key("cat"));
+
+        let key = ThinRc::from(String::from("shared"));
+        let key_clone = key.clone();
+        assert!(ThinRc::ptr_eq(&key, &key_clone));
+        assert_eq!(ThinRc::strong_count(&key), 2);
+        assert_eq!(&*key_clone, "shared");
+        assert_eq!(&*ThinRc::from(""), "");
+    }
+
+
+    #[test]
+    fn thin_rc_slice() {
+        let from_vec: ThinRc<[String]> = ThinRc::from(vec![String::from("a"), String::from("b")]);
+        let from_slice: ThinRc<[String]> = ThinRc::from(&[String::from("a"), String::from("b")][..]);
+        let empty: ThinRc<[u8]> = ThinRc::from(Vec::new());
+
+        assert_eq!(from_vec, from_slice);
+        assert_eq!(from_vec.len(), 2);
+        assert!(empty.is_empty());
+    }
+
+
+    #[test]
+    fn thin_rc_trait_object() {
+        trait Shape {
+            fn area(&self) -> f64;
+        }
+
+        struct Square(f64);
+
+        impl Shape for Square {
+            fn area(&self) -> f64 {
+                self.0 * self.0
+            }
+        }
+
+        struct Tracked<'a>(&'a Cell<usize>);
+
+        impl Drop for Tracked<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let shape: ThinRc<dyn Shape> = crate::thin_rc!(Square(3.0) => dyn Shape);
+        let shape_clone = shape.clone();
+        assert_eq!(shape_clone.area(), 9.0);
+
+        let drops = Cell::new(0);
+        let tracked = ThinRc::new(Tracked(&drops));
+        let tracked_clone = tracked.clone();
+        drop(tracked);
+        assert_eq!(drops.get(), 0);
+        drop(tracked_clone);
+        assert_eq!(drops.get(), 1);
+    }
+}
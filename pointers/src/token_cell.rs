@@ -0,0 +1,236 @@
+/*
+- `TokenCell<T>` is the runtime-checked sibling of `GhostCell<'id, T>`: every cell belongs to one
+`CellOwner`, and the value can only be reached through that owner. A `&CellOwner` unlocks shared
+borrows of all of its cells, a `&mut CellOwner` unlocks an exclusive borrow, so one borrow of the
+owner covers any number of cells, something a `RefCell<T>` per value cannot express.
+
+- Instead of a brand lifetime, every owner gets a unique id when it is created, and each cell
+stores the id of the owner it was created for. Borrowing compares the two ids, which is the only
+runtime cost; there is no borrow flag to update and nothing to release afterwards.
+
+- Handing a cell the wrong owner is a bug in the caller, so `borrow` and `borrow_mut` panic, while
+`try_borrow` and `try_borrow_mut` report it as a `ForeignOwnerError`.
+
+- Unlike a `GhostToken`, a `CellOwner` is an ordinary value: it can be stored in a struct,
+returned from functions and outlive any scope, which makes it practical for long-lived graphs of
+mutable nodes.
+
+- `CellOwner::borrow_mut2` borrows two cells of the same owner mutably at once, after checking that
+they are not the same cell, e.g. to update both ends of an edge.
+
+- As with `GhostCell`, the owner is what grants access, so cells can be shared between threads
+whenever `&T` and `&mut T` could be.
+*/
+use std::{error::Error, fmt, ptr};
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+
+// ids are never reused; a 64-bit counter does not run out in practice
+static NEXT_OWNER_ID: AtomicU64 = AtomicU64::new(0);
+
+
+pub struct CellOwner {
+    id: u64
+}
+
+
+impl CellOwner {
+    pub fn new() -> Self {
+        CellOwner {id: NEXT_OWNER_ID.fetch_add(1, Ordering::Relaxed)}
+    }
+
+
+    pub fn cell<T>(&self, value: T) -> TokenCell<T> {
+        TokenCell::new(value, self)
+    }
+
+
+    #[track_caller]
+    pub fn borrow_mut2<'owner, T: ?Sized, U: ?Sized>(
+        &'owner mut self,
+        first: &'owner TokenCell<T>,
+        second: &'owner TokenCell<U>
+    ) -> (&'owner mut T, &'owner mut U) {
+        assert!(first.owner == self.id && second.owner == self.id, "{}", ForeignOwnerError {_private: ()});
+        assert!(!ptr::addr_eq(first, second), "cannot borrow the same cell mutably twice");
+
+        unsafe { (&mut *first.value.get(), &mut *second.value.get()) }
+    }
+}
+
+
+impl Default for CellOwner {
+    fn default() -> Self {
+        CellOwner::new()
+    }
+}
+
+
+pub struct TokenCell<T: ?Sized> {
+    owner: u64,
+    value: UnsafeCell<T>
+}
+
+
+// the owner decides who may touch the value, so the cell is thread-safe whenever `&T` and `&mut T`
+// may be sent between threads
+unsafe impl<T: ?Sized + Send> Send for TokenCell<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for TokenCell<T> {}
+
+
+impl<T> TokenCell<T> {
+    pub fn new(value: T, owner: &CellOwner) -> Self {
+        Self {
+            owner: owner.id,
+            value: UnsafeCell::new(value)
+        }
+    }
+
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+
+impl<T: ?Sized> TokenCell<T> {
+    #[track_caller]
+    pub fn borrow<'owner>(&'owner self, owner: &'owner CellOwner) -> &'owner T {
+        match self.try_borrow(owner) {
+            Ok(value) => value,
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+
+    #[track_caller]
+    pub fn borrow_mut<'owner>(&'owner self, owner: &'owner mut CellOwner) -> &'owner mut T {
+        match self.try_borrow_mut(owner) {
+            Ok(value) => value,
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+
+    pub fn try_borrow<'owner>(&'owner self, owner: &'owner CellOwner) -> Result<&'owner T, ForeignOwnerError> {
+        if self.owner != owner.id {
+            return Err(ForeignOwnerError {_private: ()});
+        }
+
+        // a shared borrow of the owner rules out any `&mut T` into its cells
+        Ok(unsafe { &*self.value.get() })
+    }
+
+
+    pub fn try_borrow_mut<'owner>(&'owner self, owner: &'owner mut CellOwner) -> Result<&'owner mut T, ForeignOwnerError> {
+        if self.owner != owner.id {
+            return Err(ForeignOwnerError {_private: ()});
+        }
+
+        // an exclusive borrow of the owner rules out any other reference into its cells
+        Ok(unsafe { &mut *self.value.get() })
+    }
+
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+
+#[derive(Debug)]
+pub struct ForeignOwnerError {
+    _private: ()
+}
+
+
+impl fmt::Display for ForeignOwnerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("cell belongs to a different owner")
+    }
+}
+
+
+impl Error for ForeignOwnerError {}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::token_cell::{CellOwner, TokenCell};
+
+
+    #[test]
+    fn token_cell_borrow_and_borrow_mut() {
+        let mut owner = CellOwner::new();
+        let cell = owner.cell(vec![1, 2]);
+
+        cell.borrow_mut(&mut owner).push(3);
+
+        let first = cell.borrow(&owner);
+        let second = cell.borrow(&owner);
+        assert_eq!(first, second);
+        assert_eq!(*first, [1, 2, 3]);
+    }
+
+
+    #[test]
+    fn token_cell_foreign_owner() {
+        let owner = CellOwner::new();
+        let mut other = CellOwner::new();
+        let mut cell = TokenCell::new(String::from("token"), &owner);
+
+        assert!(cell.try_borrow(&other).is_err());
+        assert_eq!(cell.try_borrow_mut(&mut other).unwrap_err().to_string(), "cell belongs to a different owner");
+
+        cell.get_mut().push_str("cell");
+        assert_eq!(cell.into_inner(), "tokencell");
+    }
+
+
+    #[test]
+    #[should_panic(expected = "cell belongs to a different owner")]
+    fn token_cell_borrow_with_foreign_owner_panics() {
+        let owner = CellOwner::new();
+        let cell = owner.cell(1);
+
+        cell.borrow(&CellOwner::new());
+    }
+
+
+    #[test]
+    fn token_cell_graph() {
+        struct Node {
+            value: TokenCell<u32>,
+            edges: Vec<usize>
+        }
+
+        let mut owner = CellOwner::new();
+        let nodes = [
+            Node {value: owner.cell(1), edges: vec![1, 2]},
+            Node {value: owner.cell(2), edges: vec![2]},
+            Node {value: owner.cell(3), edges: vec![]}
+        ];
+
+        // moves every value along its edges, borrowing both ends at once through the owner
+        for (from, node) in nodes.iter().enumerate() {
+            for &to in &node.edges {
+                let (source, target) = owner.borrow_mut2(&nodes[from].value, &nodes[to].value);
+                *target += *source;
+            }
+        }
+
+        let values: Vec<u32> = nodes.iter().map(|node| *node.value.borrow(&owner)).collect();
+        assert_eq!(values, [1, 3, 7]);
+    }
+
+
+    #[test]
+    #[should_panic(expected = "cannot borrow the same cell mutably twice")]
+    fn token_cell_borrow_mut2_same_cell_panics() {
+        let mut owner = CellOwner::new();
+        let cell = owner.cell(1);
+
+        owner.borrow_mut2(&cell, &cell);
+    }
+}
@@ -0,0 +1,231 @@
+/*
+- `TreeNode<T>` is a handle to a node of a mutable tree, and puts `MyRc`, `MyRefCell` and `MyWeak`
+together: every node lives in a `MyRc<MyRefCell<..>>`, parents own their children through the
+`MyRc`, and children point back at their parent through a `MyWeak`.
+
+- The back pointer has to be weak: with strong references in both directions, a parent and its
+children would keep each other alive forever, and the whole tree would leak once the last outside
+handle is dropped. With weak parents, dropping the last handle to the root frees the whole tree,
+and a handle to an inner node keeps its subtree alive but not its ancestors.
+
+- `attach` moves a node, with its subtree, below a new parent, detaching it from its old parent
+first, and `detach` turns a node into the root of its own tree. Attaching a node below itself or
+below one of its own descendants would create a cycle of strong references, so it panics.
+
+- The `MyRefCell` of a node is only borrowed for as long as a method runs (or for as long as the
+guard of `value` or `value_mut` lives), so nodes can be traversed and reorganised freely between
+calls. `depth_first` lists a subtree in pre-order without recursion.
+*/
+use crate::rc::{MyRc, MyWeak};
+use crate::refcell::{MyRefCell, Ref, RefMut};
+
+
+pub struct TreeNode<T> {
+    inner: MyRc<MyRefCell<Node<T>>>
+}
+
+
+struct Node<T> {
+    value: T,
+    parent: MyWeak<MyRefCell<Node<T>>>,
+    children: Vec<MyRc<MyRefCell<Node<T>>>>
+}
+
+
+impl<T> TreeNode<T> {
+    pub fn new(value: T) -> Self {
+        TreeNode {
+            inner: MyRc::new(MyRefCell::new(Node {
+                value,
+                parent: MyWeak::new(),
+                children: Vec::new()
+            }))
+        }
+    }
+
+
+    pub fn value(&self) -> Ref<'_, T> {
+        Ref::map(self.inner.borrow(), |node| &node.value)
+    }
+
+
+    pub fn value_mut(&self) -> RefMut<'_, T> {
+        RefMut::map(self.inner.borrow_mut(), |node| &mut node.value)
+    }
+
+
+    pub fn parent(&self) -> Option<TreeNode<T>> {
+        let inner = self.inner.borrow().parent.upgrade()?;
+        Some(TreeNode {inner})
+    }
+
+
+    pub fn children(&self) -> Vec<TreeNode<T>> {
+        self.inner.borrow().children.iter().map(|child| TreeNode {inner: child.clone()}).collect()
+    }
+
+
+    pub fn root(&self) -> TreeNode<T> {
+        let mut root = self.clone();
+        while let Some(parent) = root.parent() {
+            root = parent;
+        }
+        root
+    }
+
+
+    #[track_caller]
+    pub fn attach(&self, child: &TreeNode<T>) {
+        // walking up from the new parent must not run into the child, or the child would end up
+        // owning itself
+        let mut ancestor = Some(self.clone());
+        while let Some(node) = ancestor {
+            assert!(!node.ptr_eq(child), "cannot attach a node below itself or its descendants");
+            ancestor = node.parent();
+        }
+
+        child.detach();
+        child.inner.borrow_mut().parent = MyRc::downgrade(&self.inner);
+        self.inner.borrow_mut().children.push(child.inner.clone());
+    }
+
+
+    pub fn detach(&self) {
+        let parent = self.inner.borrow().parent.upgrade();
+
+        if let Some(parent) = parent {
+            parent.borrow_mut().children.retain(|child| !MyRc::ptr_eq(child, &self.inner));
+            self.inner.borrow_mut().parent = MyWeak::new();
+        }
+    }
+
+
+    // the node itself followed by its subtree, every node before its children
+    pub fn depth_first(&self) -> Vec<TreeNode<T>> {
+        let mut nodes = Vec::new();
+        let mut pending = vec![self.clone()];
+
+        while let Some(node) = pending.pop() {
+            // pushed in reverse, so the first child is visited first
+            pending.extend(node.children().into_iter().rev());
+            nodes.push(node);
+        }
+
+        nodes
+    }
+
+
+    pub fn ptr_eq(&self, other: &TreeNode<T>) -> bool {
+        MyRc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+
+impl<T> Clone for TreeNode<T> {
+    fn clone(&self) -> Self {
+        TreeNode {inner: self.inner.clone()}
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use crate::rc::MyRc;
+    use crate::tree::TreeNode;
+
+
+    fn values(nodes: &[TreeNode<&'static str>]) -> Vec<&'static str> {
+        nodes.iter().map(|node| *node.value()).collect()
+    }
+
+
+    #[test]
+    fn tree_attach_and_traverse() {
+        let root = TreeNode::new("root");
+        let left = TreeNode::new("left");
+        let right = TreeNode::new("right");
+        let leaf = TreeNode::new("leaf");
+
+        root.attach(&left);
+        root.attach(&right);
+        left.attach(&leaf);
+
+        assert_eq!(values(&root.depth_first()), ["root", "left", "leaf", "right"]);
+        assert_eq!(values(&root.children()), ["left", "right"]);
+        assert!(leaf.parent().unwrap().ptr_eq(&left));
+        assert!(leaf.root().ptr_eq(&root));
+        assert!(root.parent().is_none());
+
+        *leaf.value_mut() = "renamed";
+        assert_eq!(values(&left.depth_first()), ["left", "renamed"]);
+    }
+
+
+    #[test]
+    fn tree_detach_and_move() {
+        let root = TreeNode::new("root");
+        let branch = TreeNode::new("branch");
+        let leaf = TreeNode::new("leaf");
+        root.attach(&branch);
+        branch.attach(&leaf);
+
+        // moving a node takes it away from its old parent
+        root.attach(&leaf);
+        assert!(branch.children().is_empty());
+        assert_eq!(values(&root.depth_first()), ["root", "branch", "leaf"]);
+
+        branch.detach();
+        assert!(branch.parent().is_none());
+        assert_eq!(values(&root.depth_first()), ["root", "leaf"]);
+
+        // detaching a root changes nothing
+        root.detach();
+        assert_eq!(root.children().len(), 1);
+    }
+
+
+    #[test]
+    #[should_panic(expected = "cannot attach a node below itself or its descendants")]
+    fn tree_attach_to_descendant_panics() {
+        let root = TreeNode::new(1);
+        let child = TreeNode::new(2);
+        root.attach(&child);
+
+        child.attach(&root);
+    }
+
+
+    #[test]
+    fn tree_does_not_leak() {
+        struct Tracked<'a>(&'a Cell<usize>);
+
+        impl Drop for Tracked<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let drops = Cell::new(0);
+        let root = TreeNode::new(Tracked(&drops));
+        let child = TreeNode::new(Tracked(&drops));
+        root.attach(&child);
+        child.attach(&TreeNode::new(Tracked(&drops)));
+
+        // the weak parent pointers do not count as owners
+        assert_eq!(MyRc::strong_count(&root.inner), 1);
+        assert_eq!(MyRc::weak_count(&root.inner), 1);
+        let root_weak = MyRc::downgrade(&root.inner);
+        let child_weak = MyRc::downgrade(&child.inner);
+
+        // a handle to an inner node keeps its subtree, but not its parent
+        drop(root);
+        assert!(root_weak.upgrade().is_none());
+        assert_eq!(drops.get(), 1);
+        assert!(child.parent().is_none());
+
+        drop(child);
+        assert!(child_weak.upgrade().is_none());
+        assert_eq!(drops.get(), 3);
+    }
+}
@@ -0,0 +1,170 @@
+/*
+- `VolatileCell<T>` wraps a value whose every read and write must actually reach memory, such as a
+memory-mapped hardware register. It uses `read_volatile`/`write_volatile`, so the compiler can
+neither elide repeated accesses nor merge or reorder them relative to other volatile accesses.
+
+- Like `Cell<T>`, it builds on `UnsafeCell<T>` and is mutated through a shared reference, which
+matches how registers behave: the hardware may change the value at any time regardless of who
+holds a reference to it.
+
+- `VolatileCell<T>` is `#[repr(transparent)]`, so a block of memory can be described as a struct
+of cells and overlaid on a raw address with `from_ptr`. That hands out a reference, though, and a
+reference tells the compiler the memory is dereferenceable, so it may add reads of its own, such
+as a load hoisted out of a branch. Those reads are harmless on ordinary RAM (shared buffers, DMA
+descriptors) but not on device registers, where a read can clear a flag or pop a FIFO. `from_ptr`
+is therefore for ordinary memory only.
+
+- For real MMIO, `MyVolatilePtr<'a, T>` only ever holds the raw address and touches it through
+`read_volatile`/`write_volatile`, so no reference to the register exists and the compiler is not
+allowed to access it on its own. It is `Copy`, like the address it wraps.
+
+- Volatile is not atomic. Accesses are not synchronized between threads and give no ordering
+guarantees with respect to normal memory accesses, so the type is `!Sync` like `Cell<T>`; use
+atomics or fences when that matters.
+
+- Values are copied in and out, so `T` must be `Copy`, and should be a type the hardware can read
+and write in a single access (typically `u8`..`u64`) to avoid torn register accesses.
+*/
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+
+
+#[repr(transparent)]
+pub struct MyVolatileCell<T> {
+    value: UnsafeCell<T>
+}
+
+
+pub struct MyVolatilePtr<'a, T> {
+    ptr: NonNull<T>,
+    // borrows the memory for `'a` and, like the cell, is neither `Send` nor `Sync`
+    _marker: PhantomData<&'a MyVolatileCell<T>>
+}
+
+
+impl<T: Copy> MyVolatileCell<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            value: UnsafeCell::new(value)
+        }
+    }
+
+
+    /// # Safety
+    ///
+    /// `ptr` must be valid, aligned and readable/writable for `'a`, and nothing may access it
+    /// non-volatilely while the returned reference is alive. It must point to ordinary memory,
+    /// where an extra read has no effect; use `MyVolatilePtr` for device registers.
+    pub unsafe fn from_ptr<'a>(ptr: *mut T) -> &'a MyVolatileCell<T> {
+        &*(ptr as *const MyVolatileCell<T>)
+    }
+
+
+    pub fn read(&self) -> T {
+        unsafe { self.value.get().read_volatile() }
+    }
+
+
+    pub fn write(&self, value: T) {
+        unsafe { self.value.get().write_volatile(value) }
+    }
+
+
+    pub const fn as_ptr(&self) -> *mut T {
+        self.value.get()
+    }
+}
+
+
+impl<'a, T: Copy> MyVolatilePtr<'a, T> {
+    /// # Safety
+    ///
+    /// `ptr` must be non-null, aligned and valid for volatile reads and writes of `T` for `'a`,
+    /// and nothing may access it non-volatilely while the handle or its copies are alive.
+    pub const unsafe fn new(ptr: *mut T) -> Self {
+        MyVolatilePtr {
+            ptr: NonNull::new_unchecked(ptr),
+            _marker: PhantomData
+        }
+    }
+
+
+    pub fn read(self) -> T {
+        unsafe { self.ptr.as_ptr().read_volatile() }
+    }
+
+
+    pub fn write(self, value: T) {
+        unsafe { self.ptr.as_ptr().write_volatile(value) }
+    }
+
+
+    pub const fn as_ptr(self) -> *mut T {
+        self.ptr.as_ptr()
+    }
+}
+
+
+impl<T> Clone for MyVolatilePtr<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+
+impl<T> Copy for MyVolatilePtr<'_, T> {}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::volatile_cell::{MyVolatileCell, MyVolatilePtr};
+
+
+    #[test]
+    fn my_volatile_cell_read_write() {
+        let cell = MyVolatileCell::new(0u32);
+
+        cell.write(0xdead);
+        cell.write(0xbeef);
+        assert_eq!(cell.read(), 0xbeef);
+        assert_eq!(unsafe { cell.as_ptr().read() }, 0xbeef);
+    }
+
+
+    #[test]
+    fn my_volatile_cell_register_block() {
+        #[repr(C)]
+        struct Registers {
+            control: MyVolatileCell<u32>,
+            status: MyVolatileCell<u32>
+        }
+
+        // stands in for a memory-mapped peripheral
+        let mut memory = [0u32; 2];
+        let registers = unsafe { &*(memory.as_mut_ptr() as *const Registers) };
+
+        registers.control.write(1);
+        // emulates the hardware updating the status register behind the program's back
+        unsafe { registers.status.as_ptr().write_volatile(0x80) };
+
+        assert_eq!(registers.status.read(), 0x80);
+        assert_eq!(registers.control.read(), 1);
+
+        let control = unsafe { MyVolatileCell::from_ptr(memory.as_mut_ptr()) };
+        control.write(2);
+        assert_eq!(memory[0], 2);
+    }
+
+    #[test]
+    fn my_volatile_ptr_register() {
+        // stands in for a device register, which is never borrowed as a reference
+        let mut register = 0u32;
+        let status = unsafe { MyVolatilePtr::new(&mut register as *mut u32) };
+
+        let copy = status;
+        status.write(0x80);
+        assert_eq!(copy.read(), 0x80);
+        assert_eq!(unsafe { copy.as_ptr().read_volatile() }, 0x80);
+    }
+}
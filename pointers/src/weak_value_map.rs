@@ -0,0 +1,196 @@
+/*
+- A `WeakValueMap<K, V>` maps keys to values that it does not own: it stores a `MyWeak<V>` per key,
+so an entry is only alive for as long as some `MyRc<V>` of its value exists elsewhere. This is the
+classic memoization cache: `get_or_insert_with` builds a value the first time a key is asked for,
+hands out the same value for as long as anyone still uses it, and builds a fresh one after the last
+user let go.
+
+- Looking up a dead entry yields `None`, exactly as if the key had never been inserted. The entry
+itself stays in the table until it is pruned, since a lookup through `&self` cannot remove it.
+
+- Dead entries are pruned lazily: whenever an insertion grows the table to twice the number of
+entries that were alive after the last pruning, the dead ones are removed, which keeps the cost of
+pruning amortized O(1) per insertion and the table proportional to the live entries. `prune`
+removes them right away.
+
+- `len` counts the entries still in the table, including dead ones that were not pruned yet, while
+`live_len` only counts the entries that still upgrade.
+*/
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use crate::rc::{MyRc, MyWeak};
+
+
+// tables this small are not worth pruning
+const MIN_PRUNE_AT: usize = 16;
+
+
+pub struct WeakValueMap<K, V> {
+    entries: HashMap<K, MyWeak<V>>,
+    // the size at which an insertion prunes the dead entries
+    prune_at: usize
+}
+
+
+impl<K: Eq + Hash, V> WeakValueMap<K, V> {
+    pub fn new() -> Self {
+        WeakValueMap {
+            entries: HashMap::new(),
+            prune_at: MIN_PRUNE_AT
+        }
+    }
+
+
+    pub fn get<Q>(&self, key: &Q) -> Option<MyRc<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash
+    {
+        self.entries.get(key)?.upgrade()
+    }
+
+
+    // returns the live value the key mapped to before, if any
+    pub fn insert(&mut self, key: K, value: &MyRc<V>) -> Option<MyRc<V>> {
+        let old = self.entries.insert(key, MyRc::downgrade(value));
+
+        if self.entries.len() >= self.prune_at {
+            self.prune();
+        }
+
+        old?.upgrade()
+    }
+
+
+    pub fn get_or_insert_with(&mut self, key: K, f: impl FnOnce() -> V) -> MyRc<V> {
+        if let Some(value) = self.get(&key) {
+            return value;
+        }
+
+        let value = MyRc::new(f());
+        self.insert(key, &value);
+        value
+    }
+
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<MyRc<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash
+    {
+        self.entries.remove(key)?.upgrade()
+    }
+
+
+    // removes the dead entries and returns how many there were
+    pub fn prune(&mut self) -> usize {
+        let len = self.entries.len();
+        self.entries.retain(|_, value| value.upgrade().is_some());
+
+        self.prune_at = (2 * self.entries.len()).max(MIN_PRUNE_AT);
+        len - self.entries.len()
+    }
+
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+
+    pub fn live_len(&self) -> usize {
+        self.entries.values().filter(|value| value.upgrade().is_some()).count()
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+
+impl<K: Eq + Hash, V> Default for WeakValueMap<K, V> {
+    fn default() -> Self {
+        WeakValueMap::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use crate::rc::MyRc;
+    use crate::weak_value_map::WeakValueMap;
+
+
+    #[test]
+    fn weak_value_map_memoizes_while_alive() {
+        let builds = Cell::new(0);
+        let mut cache: WeakValueMap<String, String> = WeakValueMap::new();
+        let load = |cache: &mut WeakValueMap<String, String>, key: &str| {
+            cache.get_or_insert_with(key.to_string(), || {
+                builds.set(builds.get() + 1);
+                key.to_uppercase()
+            })
+        };
+
+        let first = load(&mut cache, "config");
+        let second = load(&mut cache, "config");
+        assert!(MyRc::ptr_eq(&first, &second));
+        assert_eq!(*cache.get("config").unwrap(), "CONFIG");
+        assert_eq!(builds.get(), 1);
+
+        // the entry vanishes with the last strong reference, and is built again on demand
+        drop(first);
+        drop(second);
+        assert!(cache.get("config").is_none());
+        assert_eq!(cache.live_len(), 0);
+
+        let third = load(&mut cache, "config");
+        assert_eq!(*third, "CONFIG");
+        assert_eq!(builds.get(), 2);
+    }
+
+
+    #[test]
+    fn weak_value_map_insert_and_remove() {
+        let mut map = WeakValueMap::new();
+        let one = MyRc::new(1);
+        let two = MyRc::new(2);
+
+        assert!(map.insert("key", &one).is_none());
+        assert!(MyRc::ptr_eq(&map.insert("key", &two).unwrap(), &one));
+        // the map never owns its values
+        assert_eq!(MyRc::strong_count(&two), 1);
+
+        assert!(MyRc::ptr_eq(&map.remove("key").unwrap(), &two));
+        assert!(map.remove("key").is_none());
+        assert!(map.is_empty());
+    }
+
+
+    #[test]
+    fn weak_value_map_prune() {
+        let mut map = WeakValueMap::new();
+        let alive: Vec<MyRc<usize>> = (0..4).map(MyRc::new).collect();
+        for (key, value) in alive.iter().enumerate() {
+            map.insert(key, value);
+        }
+
+        for key in 4..10 {
+            map.insert(key, &MyRc::new(key));
+        }
+        assert_eq!(map.len(), 10);
+        assert_eq!(map.live_len(), 4);
+        assert_eq!(map.prune(), 6);
+        assert_eq!(map.len(), 4);
+
+        // insertions prune dead entries on their own, so the table stays proportional to the
+        // live entries
+        for key in 10..1_000 {
+            map.insert(key, &MyRc::new(key));
+            assert!(map.len() <= 16);
+        }
+        assert_eq!(*map.get(&3).unwrap(), 3);
+    }
+}
@@ -0,0 +1,79 @@
+use std::sync::Barrier;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use send_and_sync::atomic_counter::MyAtomicCounter;
+use send_and_sync::local_counter::LocalCounter;
+use send_and_sync::sharded_counter::ShardedCounter;
+use send_and_sync::sync::MyCounter;
+
+
+// the same harness as `contended` in the pointers arc bench: each of `threads` threads
+// increments `iters` times, all released by one barrier, and the time runs until the slowest is done
+fn contended(threads: usize, iters: u64, op: impl Fn() + Sync) -> Duration {
+    let barrier = Barrier::new(threads + 1);
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                barrier.wait();
+                for _ in 0..iters {
+                    op();
+                }
+            });
+        }
+
+        barrier.wait();
+        let start = Instant::now();
+
+        // leaving the scope joins the threads
+        start
+    }).elapsed()
+}
+
+
+// all threads count into one counter, the way request handlers bump a shared statistic. One
+// iteration is one increment per thread, so the throughput is in increments of all threads. With
+// more threads than cores a thread can be preempted while it holds the mutex and the other ones
+// wait out its time slice, so those counts are not run
+fn increment_contended(c: &mut Criterion) {
+    let mut group = c.benchmark_group("counter_increment_contended");
+    let cores = thread::available_parallelism().map_or(1, |cores| cores.get());
+
+    for threads in [1, 2, 4, 8, 16].into_iter().filter(|&threads| threads == 1 || threads <= cores) {
+        group.throughput(Throughput::Elements(threads as u64));
+
+        let counter = MyCounter::<i64>::default();
+        group.bench_with_input(BenchmarkId::new("mutex", threads), &threads, |b, &threads| {
+            b.iter_custom(|iters| contended(threads, iters, || counter.increment()))
+        });
+
+        let counter = MyAtomicCounter::new();
+        group.bench_with_input(BenchmarkId::new("atomic_seq_cst", threads), &threads, |b, &threads| {
+            b.iter_custom(|iters| contended(threads, iters, || counter.increment()))
+        });
+
+        let counter: MyAtomicCounter = MyAtomicCounter::with_ordering(Ordering::Relaxed);
+        group.bench_with_input(BenchmarkId::new("atomic_relaxed", threads), &threads, |b, &threads| {
+            b.iter_custom(|iters| contended(threads, iters, || counter.increment()))
+        });
+
+        let counter = ShardedCounter::new();
+        group.bench_with_input(BenchmarkId::new("sharded", threads), &threads, |b, &threads| {
+            b.iter_custom(|iters| contended(threads, iters, || counter.increment()))
+        });
+
+        let counter = LocalCounter::new();
+        group.bench_with_input(BenchmarkId::new("local", threads), &threads, |b, &threads| {
+            b.iter_custom(|iters| contended(threads, iters, || counter.increment()))
+        });
+    }
+
+    group.finish();
+}
+
+
+criterion_group!(benches, increment_contended);
+criterion_main!(benches);
@@ -0,0 +1,233 @@
+/*
+- `MyAtomicCounter<T>` is a lock-free counterpart of `MyCounter<T>`: the count is an atomic, so an
+increment is a single atomic instruction instead of locking a `Mutex`, and no thread ever blocks
+another one. `T` defaults to `i64`, which counts in an `AtomicI64`; the other `CounterValue` types
+bring their own atomic, and `f64` runs a compare-exchange loop for its arithmetic. Like `MyCounter`,
+it is `Send` and `Sync` and is shared between threads through an `Arc`.
+
+- Every atomic operation takes a memory ordering. `increment`, `add`, `decrement`, `get`,
+`get_and_reset` and `compare_and_set` use the ordering the counter was created with: `SeqCst` for
+`MyAtomicCounter::new` and `default`, or any other one through `with_ordering`. A counter that only
+counts (requests served, bytes sent) can use `Relaxed`, since no other memory depends on it, while a
+counter that tells other threads that some work is done needs `Release` on the writing side and
+`Acquire` on the reading side.
+
+- Loads cannot be `Release` and stores cannot be `Acquire`, so `get` weakens the ordering where
+needed: `Release` reads with `Relaxed`, and `AcqRel` reads with `Acquire`. A failed
+`compare_and_set` only reads, and weakens its ordering the same way.
+
+- The methods match those of `MyCounter<T>` for the same `T`, so one can replace the other; only the
+default types differ, `i64` here and `i32` for `MyCounter`, which keeps its original count type.
+`get_and_reset` swaps the count for zero in one step, so a metrics scraper that calls it
+periodically reports every increment exactly once.
+
+- The `fetch_*` operations return the previous count and take their ordering as an argument, like
+the methods of the std atomics they forward to.
+*/
+use std::sync::atomic::Ordering;
+use crate::counter_value::{self, CounterValue};
+
+
+pub struct MyAtomicCounter<T: CounterValue = i64> {
+    count: T::Atomic,
+    // the ordering of the methods that take none
+    ordering: Ordering
+}
+
+
+// as for `MyCounter`, `new` creates the default type only; `with_ordering` and `default` create
+// any of them
+impl MyAtomicCounter {
+    pub fn new() -> Self {
+        MyAtomicCounter::default()
+    }
+}
+
+
+impl<T: CounterValue> MyAtomicCounter<T> {
+    pub fn with_ordering(ordering: Ordering) -> Self {
+        MyAtomicCounter {
+            count: T::new_atomic(T::ZERO),
+            ordering
+        }
+    }
+
+
+    pub fn increment(&self) {
+        self.add(T::ONE);
+    }
+
+
+    pub fn add(&self, value: T) {
+        T::fetch_add(&self.count, value, self.ordering);
+    }
+
+
+    pub fn decrement(&self) {
+        T::fetch_sub(&self.count, T::ONE, self.ordering);
+    }
+
+
+    pub fn get(&self) -> T {
+        T::load(&self.count, counter_value::load_ordering(self.ordering))
+    }
+
+
+    pub fn get_and_reset(&self) -> T {
+        T::swap(&self.count, T::ZERO, self.ordering)
+    }
+
+
+    pub fn compare_and_set(&self, current: T, new: T) -> Result<T, T> {
+        let failure = counter_value::load_ordering(self.ordering);
+        T::compare_exchange(&self.count, current, new, self.ordering, failure)
+    }
+
+
+    pub fn fetch_add(&self, value: T, ordering: Ordering) -> T {
+        T::fetch_add(&self.count, value, ordering)
+    }
+
+
+    pub fn fetch_sub(&self, value: T, ordering: Ordering) -> T {
+        T::fetch_sub(&self.count, value, ordering)
+    }
+
+
+    pub fn fetch_max(&self, value: T, ordering: Ordering) -> T {
+        T::fetch_max(&self.count, value, ordering)
+    }
+
+
+    pub fn fetch_min(&self, value: T, ordering: Ordering) -> T {
+        T::fetch_min(&self.count, value, ordering)
+    }
+}
+
+
+impl<T: CounterValue> Default for MyAtomicCounter<T> {
+    fn default() -> Self {
+        MyAtomicCounter::with_ordering(Ordering::SeqCst)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use crate::atomic_counter::MyAtomicCounter;
+
+
+    #[test]
+    fn my_atomic_counter() {
+        // a counter that only counts needs no ordering with other memory
+        let counter: Arc<MyAtomicCounter> =
+            Arc::new(MyAtomicCounter::with_ordering(Ordering::Relaxed));
+
+        let mut handles = vec![];
+        for _ in 0..10 {
+            let counter_ref = counter.clone();
+
+            handles.push(
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        counter_ref.increment();
+                    }
+                    counter_ref.add(10);
+                })
+            );
+        }
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(counter.get(), 1_100);
+    }
+
+
+    #[test]
+    fn my_atomic_counter_fetch() {
+        let counter: MyAtomicCounter = MyAtomicCounter::default();
+
+        assert_eq!(counter.fetch_add(5, Ordering::Relaxed), 0);
+        assert_eq!(counter.fetch_sub(7, Ordering::Relaxed), 5);
+        assert_eq!(counter.fetch_max(3, Ordering::Relaxed), -2);
+        assert_eq!(counter.fetch_min(1, Ordering::Relaxed), 3);
+        assert_eq!(counter.get(), 1);
+
+        // the default `i64` count does not wrap where an `i32` would
+        counter.add(i64::from(i32::MAX));
+        assert_eq!(counter.get(), 1 << 31);
+    }
+
+
+    #[test]
+    fn my_atomic_counter_operations() {
+        // the same sequence as for `MyCounter`, with an ordering that weakens for reads
+        let counter: MyAtomicCounter = MyAtomicCounter::with_ordering(Ordering::AcqRel);
+
+        counter.add(5);
+        counter.decrement();
+        assert_eq!(counter.compare_and_set(3, 10), Err(4));
+        assert_eq!(counter.compare_and_set(4, 10), Ok(4));
+        assert_eq!(counter.get_and_reset(), 10);
+        assert_eq!(counter.get(), 0);
+    }
+
+
+    #[test]
+    fn my_atomic_counter_publishes_with_release() {
+        // with `Release` increments and `Acquire` reads, a thread that sees the count also sees
+        // everything written before the increment
+        let counter: Arc<MyAtomicCounter> =
+            Arc::new(MyAtomicCounter::with_ordering(Ordering::AcqRel));
+        let ready = Arc::new(AtomicBool::new(false));
+
+        let writer = {
+            let (counter, ready) = (counter.clone(), ready.clone());
+            thread::spawn(move || {
+                ready.store(true, Ordering::Relaxed);
+                counter.increment();
+            })
+        };
+
+        while counter.get() == 0 {
+            std::hint::spin_loop();
+        }
+        assert!(ready.load(Ordering::Relaxed));
+        writer.join().unwrap();
+    }
+
+
+    #[test]
+    fn my_atomic_counter_f64() {
+        // seconds spent, summed by several threads at once
+        let gauge = Arc::new(MyAtomicCounter::<f64>::with_ordering(Ordering::Relaxed));
+
+        let handles: Vec<_> = (0..4).map(|_| {
+            let gauge_ref = gauge.clone();
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    gauge_ref.add(0.5);
+                }
+            })
+        }).collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(gauge.get_and_reset(), 200.0);
+        gauge.decrement();
+        assert_eq!(gauge.fetch_max(2.0, Ordering::Relaxed), -1.0);
+        assert_eq!(gauge.compare_and_set(2.0, 0.5), Ok(2.0));
+        assert_eq!(gauge.get(), 0.5);
+
+        let counter = MyAtomicCounter::<usize>::default();
+        counter.increment();
+        assert_eq!(counter.compare_and_set(0, 5), Err(1));
+    }
+}
@@ -0,0 +1,262 @@
+/*
+- `CounterValue` is the numeric type a `MyCounter<T>` or `MyAtomicCounter<T>` counts in. It is
+implemented for `i32`, `i64`, `u64` and `usize`, and for `f64`, so a gauge measured in seconds or
+bytes per second does not need a counter of its own.
+
+- The mutex-based `MyCounter<T>` only needs `ZERO`, `ONE`, the arithmetic and a comparison.
+`MyAtomicCounter<T>` also needs an atomic that holds a `T`, named by the `Atomic` type, and the
+atomic operations, which the trait provides as associated functions.
+
+- The integers forward to the std atomic of the same width. There is no `AtomicF64`, so `f64`
+keeps its bit pattern in an `AtomicU64` (`f64::to_bits` and `f64::from_bits`): loads, stores and
+swaps move the bits unchanged, and the arithmetic operations run a compare-exchange loop that
+retries until no other thread changed the bits in between. The compare-exchange compares bit
+patterns too, so `0.0` and `-0.0` differ and a `NaN` matches the same `NaN`.
+
+- `wrapping_add`, `wrapping_sub` and `same` make `MyCounter<T>` behave like the atomics, so both
+counters give the same answers for the same calls. Integer arithmetic wraps around on overflow,
+as the std atomics do, instead of panicking in debug builds with the lock held; `f64` uses plain
+`+` and `-`. `same` compares integers with `==` and `f64` by its bits, like the compare-exchange.
+*/
+use std::sync::atomic::{AtomicI32, AtomicI64, AtomicU64, AtomicUsize, Ordering};
+
+
+pub trait CounterValue: Copy + PartialEq {
+    type Atomic: Send + Sync;
+
+    const ZERO: Self;
+    const ONE: Self;
+
+    fn wrapping_add(self, other: Self) -> Self;
+    fn wrapping_sub(self, other: Self) -> Self;
+    // whether a compare-exchange holding `self` succeeds when it expects `other`
+    fn same(self, other: Self) -> bool;
+
+    fn new_atomic(value: Self) -> Self::Atomic;
+    fn load(atomic: &Self::Atomic, ordering: Ordering) -> Self;
+    fn swap(atomic: &Self::Atomic, value: Self, ordering: Ordering) -> Self;
+    fn compare_exchange(
+        atomic: &Self::Atomic,
+        current: Self,
+        new: Self,
+        success: Ordering,
+        failure: Ordering
+    ) -> Result<Self, Self>;
+    fn fetch_add(atomic: &Self::Atomic, value: Self, ordering: Ordering) -> Self;
+    fn fetch_sub(atomic: &Self::Atomic, value: Self, ordering: Ordering) -> Self;
+    fn fetch_max(atomic: &Self::Atomic, value: Self, ordering: Ordering) -> Self;
+    fn fetch_min(atomic: &Self::Atomic, value: Self, ordering: Ordering) -> Self;
+}
+
+
+// loads cannot be `Release`, so an ordering that is used to read weakens to the strongest one
+// that is allowed
+pub(crate) fn load_ordering(ordering: Ordering) -> Ordering {
+    match ordering {
+        Ordering::Release => Ordering::Relaxed,
+        Ordering::AcqRel => Ordering::Acquire,
+        ordering => ordering
+    }
+}
+
+
+macro_rules! impl_counter_value {
+    ($($value:ty => $atomic:ty),*) => {
+        $(
+            impl CounterValue for $value {
+                type Atomic = $atomic;
+
+                const ZERO: Self = 0;
+                const ONE: Self = 1;
+
+
+                fn wrapping_add(self, other: Self) -> Self {
+                    <$value>::wrapping_add(self, other)
+                }
+
+
+                fn wrapping_sub(self, other: Self) -> Self {
+                    <$value>::wrapping_sub(self, other)
+                }
+
+
+                fn same(self, other: Self) -> bool {
+                    self == other
+                }
+
+
+                fn new_atomic(value: Self) -> $atomic {
+                    <$atomic>::new(value)
+                }
+
+
+                fn load(atomic: &$atomic, ordering: Ordering) -> Self {
+                    atomic.load(ordering)
+                }
+
+
+                fn swap(atomic: &$atomic, value: Self, ordering: Ordering) -> Self {
+                    atomic.swap(value, ordering)
+                }
+
+
+                fn compare_exchange(
+                    atomic: &$atomic,
+                    current: Self,
+                    new: Self,
+                    success: Ordering,
+                    failure: Ordering
+                ) -> Result<Self, Self> {
+                    atomic.compare_exchange(current, new, success, failure)
+                }
+
+
+                fn fetch_add(atomic: &$atomic, value: Self, ordering: Ordering) -> Self {
+                    atomic.fetch_add(value, ordering)
+                }
+
+
+                fn fetch_sub(atomic: &$atomic, value: Self, ordering: Ordering) -> Self {
+                    atomic.fetch_sub(value, ordering)
+                }
+
+
+                fn fetch_max(atomic: &$atomic, value: Self, ordering: Ordering) -> Self {
+                    atomic.fetch_max(value, ordering)
+                }
+
+
+                fn fetch_min(atomic: &$atomic, value: Self, ordering: Ordering) -> Self {
+                    atomic.fetch_min(value, ordering)
+                }
+            }
+        )*
+    };
+}
+
+
+impl_counter_value!(i32 => AtomicI32, i64 => AtomicI64, u64 => AtomicU64, usize => AtomicUsize);
+
+
+impl CounterValue for f64 {
+    type Atomic = AtomicU64;
+
+    const ZERO: Self = 0.0;
+    const ONE: Self = 1.0;
+
+
+    fn wrapping_add(self, other: Self) -> Self {
+        self + other
+    }
+
+
+    fn wrapping_sub(self, other: Self) -> Self {
+        self - other
+    }
+
+
+    fn same(self, other: Self) -> bool {
+        self.to_bits() == other.to_bits()
+    }
+
+
+    fn new_atomic(value: Self) -> AtomicU64 {
+        AtomicU64::new(value.to_bits())
+    }
+
+
+    fn load(atomic: &AtomicU64, ordering: Ordering) -> Self {
+        f64::from_bits(atomic.load(ordering))
+    }
+
+
+    fn swap(atomic: &AtomicU64, value: Self, ordering: Ordering) -> Self {
+        f64::from_bits(atomic.swap(value.to_bits(), ordering))
+    }
+
+
+    fn compare_exchange(
+        atomic: &AtomicU64,
+        current: Self,
+        new: Self,
+        success: Ordering,
+        failure: Ordering
+    ) -> Result<Self, Self> {
+        atomic.compare_exchange(current.to_bits(), new.to_bits(), success, failure)
+            .map(f64::from_bits)
+            .map_err(f64::from_bits)
+    }
+
+
+    fn fetch_add(atomic: &AtomicU64, value: Self, ordering: Ordering) -> Self {
+        fetch_update_f64(atomic, ordering, |current| current + value)
+    }
+
+
+    fn fetch_sub(atomic: &AtomicU64, value: Self, ordering: Ordering) -> Self {
+        fetch_update_f64(atomic, ordering, |current| current - value)
+    }
+
+
+    fn fetch_max(atomic: &AtomicU64, value: Self, ordering: Ordering) -> Self {
+        fetch_update_f64(atomic, ordering, |current| current.max(value))
+    }
+
+
+    fn fetch_min(atomic: &AtomicU64, value: Self, ordering: Ordering) -> Self {
+        fetch_update_f64(atomic, ordering, |current| current.min(value))
+    }
+}
+
+
+// applies `update` to the `f64` behind `atomic` and returns the previous value; the update is
+// recomputed whenever another thread changed the bits since they were read
+fn fetch_update_f64(atomic: &AtomicU64, ordering: Ordering, update: impl Fn(f64) -> f64) -> f64 {
+    let previous = atomic.fetch_update(ordering, load_ordering(ordering), |bits| {
+        Some(update(f64::from_bits(bits)).to_bits())
+    });
+
+    // the closure never gives up, so the update always succeeds
+    f64::from_bits(previous.unwrap_or_else(|bits| bits))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering::Relaxed;
+    use crate::counter_value::CounterValue;
+
+
+    #[test]
+    fn counter_value_integers() {
+        let atomic = u64::new_atomic(u64::ONE);
+
+        assert_eq!(u64::fetch_add(&atomic, 4, Relaxed), 1);
+        assert_eq!(u64::fetch_max(&atomic, 3, Relaxed), 5);
+        assert_eq!(u64::compare_exchange(&atomic, 5, 2, Relaxed, Relaxed), Ok(5));
+        assert_eq!(u64::swap(&atomic, u64::ZERO, Relaxed), 2);
+
+        // the atomics wrap around on overflow
+        assert_eq!(u64::fetch_sub(&atomic, 1, Relaxed), 0);
+        assert_eq!(u64::load(&atomic, Relaxed), u64::MAX);
+    }
+
+
+    #[test]
+    fn counter_value_f64() {
+        let atomic = f64::new_atomic(f64::ONE);
+
+        assert_eq!(f64::fetch_add(&atomic, 0.5, Relaxed), 1.0);
+        assert_eq!(f64::fetch_sub(&atomic, 2.0, Relaxed), 1.5);
+        assert_eq!(f64::fetch_min(&atomic, -1.0, Relaxed), -0.5);
+        assert_eq!(f64::fetch_max(&atomic, 4.0, Relaxed), -1.0);
+        assert_eq!(f64::load(&atomic, Relaxed), 4.0);
+
+        // compare-exchange goes by the bits, so zero and negative zero differ
+        let zero = f64::new_atomic(f64::ZERO);
+        assert_eq!(f64::compare_exchange(&zero, -0.0, 1.0, Relaxed, Relaxed), Err(0.0));
+        assert_eq!(f64::compare_exchange(&zero, 0.0, 1.0, Relaxed, Relaxed), Ok(0.0));
+        assert_eq!(f64::swap(&zero, f64::NAN, Relaxed), 1.0);
+        assert!(f64::load(&zero, Relaxed).is_nan());
+    }
+}
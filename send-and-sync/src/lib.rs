@@ -1,2 +1,6 @@
-mod send;
-mod sync;
+pub mod send;
+pub mod sync;
+pub mod counter_value;
+pub mod atomic_counter;
+pub mod sharded_counter;
+pub mod local_counter;
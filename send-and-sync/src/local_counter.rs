@@ -0,0 +1,256 @@
+/*
+- `LocalCounter` gives every thread that touches it a cell of its own: an increment only writes
+the calling thread's cell, which no other thread ever writes, so writers never contend, not even
+on a cache line the way two threads on the same shard of a `ShardedCounter` do.
+
+- The cells live in a registry next to the counter, so `get` can find them: it adds up the cells
+of all live threads plus the counts that exited threads left behind. A read therefore locks the
+registry and walks one cell per thread, which makes `get` much more expensive than the increments.
+It suits counters that are bumped on every request and read every few seconds.
+
+- Each thread keeps a thread-local list of the counters it used and its cells for them. The first
+increment on a thread creates the cell and registers it under the registry lock; the later ones
+find it in the list and do a `Relaxed` load and store, with no read-modify-write, since the thread
+is the cell's only writer. A thread rarely uses more than a handful of counters, so the list is
+searched linearly, which beats hashing the counter id.
+
+- When a thread exits, its thread-local list is dropped and flushes every cell into its counter:
+under the registry lock, the cell leaves the registry and its count is added to the counts of
+exited threads, so a concurrent `get` sees the count exactly once. Counters that are already gone
+are skipped; the list only holds weak references to them and drops the stale entries as it goes.
+*/
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+
+
+pub struct LocalCounter {
+    inner: Arc<Registry>
+}
+
+
+struct Registry {
+    // tells the counters apart in the thread-local lists; unlike addresses, ids are never reused
+    id: usize,
+    state: Mutex<State>
+}
+
+
+struct State {
+    cells: Vec<Arc<AtomicI64>>,
+    // the counts of the threads that exited
+    retired: i64
+}
+
+
+// the counters the current thread used, with its cell for each of them
+struct LocalCells {
+    cells: Vec<LocalCell>
+}
+
+
+struct LocalCell {
+    id: usize,
+    registry: Weak<Registry>,
+    cell: Arc<AtomicI64>
+}
+
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+
+thread_local! {
+    static LOCAL_CELLS: RefCell<LocalCells> = const {
+        RefCell::new(LocalCells {cells: Vec::new()})
+    };
+}
+
+
+impl LocalCounter {
+    pub fn new() -> Self {
+        LocalCounter {
+            inner: Arc::new(Registry {
+                id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+                state: Mutex::new(State {cells: Vec::new(), retired: 0})
+            })
+        }
+    }
+
+
+    pub fn increment(&self) {
+        self.add(1);
+    }
+
+
+    pub fn add(&self, value: i64) {
+        LOCAL_CELLS.with(|local| {
+            let mut local = local.borrow_mut();
+            let cell = match local.cells.iter().position(|local| local.id == self.inner.id) {
+                Some(index) => &local.cells[index].cell,
+                None => local.register(&self.inner)
+            };
+
+            // this thread is the only writer of the cell, so a load and a store cannot lose an
+            // update; `get` may read the old or the new count. The add wraps around on overflow, as a
+            // `fetch_add` would
+            cell.store(cell.load(Ordering::Relaxed).wrapping_add(value), Ordering::Relaxed);
+        });
+    }
+
+
+    pub fn get(&self) -> i64 {
+        let state = self.inner.state.lock().unwrap();
+        state.cells.iter()
+            .map(|cell| cell.load(Ordering::Relaxed))
+            .fold(state.retired, i64::wrapping_add)
+    }
+}
+
+
+impl Default for LocalCounter {
+    fn default() -> Self {
+        LocalCounter::new()
+    }
+}
+
+
+impl LocalCells {
+    fn register(&mut self, registry: &Arc<Registry>) -> &Arc<AtomicI64> {
+        // forgets the counters that were dropped since, so the list does not grow with every
+        // counter the thread ever used
+        self.cells.retain(|local| local.registry.strong_count() > 0);
+
+        let cell = Arc::new(AtomicI64::new(0));
+        registry.state.lock().unwrap().cells.push(cell.clone());
+
+        self.cells.push(LocalCell {id: registry.id, registry: Arc::downgrade(registry), cell});
+        &self.cells[self.cells.len() - 1].cell
+    }
+}
+
+
+impl Drop for LocalCells {
+    fn drop(&mut self) {
+        for LocalCell {registry, cell, ..} in &self.cells {
+            let Some(registry) = registry.upgrade() else {
+                continue;
+            };
+
+            // moving the count and removing the cell under one lock keeps `get` from counting it
+            // twice or not at all
+            let mut state = registry.state.lock().unwrap();
+            state.cells.retain(|registered| !Arc::ptr_eq(registered, cell));
+            state.retired = state.retired.wrapping_add(cell.load(Ordering::Relaxed));
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::sync::{Arc, Barrier};
+    use crate::local_counter::LocalCounter;
+
+
+    #[test]
+    fn local_counter() {
+        let counter = Arc::new(LocalCounter::new());
+
+        let mut handles = vec![];
+        for _ in 0..10 {
+            let counter_ref = counter.clone();
+
+            handles.push(
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        counter_ref.increment();
+                    }
+                    counter_ref.add(-10);
+                })
+            );
+        }
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // the exited threads flushed their cells
+        assert_eq!(counter.get(), 900);
+        assert!(counter.inner.state.lock().unwrap().cells.is_empty());
+    }
+
+
+    #[test]
+    fn local_counter_reads_live_threads() {
+        let counter = LocalCounter::new();
+        let (counted, read) = (Barrier::new(3), Barrier::new(3));
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = (0..2).map(|_| scope.spawn(|| {
+                counter.add(5);
+                counted.wait();
+                read.wait();
+            })).collect();
+
+            counted.wait();
+            counter.increment();
+            assert_eq!(counter.get(), 11);
+            assert_eq!(counter.inner.state.lock().unwrap().cells.len(), 3);
+            read.wait();
+
+            // joining waits for the thread-local lists to be dropped, the end of the scope does not
+            for handle in handles {
+                handle.join().unwrap();
+            }
+        });
+
+        // the current thread is still alive and keeps its cell
+        assert_eq!(counter.get(), 11);
+        assert_eq!(counter.inner.state.lock().unwrap().cells.len(), 1);
+    }
+
+
+    #[test]
+    fn local_counter_dropped_before_threads() {
+        let counter = Arc::new(LocalCounter::default());
+        let barrier = Arc::new(Barrier::new(2));
+
+        let handle = {
+            let (counter, barrier) = (counter.clone(), barrier.clone());
+            thread::spawn(move || {
+                counter.increment();
+                drop(counter);
+                barrier.wait();
+                barrier.wait();
+
+                // registers with a new counter while the cell of the old one is still around
+                let other = LocalCounter::new();
+                other.increment();
+                other.get()
+            })
+        };
+
+        barrier.wait();
+        drop(counter);
+        barrier.wait();
+
+        // the thread exits with a cell for a counter that no longer exists
+        assert_eq!(handle.join().unwrap(), 1);
+    }
+
+
+    #[test]
+    fn local_counter_wraps_around() {
+        let counter = LocalCounter::new();
+
+        thread::scope(|scope| {
+            scope.spawn(|| counter.add(i64::MAX)).join().unwrap();
+        });
+        counter.increment();
+        assert_eq!(counter.get(), i64::MIN);
+
+        counter.add(-1);
+        assert_eq!(counter.get(), i64::MAX);
+    }
+}
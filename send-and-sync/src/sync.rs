@@ -15,38 +15,87 @@ to unsynchronized mutation if shared between threads.
 - Both `Send` and `Sync` traits are marker traits, meaning they don’t contain methods but instead
 serve as guarantees to the Rust compiler about thread safety properties.
 */
+use std::mem;
 use std::sync::Mutex;
+use crate::counter_value::CounterValue;
 
 
-struct MyCounter {
+// `T` is the numeric type of the count; see `CounterValue` for the types that can be counted
+pub struct MyCounter<T = i32> {
     // mutex provides mutual exclusion to protect access to the count value
     // only one thread can access the value at a time
-    count: Mutex<i32>
+    count: Mutex<T>
 }
 
 
+// like `HashMap::new`, `new` creates the default type only, so `MyCounter::new()` needs no type
+// annotation; the other types are created with `MyCounter::<T>::default()`
 impl MyCounter {
     pub fn new() -> Self {
-        MyCounter {
-            count: Mutex::new(0)
-        }
+        MyCounter::default()
     }
+}
 
 
+impl<T: CounterValue> MyCounter<T> {
     pub fn increment(&self) {
         // the lock() method will block until the lock is acquired
         let mut count = self.count.lock().unwrap();
 
-        // by dereferencing the MutexGuard (`count`), we access the inner `i32` and increment it
-        *count += 1;
+        // by dereferencing the MutexGuard (`count`), we access the inner `T` and increment it
+        // wraps around like the atomics, instead of panicking on overflow and poisoning the lock
+        *count = count.wrapping_add(T::ONE);
+    }
+
+
+    pub fn add(&self, value: T) {
+        let mut count = self.count.lock().unwrap();
+        *count = count.wrapping_add(value);
+    }
+
+
+    pub fn decrement(&self) {
+        let mut count = self.count.lock().unwrap();
+        *count = count.wrapping_sub(T::ONE);
     }
 
 
-    pub fn get(&self) -> i32 {
+    pub fn get(&self) -> T {
         // ensures that no other thread can mutate `count` while we are reading it
         let count = self.count.lock().unwrap();
         *count
     }
+
+
+    pub fn get_and_reset(&self) -> T {
+        // reading and resetting under one lock means no increment can slip in between and get
+        // lost, so every increment is reported by exactly one call
+        let mut count = self.count.lock().unwrap();
+        mem::replace(&mut *count, T::ZERO)
+    }
+
+
+    pub fn compare_and_set(&self, current: T, new: T) -> Result<T, T> {
+        // like `compare_exchange` on the atomics: `Ok` with the previous count if it was `current`
+        // and is now `new`, `Err` with the actual count otherwise; `f64` compares bits there, and
+        // `same` does too
+        let mut count = self.count.lock().unwrap();
+        if !count.same(current) {
+            return Err(*count);
+        }
+
+        *count = new;
+        Ok(current)
+    }
+}
+
+
+impl<T: CounterValue> Default for MyCounter<T> {
+    fn default() -> Self {
+        MyCounter {
+            count: Mutex::new(T::ZERO)
+        }
+    }
 }
 
 
@@ -83,4 +132,78 @@ mod tests {
 
         assert_eq!(counter.get(), 100);
     }
+
+
+    #[test]
+    fn my_counter_operations() {
+        let counter = MyCounter::default();
+
+        counter.add(5);
+        counter.decrement();
+        assert_eq!(counter.compare_and_set(3, 10), Err(4));
+        assert_eq!(counter.compare_and_set(4, 10), Ok(4));
+        assert_eq!(counter.get_and_reset(), 10);
+        assert_eq!(counter.get(), 0);
+    }
+
+
+    #[test]
+    fn my_counter_get_and_reset() {
+        // every increment is reported by exactly one reset, even while the threads keep counting
+        let counter = Arc::new(MyCounter::new());
+
+        let handles: Vec<_> = (0..4).map(|_| {
+            let counter_ref = counter.clone();
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    counter_ref.increment();
+                }
+            })
+        }).collect();
+
+        let mut reported = 0;
+        while handles.iter().any(|handle| !handle.is_finished()) {
+            reported += counter.get_and_reset();
+        }
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(reported + counter.get_and_reset(), 400);
+    }
+
+
+    #[test]
+    fn my_counter_generic() {
+        let counter = MyCounter::<u64>::default();
+        counter.add(3);
+        counter.decrement();
+        assert_eq!(counter.get_and_reset(), 2);
+
+        // a gauge in seconds
+        let gauge = MyCounter::<f64>::default();
+        gauge.add(1.5);
+        gauge.increment();
+        assert_eq!(gauge.compare_and_set(2.5, 0.25), Ok(2.5));
+        assert_eq!(gauge.get(), 0.25);
+    }
+
+
+    #[test]
+    fn my_counter_matches_atomic_counter() {
+        // wraps around like `MyAtomicCounter<u64>`, and the lock stays usable
+        let counter = MyCounter::<u64>::default();
+        counter.decrement();
+        assert_eq!(counter.get(), u64::MAX);
+        counter.increment();
+        assert_eq!(counter.get(), 0);
+
+        // compares `f64` by its bits like `MyAtomicCounter<f64>`
+        let gauge = MyCounter::<f64>::default();
+        assert!(gauge.compare_and_set(-0.0, 1.0).is_err());
+        assert_eq!(gauge.compare_and_set(0.0, f64::NAN), Ok(0.0));
+        assert!(gauge.compare_and_set(f64::NAN, 2.0).is_ok());
+        assert_eq!(gauge.get(), 2.0);
+    }
 }
\ No newline at end of file